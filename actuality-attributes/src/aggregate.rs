@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::{Error, FnArg, Ident, ImplItem, ImplItemFn, ItemImpl, LitStr, Pat, Path, Receiver, Type};
+
+const COMMAND_HANDLER: &str = "command_handler";
+const EVENT_APPLIER: &str = "event_applier";
+
+/// The properties given to `#[aggregate(...)]`.
+#[derive(Default)]
+pub(crate) struct AggregateArgs {
+    aggregate_type: Option<LitStr>,
+    command: Option<Path>,
+    event: Option<Path>,
+    error: Option<Type>,
+    services: Option<Type>,
+    metadata: Option<Type>,
+}
+
+impl AggregateArgs {
+    pub(crate) fn parse(&mut self, meta: ParseNestedMeta) -> Result<(), Error> {
+        if meta.path.is_ident("type") {
+            self.aggregate_type = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("command") {
+            self.command = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("event") {
+            self.event = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("error") {
+            self.error = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("services") {
+            self.services = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("metadata") {
+            self.metadata = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(
+                "unknown aggregate property, expected `type`, `command`, `event`, `error`, `services` or `metadata`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T, Error> {
+    value.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            format!("#[aggregate] requires the `{}` property", name),
+        )
+    })
+}
+
+pub(crate) fn expand(args: AggregateArgs, mut item: ItemImpl) -> Result<TokenStream, Error> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "#[aggregate] must be placed on an inherent impl",
+        ));
+    }
+    let aggregate_type = required(args.aggregate_type, "type")?;
+    let command = required(args.command, "command")?;
+    let event = required(args.event, "event")?;
+    let error = required(args.error, "error")?;
+    let services = args.services.unwrap_or_else(|| syn::parse_quote!(()));
+    let metadata = args
+        .metadata
+        .unwrap_or_else(|| syn::parse_quote!(::actuality::EventMetadata));
+
+    let mut command_arms = Vec::new();
+    let mut event_arms = Vec::new();
+    let mut commands_handled = HashSet::new();
+    let mut events_applied = HashSet::new();
+    for impl_item in item.items.iter_mut() {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        if let Some(variant) = take_marker(method, COMMAND_HANDLER, &mut commands_handled)? {
+            command_arms.push(dispatch(method, &command, &variant, false)?);
+        }
+        if let Some(variant) = take_marker(method, EVENT_APPLIER, &mut events_applied)? {
+            event_arms.push(dispatch(method, &event, &variant, true)?);
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        #[::async_trait::async_trait]
+        impl #impl_generics ::actuality::Aggregate for #self_ty #where_clause {
+            type Command = #command;
+            type Event = #event;
+            type Error = #error;
+            type Services = #services;
+            type Metadata = #metadata;
+
+            fn aggregate_type() -> ::std::string::String {
+                #aggregate_type.to_string()
+            }
+
+            #[allow(unused_variables)]
+            async fn handle(
+                &self,
+                command: Self::Command,
+                services: &Self::Services,
+            ) -> ::std::result::Result<::std::vec::Vec<Self::Event>, Self::Error> {
+                match command {
+                    #(#command_arms,)*
+                }
+            }
+
+            fn apply(&mut self, event: Self::Event) {
+                match event {
+                    #(#event_arms,)*
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        }
+    })
+}
+
+/// Removes the marker attribute from the method, returning the variant it names.
+fn take_marker(
+    method: &mut ImplItemFn,
+    name: &str,
+    seen: &mut HashSet<Ident>,
+) -> Result<Option<Ident>, Error> {
+    let position = match method
+        .attrs
+        .iter()
+        .position(|attr| attr.path().is_ident(name))
+    {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let attr = method.attrs.remove(position);
+    let variant: Ident = attr.parse_args().map_err(|_| {
+        Error::new_spanned(
+            &attr,
+            format!("expected the variant handled, e.g., #[{}(Variant)]", name),
+        )
+    })?;
+    if !seen.insert(variant.clone()) {
+        return Err(Error::new_spanned(
+            &attr,
+            format!("'{}' is handled by more than one method", variant),
+        ));
+    }
+    if method.attrs.iter().any(|attr| attr.path().is_ident(name)) {
+        return Err(Error::new_spanned(
+            &method.sig.ident,
+            format!("a method may only be marked #[{}] once", name),
+        ));
+    }
+    Ok(Some(variant))
+}
+
+/// Builds the match arm calling the method with the variant's fields, and the services for a
+/// command handler.
+fn dispatch(
+    method: &ImplItemFn,
+    enum_path: &Path,
+    variant: &Ident,
+    applier: bool,
+) -> Result<TokenStream, Error> {
+    let sig = &method.sig;
+    let receiver = match sig.inputs.first() {
+        Some(FnArg::Receiver(receiver)) => Some(receiver),
+        _ => None,
+    };
+    let expected_receiver = if applier { "&mut self" } else { "&self" };
+    if !receiver.is_some_and(|receiver| is_reference(receiver, applier)) {
+        return Err(Error::new_spanned(
+            &sig.ident,
+            format!("expected a method taking `{}`", expected_receiver),
+        ));
+    }
+    if applier && sig.asyncness.is_some() {
+        return Err(Error::new_spanned(
+            sig.asyncness,
+            "an event applier cannot be async",
+        ));
+    }
+
+    let mut fields = Vec::new();
+    let mut args = Vec::new();
+    for input in sig.inputs.iter().skip(1) {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(receiver, "unexpected receiver"))
+            }
+        };
+        if !applier && matches!(*input.ty, Type::Reference(_)) {
+            args.push(quote! { services });
+            continue;
+        }
+        let field = match &*input.pat {
+            Pat::Ident(pat) => &pat.ident,
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "expected a parameter named for a field of the variant",
+                ))
+            }
+        };
+        let binding = format_ident!("__{}", field);
+        fields.push(quote! { #field: #binding });
+        args.push(quote! { #binding });
+    }
+
+    let name = &sig.ident;
+    let call = match sig.asyncness {
+        Some(_) => quote! { self.#name(#(#args),*).await },
+        None => quote! { self.#name(#(#args),*) },
+    };
+    Ok(quote! { #enum_path::#variant { #(#fields,)* .. } => #call })
+}
+
+fn is_reference(receiver: &Receiver, mutable: bool) -> bool {
+    receiver.reference.is_some() && receiver.mutability.is_some() == mutable
+}
@@ -1,8 +1,110 @@
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
-    }
+//! Attribute macros for the `actuality` crate.
+//!
+//! - `aggregate` - implements `actuality::Aggregate` from the methods of an inherent impl
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, Error, ItemImpl};
+
+use aggregate::AggregateArgs;
+
+mod aggregate;
+
+/// Implements `actuality::Aggregate` for the type of an inherent impl, dispatching each command
+/// to the method marked `#[command_handler(Variant)]` and each event to the method marked
+/// `#[event_applier(Variant)]`.
+///
+/// The aggregate is configured with:
+/// - `type` - the aggregate type, required
+/// - `command`, `event` and `error` - the associated types, required
+/// - `services` - the associated services, `()` if not given
+/// - `metadata` - the metadata committed with the events, `actuality::EventMetadata` if not given
+///
+/// The fields of a command or event variant are passed to the method's parameters of the same
+/// name, any fields not needed may be left out. A command handler may take the services as a
+/// reference parameter, and may be `async`. Every command must have a handler, events without
+/// an applier leave the aggregate unchanged.
+///
+/// As the `Aggregate` trait is implemented with `async_trait`, the `async-trait` crate must be a
+/// dependency.
+///
+/// ```
+/// use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent, CustomerService};
+/// use actuality_attributes::aggregate;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Default, Serialize, Deserialize)]
+/// struct Customer {
+///     name: Option<String>,
+///     email: Option<String>,
+/// }
+///
+/// #[aggregate(
+///     type = "customer",
+///     command = CustomerCommand,
+///     event = CustomerEvent,
+///     error = CustomerError,
+///     services = CustomerService
+/// )]
+/// impl Customer {
+///     #[command_handler(AddCustomerName)]
+///     fn add_name(&self, name: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+///         if self.name.is_some() {
+///             return Err("a name has already been added".into());
+///         }
+///         Ok(vec![CustomerEvent::NameAdded { name }])
+///     }
+///
+///     #[command_handler(UpdateEmail)]
+///     async fn update_email(
+///         &self,
+///         new_email: String,
+///         _services: &CustomerService,
+///     ) -> Result<Vec<CustomerEvent>, CustomerError> {
+///         Ok(vec![CustomerEvent::EmailUpdated { new_email }])
+///     }
+///
+///     #[event_applier(NameAdded)]
+///     fn name_added(&mut self, name: String) {
+///         self.name = Some(name);
+///     }
+///
+///     #[event_applier(EmailUpdated)]
+///     fn email_updated(&mut self, new_email: String) {
+///         self.email = Some(new_email);
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn aggregate(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut aggregate_args = AggregateArgs::default();
+    let parser = syn::meta::parser(|meta| aggregate_args.parse(meta));
+    parse_macro_input!(args with parser);
+    let item = parse_macro_input!(item as ItemImpl);
+    aggregate::expand(aggregate_args, item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Marks the method handling a command variant, within an `#[aggregate]` impl.
+#[proc_macro_attribute]
+pub fn command_handler(_args: TokenStream, item: TokenStream) -> TokenStream {
+    misplaced("command_handler", item)
+}
+
+/// Marks the method applying an event variant, within an `#[aggregate]` impl.
+#[proc_macro_attribute]
+pub fn event_applier(_args: TokenStream, item: TokenStream) -> TokenStream {
+    misplaced("event_applier", item)
+}
+
+fn misplaced(name: &str, item: TokenStream) -> TokenStream {
+    let item = proc_macro2::TokenStream::from(item);
+    let error = Error::new_spanned(
+        &item,
+        format!(
+            "#[{}] must be placed on a method of an #[aggregate] impl",
+            name
+        ),
+    )
+    .into_compile_error();
+    quote::quote!(#error #item).into()
 }
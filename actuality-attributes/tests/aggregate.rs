@@ -0,0 +1,130 @@
+use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent, CustomerService};
+use actuality::test::TestFramework;
+use actuality::{Aggregate, EventMetadata};
+use actuality_attributes::aggregate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Customer {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+#[aggregate(
+    type = "customer",
+    command = CustomerCommand,
+    event = CustomerEvent,
+    error = CustomerError,
+    services = CustomerService
+)]
+impl Customer {
+    #[command_handler(AddCustomerName)]
+    fn add_name(&self, name: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+        if self.name.is_some() {
+            return Err("a name has already been added".into());
+        }
+        Ok(vec![CustomerEvent::NameAdded { name }])
+    }
+
+    #[command_handler(UpdateEmail)]
+    async fn update_email(
+        &self,
+        _services: &CustomerService,
+        new_email: String,
+    ) -> Result<Vec<CustomerEvent>, CustomerError> {
+        Ok(vec![CustomerEvent::EmailUpdated { new_email }])
+    }
+
+    #[event_applier(NameAdded)]
+    fn name_added(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    fn has_email(&self) -> bool {
+        self.email.is_some()
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct Audit {
+    user: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AuditedCustomer;
+
+#[aggregate(
+    type = "audited_customer",
+    command = CustomerCommand,
+    event = CustomerEvent,
+    error = CustomerError,
+    metadata = Audit
+)]
+impl AuditedCustomer {
+    #[command_handler(AddCustomerName)]
+    fn add_name(&self, name: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+        Ok(vec![CustomerEvent::NameAdded { name }])
+    }
+
+    #[command_handler(UpdateEmail)]
+    fn update_email(&self, new_email: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+        Ok(vec![CustomerEvent::EmailUpdated { new_email }])
+    }
+}
+
+#[test]
+fn aggregate_type() {
+    assert_eq!("customer", Customer::aggregate_type());
+}
+
+#[test]
+fn handle_commands() {
+    TestFramework::<Customer>::with(CustomerService)
+        .given_no_previous_events()
+        .when(CustomerCommand::AddCustomerName {
+            name: "John Doe".to_string(),
+        })
+        .then_expect_events(vec![CustomerEvent::NameAdded {
+            name: "John Doe".to_string(),
+        }]);
+
+    TestFramework::<Customer>::with(CustomerService)
+        .given(vec![CustomerEvent::NameAdded {
+            name: "John Doe".to_string(),
+        }])
+        .when(CustomerCommand::AddCustomerName {
+            name: "John Doe".to_string(),
+        })
+        .then_expect_error_message("a name has already been added");
+
+    TestFramework::<Customer>::with(CustomerService)
+        .given_no_previous_events()
+        .when(CustomerCommand::UpdateEmail {
+            new_email: "john@example.com".to_string(),
+        })
+        .then_expect_events(vec![CustomerEvent::EmailUpdated {
+            new_email: "john@example.com".to_string(),
+        }]);
+}
+
+#[test]
+fn apply_events() {
+    let mut customer = Customer::default();
+    customer.apply(CustomerEvent::NameAdded {
+        name: "John Doe".to_string(),
+    });
+    customer.apply(CustomerEvent::EmailUpdated {
+        new_email: "john@example.com".to_string(),
+    });
+    assert_eq!(Some("John Doe".to_string()), customer.name);
+    assert!(!customer.has_email());
+}
+
+#[test]
+fn metadata() {
+    let _default: <Customer as Aggregate>::Metadata = EventMetadata::new();
+    let audit: <AuditedCustomer as Aggregate>::Metadata = Audit {
+        user: "ops".to_string(),
+    };
+    assert_eq!("ops", audit.user);
+}
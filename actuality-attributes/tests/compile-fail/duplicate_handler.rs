@@ -0,0 +1,28 @@
+use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent};
+use actuality_attributes::aggregate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Customer {
+    name: Option<String>,
+}
+
+#[aggregate(
+    type = "customer",
+    command = CustomerCommand,
+    event = CustomerEvent,
+    error = CustomerError
+)]
+impl Customer {
+    #[command_handler(AddCustomerName)]
+    fn add_name(&self, name: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+        Ok(vec![CustomerEvent::NameAdded { name }])
+    }
+
+    #[command_handler(AddCustomerName)]
+    fn replace_name(&self, name: String) -> Result<Vec<CustomerEvent>, CustomerError> {
+        Ok(vec![CustomerEvent::NameAdded { name }])
+    }
+}
+
+fn main() {}
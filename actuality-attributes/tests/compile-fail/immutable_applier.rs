@@ -0,0 +1,23 @@
+use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent};
+use actuality_attributes::aggregate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Customer {
+    name: Option<String>,
+}
+
+#[aggregate(
+    type = "customer",
+    command = CustomerCommand,
+    event = CustomerEvent,
+    error = CustomerError
+)]
+impl Customer {
+    #[event_applier(NameAdded)]
+    fn name_added(&self, name: String) {
+        println!("{}", name);
+    }
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use actuality_attributes::command_handler;
+
+struct Customer;
+
+impl Customer {
+    #[command_handler(AddCustomerName)]
+    fn add_name(&self) {}
+}
+
+fn main() {}
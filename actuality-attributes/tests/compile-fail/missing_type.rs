@@ -0,0 +1,13 @@
+use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent};
+use actuality_attributes::aggregate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Customer {
+    name: Option<String>,
+}
+
+#[aggregate(command = CustomerCommand, event = CustomerEvent, error = CustomerError)]
+impl Customer {}
+
+fn main() {}
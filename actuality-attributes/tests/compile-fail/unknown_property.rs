@@ -0,0 +1,19 @@
+use actuality::doc::setup::{CustomerCommand, CustomerError, CustomerEvent};
+use actuality_attributes::aggregate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+struct Customer {
+    name: Option<String>,
+}
+
+#[aggregate(
+    type = "customer",
+    command = CustomerCommand,
+    event = CustomerEvent,
+    error = CustomerError,
+    service = ()
+)]
+impl Customer {}
+
+fn main() {}
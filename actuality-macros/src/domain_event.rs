@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Error, Fields, LitStr};
+
+const EVENT_TYPE: &str = "event_type";
+const EVENT_VERSION: &str = "event_version";
+
+pub(crate) fn expand(input: DeriveInput) -> Result<TokenStream, Error> {
+    let ident = &input.ident;
+    let event_version = find_attribute(&input.attrs, EVENT_VERSION)?;
+    let (event_type_body, event_version_body) = match &input.data {
+        Data::Struct(_) => {
+            let event_version = required_version(event_version, &input)?;
+            let event_type = find_attribute(&input.attrs, EVENT_TYPE)?
+                .map(|event_type| event_type.value())
+                .unwrap_or_else(|| ident.to_string());
+            (
+                quote! { #event_type.to_string() },
+                quote! { #event_version.to_string() },
+            )
+        }
+        Data::Enum(data) => {
+            if let Some(attr) = input
+                .attrs
+                .iter()
+                .find(|attr| attr.path().is_ident(EVENT_TYPE))
+            {
+                return Err(Error::new_spanned(
+                    attr,
+                    "#[event_type] renames a single event, place it on the variant",
+                ));
+            }
+            let mut seen: HashMap<String, &syn::Ident> = HashMap::new();
+            let mut event_types = Vec::new();
+            let mut event_versions = Vec::new();
+            for variant in &data.variants {
+                let variant_ident = &variant.ident;
+                let pattern = match variant.fields {
+                    Fields::Named(_) => quote! { Self::#variant_ident { .. } },
+                    Fields::Unnamed(_) => quote! { Self::#variant_ident(..) },
+                    Fields::Unit => quote! { Self::#variant_ident },
+                };
+                let event_type = find_attribute(&variant.attrs, EVENT_TYPE)?
+                    .map(|event_type| event_type.value())
+                    .unwrap_or_else(|| variant_ident.to_string());
+                if let Some(other) = seen.insert(event_type.clone(), variant_ident) {
+                    return Err(Error::new(
+                        variant_ident.span(),
+                        format!(
+                            "event type '{}' is already used by variant '{}'",
+                            event_type, other
+                        ),
+                    ));
+                }
+                let event_version = match find_attribute(&variant.attrs, EVENT_VERSION)? {
+                    Some(event_version) => valid_version(event_version)?,
+                    None => required_version(event_version.clone(), &input)?,
+                };
+                event_types.push(quote! { #pattern => #event_type.to_string() });
+                event_versions.push(quote! { #pattern => #event_version.to_string() });
+            }
+            (
+                quote! { match *self { #(#event_types,)* } },
+                quote! { match *self { #(#event_versions,)* } },
+            )
+        }
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span,
+                "DomainEvent cannot be derived for a union",
+            ))
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::actuality::DomainEvent for #ident #ty_generics #where_clause {
+            fn event_type(&self) -> ::std::string::String {
+                #event_type_body
+            }
+
+            fn event_version(&self) -> ::std::string::String {
+                #event_version_body
+            }
+        }
+    })
+}
+
+/// Finds the single string argument of the named attribute, e.g., `#[event_version("1.0")]`.
+fn find_attribute(attrs: &[Attribute], name: &str) -> Result<Option<LitStr>, Error> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident(name)) {
+        if found.is_some() {
+            return Err(Error::new_spanned(
+                attr,
+                format!("#[{}] may only be given once", name),
+            ));
+        }
+        let value: LitStr = attr.parse_args().map_err(|_| {
+            Error::new_spanned(
+                attr,
+                format!("expected a string, e.g., #[{}(\"...\")]", name),
+            )
+        })?;
+        found = Some(value);
+    }
+    Ok(found)
+}
+
+fn required_version(event_version: Option<LitStr>, input: &DeriveInput) -> Result<String, Error> {
+    match event_version {
+        Some(event_version) => valid_version(event_version),
+        None => Err(Error::new(
+            input.ident.span(),
+            "#[derive(DomainEvent)] requires an #[event_version(\"...\")] attribute",
+        )),
+    }
+}
+
+/// Checks the version is a semantic version as read by `actuality::persist::SemanticVersion`.
+fn valid_version(event_version: LitStr) -> Result<String, Error> {
+    let value = event_version.value();
+    let parts: Vec<&str> = value.split('.').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.parse::<u32>().is_err()) {
+        return Err(Error::new(
+            event_version.span(),
+            format!(
+                "'{}' is not a semantic version, e.g., \"1.2\" or \"1.2.3\"",
+                value
+            ),
+        ));
+    }
+    Ok(value)
+}
@@ -1,8 +1,137 @@
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        let result = 2 + 2;
-        assert_eq!(result, 4);
-    }
+//! Macros for the `actuality` crate.
+//!
+//! - `DomainEvent` - implements `actuality::DomainEvent` for an event enum or struct
+//! - `view` - implements `actuality::View` from the event handlers of an inherent impl
+//! - `ViewQuery` - wires a view into an `actuality::persist::GenericQuery`
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, Error, ItemImpl};
+
+use view::ViewArgs;
+
+mod domain_event;
+mod view;
+mod view_query;
+
+/// Implements `actuality::DomainEvent`, taking the event type from the name of each variant
+/// and the event version from the `#[event_version]` attribute.
+///
+/// The event type of a variant may be renamed with `#[event_type("...")]`, and the version of
+/// a single variant overridden with its own `#[event_version("...")]`. Versions must be
+/// semantic versions, e.g., `"1.2"` or `"1.2.3"`, so that events may be upcast.
+///
+/// ```
+/// use actuality_macros::DomainEvent;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+/// #[event_version("1.2")]
+/// pub enum BankAccountEvent {
+///     AccountOpened { account_id: String },
+///     #[event_type("CustomerDepositedMoney")]
+///     MoneyDeposited { amount: f64 },
+///     #[event_version("2.0")]
+///     MoneyWithdrawn { amount: f64 },
+/// }
+///
+/// # use actuality::DomainEvent;
+/// let event = BankAccountEvent::MoneyDeposited { amount: 10.0 };
+/// assert_eq!("CustomerDepositedMoney", event.event_type());
+/// assert_eq!("1.2", event.event_version());
+/// ```
+#[proc_macro_derive(DomainEvent, attributes(event_type, event_version))]
+pub fn derive_domain_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    domain_event::expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implements `actuality::View` for the type of an inherent impl, updating the view with the
+/// method marked `#[event_handler(Variant)]` for each event.
+///
+/// The view is configured with:
+/// - `aggregate` - the aggregate whose events update the view, required
+/// - `event` - the aggregate's event enum, required
+///
+/// References to the fields of an event variant are passed to the handler's parameters of the
+/// same name, any fields not needed may be left out. A parameter taking an `&EventEnvelope`
+/// receives the whole event, e.g., for its metadata. Events without a handler leave the view
+/// unchanged.
+///
+/// ```
+/// use actuality::doc::setup::{Customer, CustomerEvent};
+/// use actuality::EventEnvelope;
+/// use actuality_macros::view;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Default, Serialize, Deserialize)]
+/// struct CustomerView {
+///     customer_id: String,
+///     name: String,
+/// }
+///
+/// #[view(aggregate = Customer, event = CustomerEvent)]
+/// impl CustomerView {
+///     #[event_handler(NameAdded)]
+///     fn name_added(&mut self, name: &str, event: &EventEnvelope<Customer>) {
+///         self.customer_id = event.aggregate_id.clone();
+///         self.name = name.to_string();
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn view(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut view_args = ViewArgs::default();
+    let parser = syn::meta::parser(|meta| view_args.parse(meta));
+    parse_macro_input!(args with parser);
+    let item = parse_macro_input!(item as ItemImpl);
+    view::expand(view_args, item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Marks the method handling an event variant, within a `#[view]` impl.
+#[proc_macro_attribute]
+pub fn event_handler(_args: TokenStream, item: TokenStream) -> TokenStream {
+    let item = proc_macro2::TokenStream::from(item);
+    let error = Error::new_spanned(
+        &item,
+        "#[event_handler] must be placed on a method of a #[view] impl",
+    )
+    .into_compile_error();
+    quote::quote!(#error #item).into()
+}
+
+/// Wires a view into a `GenericQuery`, adding a `{View}Query<R>` type alias for the query
+/// over a view repository `R` and a `query` function creating it.
+///
+/// ```
+/// # use actuality::doc::setup::{Customer, CustomerEvent};
+/// # use actuality::View;
+/// use actuality_macros::ViewQuery;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Default, Serialize, Deserialize, ViewQuery)]
+/// #[view_query(aggregate = Customer)]
+/// pub struct CustomerView {
+///     name: String,
+/// }
+/// # impl View<Customer> for CustomerView {
+/// #     fn update(&mut self, _event: &actuality::EventEnvelope<Customer>) {}
+/// # }
+///
+/// // e.g., with a `SqliteViewRepository`
+/// fn configure<R>(view_repository: std::sync::Arc<R>) -> CustomerViewQuery<R>
+/// where
+///     R: actuality::persist::ViewRepository<CustomerView, Customer>,
+/// {
+///     CustomerView::query(view_repository)
+/// }
+/// ```
+#[proc_macro_derive(ViewQuery, attributes(view_query))]
+pub fn derive_view_query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    view_query::expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
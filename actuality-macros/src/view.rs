@@ -0,0 +1,165 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::{Error, FnArg, Ident, ImplItem, ImplItemFn, ItemImpl, Pat, Path, Type};
+
+const EVENT_HANDLER: &str = "event_handler";
+
+/// The properties given to `#[view(...)]`.
+#[derive(Default)]
+pub(crate) struct ViewArgs {
+    aggregate: Option<Type>,
+    event: Option<Path>,
+}
+
+impl ViewArgs {
+    pub(crate) fn parse(&mut self, meta: ParseNestedMeta) -> Result<(), Error> {
+        if meta.path.is_ident("aggregate") {
+            self.aggregate = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("event") {
+            self.event = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("unknown view property, expected `aggregate` or `event`"));
+        }
+        Ok(())
+    }
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T, Error> {
+    value.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            format!("#[view] requires the `{}` property", name),
+        )
+    })
+}
+
+pub(crate) fn expand(args: ViewArgs, mut item: ItemImpl) -> Result<TokenStream, Error> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "#[view] must be placed on an inherent impl",
+        ));
+    }
+    let aggregate = required(args.aggregate, "aggregate")?;
+    let event = required(args.event, "event")?;
+
+    let mut arms = Vec::new();
+    let mut handled = HashSet::new();
+    for impl_item in item.items.iter_mut() {
+        let method = match impl_item {
+            ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+        if let Some(variant) = take_marker(method, &mut handled)? {
+            arms.push(dispatch(method, &event, &variant)?);
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::actuality::View<#aggregate> for #self_ty #where_clause {
+            fn update(&mut self, envelope: &::actuality::EventEnvelope<#aggregate>) {
+                match &envelope.payload {
+                    #(#arms,)*
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        }
+    })
+}
+
+/// Removes the `#[event_handler]` attribute from the method, returning the variant it names.
+fn take_marker(method: &mut ImplItemFn, seen: &mut HashSet<Ident>) -> Result<Option<Ident>, Error> {
+    let position = match method
+        .attrs
+        .iter()
+        .position(|attr| attr.path().is_ident(EVENT_HANDLER))
+    {
+        Some(position) => position,
+        None => return Ok(None),
+    };
+    let attr = method.attrs.remove(position);
+    let variant: Ident = attr.parse_args().map_err(|_| {
+        Error::new_spanned(
+            &attr,
+            "expected the event variant handled, e.g., #[event_handler(Variant)]",
+        )
+    })?;
+    if !seen.insert(variant.clone()) {
+        return Err(Error::new_spanned(
+            &attr,
+            format!("'{}' is handled by more than one method", variant),
+        ));
+    }
+    Ok(Some(variant))
+}
+
+/// Builds the match arm calling the method with references to the variant's fields, and to the
+/// event envelope where a parameter takes an `&EventEnvelope`.
+fn dispatch(method: &ImplItemFn, event: &Path, variant: &Ident) -> Result<TokenStream, Error> {
+    let sig = &method.sig;
+    let mutable_receiver = match sig.inputs.first() {
+        Some(FnArg::Receiver(receiver)) => {
+            receiver.reference.is_some() && receiver.mutability.is_some()
+        }
+        _ => false,
+    };
+    if !mutable_receiver || sig.asyncness.is_some() {
+        return Err(Error::new_spanned(
+            &sig.ident,
+            "expected a method taking `&mut self`, an event handler cannot be async",
+        ));
+    }
+
+    let mut fields = Vec::new();
+    let mut args = Vec::new();
+    for input in sig.inputs.iter().skip(1) {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(receiver) => {
+                return Err(Error::new_spanned(receiver, "unexpected receiver"))
+            }
+        };
+        if is_envelope(&input.ty) {
+            args.push(quote! { envelope });
+            continue;
+        }
+        let field = match &*input.pat {
+            Pat::Ident(pat) => &pat.ident,
+            pat => {
+                return Err(Error::new_spanned(
+                    pat,
+                    "expected a parameter named for a field of the event",
+                ))
+            }
+        };
+        let binding = format_ident!("__{}", field);
+        fields.push(quote! { #field: #binding });
+        args.push(quote! { #binding });
+    }
+
+    let name = &sig.ident;
+    Ok(quote! { #event::#variant { #(#fields,)* .. } => self.#name(#(#args),*) })
+}
+
+fn is_envelope(ty: &Type) -> bool {
+    let reference = match ty {
+        Type::Reference(reference) => reference,
+        _ => return false,
+    };
+    match &*reference.elem {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "EventEnvelope"),
+        _ => false,
+    }
+}
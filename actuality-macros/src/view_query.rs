@@ -0,0 +1,65 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{DeriveInput, Error, Type};
+
+const VIEW_QUERY: &str = "view_query";
+
+pub(crate) fn expand(input: DeriveInput) -> Result<TokenStream, Error> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "ViewQuery cannot be derived for a generic view",
+        ));
+    }
+    let aggregate = find_aggregate(&input)?;
+    let vis = &input.vis;
+    let alias = format_ident!("{}Query", ident);
+    let alias_doc = format!(
+        "A `GenericQuery` maintaining `{}` views in the repository `R`.",
+        ident
+    );
+
+    Ok(quote! {
+        #[doc = #alias_doc]
+        #vis type #alias<R> = ::actuality::persist::GenericQuery<R, #ident, #aggregate>;
+
+        impl #ident {
+            /// Creates a `GenericQuery` maintaining this view in the provided repository.
+            #vis fn query<R>(view_repository: ::std::sync::Arc<R>) -> #alias<R>
+            where
+                R: ::actuality::persist::ViewRepository<#ident, #aggregate>,
+            {
+                ::actuality::persist::GenericQuery::new(view_repository)
+            }
+        }
+    })
+}
+
+/// Reads the aggregate from `#[view_query(aggregate = ...)]`.
+fn find_aggregate(input: &DeriveInput) -> Result<Type, Error> {
+    let mut aggregate: Option<Type> = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident(VIEW_QUERY))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("aggregate") {
+                if aggregate.is_some() {
+                    return Err(meta.error("the aggregate may only be given once"));
+                }
+                aggregate = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown view query property, expected `aggregate`"))
+            }
+        })?;
+    }
+    aggregate.ok_or_else(|| {
+        Error::new(
+            input.ident.span(),
+            "#[derive(ViewQuery)] requires a #[view_query(aggregate = ...)] attribute",
+        )
+    })
+}
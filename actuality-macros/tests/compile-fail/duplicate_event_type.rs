@@ -0,0 +1,12 @@
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_version("1.0")]
+enum AccountEvent {
+    Opened,
+    #[event_type("Opened")]
+    Reopened,
+}
+
+fn main() {}
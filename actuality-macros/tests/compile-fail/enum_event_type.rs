@@ -0,0 +1,11 @@
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_type("Account")]
+#[event_version("1.0")]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
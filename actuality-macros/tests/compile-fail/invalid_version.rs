@@ -0,0 +1,10 @@
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_version("1.x")]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
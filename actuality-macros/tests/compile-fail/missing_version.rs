@@ -0,0 +1,9 @@
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
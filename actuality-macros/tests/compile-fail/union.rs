@@ -0,0 +1,9 @@
+use actuality_macros::DomainEvent;
+
+#[derive(DomainEvent)]
+#[event_version("1.0")]
+union AccountEvent {
+    opened: u32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_version(1.0)]
+enum AccountEvent {
+    Opened,
+}
+
+fn main() {}
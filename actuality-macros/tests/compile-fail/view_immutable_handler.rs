@@ -0,0 +1,18 @@
+use actuality::doc::setup::{Customer, CustomerEvent};
+use actuality_macros::view;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CustomerView {
+    name: String,
+}
+
+#[view(aggregate = Customer, event = CustomerEvent)]
+impl CustomerView {
+    #[event_handler(NameAdded)]
+    fn name_added(&self, name: &String) {
+        println!("{}", name);
+    }
+}
+
+fn main() {}
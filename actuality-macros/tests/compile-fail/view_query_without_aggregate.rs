@@ -0,0 +1,8 @@
+use actuality_macros::ViewQuery;
+
+#[derive(ViewQuery)]
+struct CustomerView {
+    name: String,
+}
+
+fn main() {}
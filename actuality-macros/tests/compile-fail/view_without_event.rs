@@ -0,0 +1,13 @@
+use actuality::doc::setup::Customer;
+use actuality_macros::view;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CustomerView {
+    name: String,
+}
+
+#[view(aggregate = Customer)]
+impl CustomerView {}
+
+fn main() {}
@@ -0,0 +1,55 @@
+use actuality::DomainEvent;
+use actuality_macros::DomainEvent;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_version("1.0")]
+enum CustomerEvent {
+    NameAdded {
+        name: String,
+    },
+    #[event_type("EmailChanged")]
+    EmailUpdated(String),
+    #[event_version("2.1.3")]
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_version("0.3")]
+struct PriceChanged {
+    price: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, DomainEvent)]
+#[event_type("ItemShipped")]
+#[event_version("1")]
+struct Shipped {
+    item: u32,
+}
+
+#[test]
+fn enum_events() {
+    let added = CustomerEvent::NameAdded {
+        name: "John".to_string(),
+    };
+    assert_eq!("NameAdded", added.event_type());
+    assert_eq!("1.0", added.event_version());
+
+    let updated = CustomerEvent::EmailUpdated("john@example.com".to_string());
+    assert_eq!("EmailChanged", updated.event_type());
+    assert_eq!("1.0", updated.event_version());
+
+    assert_eq!("Closed", CustomerEvent::Closed.event_type());
+    assert_eq!("2.1.3", CustomerEvent::Closed.event_version());
+}
+
+#[test]
+fn struct_events() {
+    let changed = PriceChanged { price: 10 };
+    assert_eq!("PriceChanged", changed.event_type());
+    assert_eq!("0.3", changed.event_version());
+
+    let shipped = Shipped { item: 7 };
+    assert_eq!("ItemShipped", shipped.event_type());
+    assert_eq!("1", shipped.event_version());
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use actuality::doc::setup::{Customer, CustomerEvent};
+use actuality::persist::{PersistenceError, ViewContext, ViewRepository};
+use actuality::{EventEnvelope, Query, View};
+use actuality_macros::{view, ViewQuery};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, ViewQuery)]
+#[view_query(aggregate = Customer)]
+pub struct CustomerView {
+    customer_id: String,
+    name: String,
+    email: Option<String>,
+    updates: usize,
+}
+
+#[view(aggregate = Customer, event = CustomerEvent)]
+impl CustomerView {
+    #[event_handler(NameAdded)]
+    fn name_added(&mut self, event: &EventEnvelope<Customer>, name: &str) {
+        self.customer_id = event.aggregate_id.clone();
+        self.name = name.to_string();
+        self.updates += 1;
+    }
+
+    #[event_handler(EmailUpdated)]
+    fn email_updated(&mut self, new_email: &str) {
+        self.email = Some(new_email.to_string());
+        self.updates += 1;
+    }
+}
+
+fn envelope(sequence: usize, payload: CustomerEvent) -> EventEnvelope<Customer> {
+    EventEnvelope {
+        aggregate_id: "customer-A".to_string(),
+        event_type: "CustomerEvent".to_string(),
+        sequence,
+        system_id: "test".to_string(),
+        payload,
+        metadata: Default::default(),
+    }
+}
+
+#[test]
+fn update_view() {
+    let mut view = CustomerView::default();
+    view.update(&envelope(
+        1,
+        CustomerEvent::NameAdded {
+            name: "John Doe".to_string(),
+        },
+    ));
+    view.update(&envelope(
+        2,
+        CustomerEvent::EmailUpdated {
+            new_email: "john@example.com".to_string(),
+        },
+    ));
+    assert_eq!(
+        CustomerView {
+            customer_id: "customer-A".to_string(),
+            name: "John Doe".to_string(),
+            email: Some("john@example.com".to_string()),
+            updates: 2,
+        },
+        view
+    );
+}
+
+#[derive(Default)]
+struct MemoryViewRepository(Mutex<HashMap<String, (CustomerView, i64)>>);
+
+#[async_trait]
+impl ViewRepository<CustomerView, Customer> for MemoryViewRepository {
+    async fn load(&self, view_id: &str) -> Result<Option<CustomerView>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(CustomerView, ViewContext)>, PersistenceError> {
+        let views = self.0.lock().unwrap();
+        Ok(views.get(view_id).map(|(view, version)| {
+            (
+                view.clone(),
+                ViewContext::new(view_id.to_string(), *version),
+            )
+        }))
+    }
+
+    async fn update_view(
+        &self,
+        view: CustomerView,
+        context: ViewContext,
+    ) -> Result<(), PersistenceError> {
+        let mut views = self.0.lock().unwrap();
+        views.insert(context.view_instance_id, (view, context.version + 1));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn view_query() {
+    let query: CustomerViewQuery<MemoryViewRepository> =
+        CustomerView::query(Arc::new(MemoryViewRepository::default()));
+    query
+        .dispatch(
+            "customer-A",
+            &[envelope(
+                1,
+                CustomerEvent::NameAdded {
+                    name: "John Doe".to_string(),
+                },
+            )],
+        )
+        .await;
+    let view = query.load("customer-A").await.unwrap();
+    assert_eq!("John Doe", view.name);
+}
@@ -0,0 +1,12 @@
+CREATE TABLE events
+(
+    position       BIGSERIAL PRIMARY KEY,
+    aggregate_type TEXT   NOT NULL,
+    aggregate_id   TEXT   NOT NULL,
+    sequence       BIGINT NOT NULL CHECK (sequence >= 0),
+    event_type     TEXT   NOT NULL,
+    event_version  TEXT   NOT NULL,
+    payload        JSONB  NOT NULL,
+    metadata       JSONB  NOT NULL,
+    UNIQUE (aggregate_type, aggregate_id, sequence)
+);
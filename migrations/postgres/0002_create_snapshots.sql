@@ -0,0 +1,9 @@
+CREATE TABLE snapshots
+(
+    aggregate_type   TEXT   NOT NULL,
+    aggregate_id     TEXT   NOT NULL,
+    last_sequence    BIGINT NOT NULL CHECK (last_sequence >= 0),
+    snapshot_version BIGINT NOT NULL CHECK (snapshot_version >= 0),
+    payload          JSONB  NOT NULL,
+    PRIMARY KEY (aggregate_type, aggregate_id)
+);
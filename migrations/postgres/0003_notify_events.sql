@@ -0,0 +1,14 @@
+CREATE FUNCTION actuality_notify_event() RETURNS TRIGGER AS
+$$
+BEGIN
+    PERFORM pg_notify('actuality_events',
+                      json_build_object('aggregate_type', NEW.aggregate_type, 'position', NEW.position)::TEXT);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_notify
+    AFTER INSERT
+    ON events
+    FOR EACH ROW
+EXECUTE FUNCTION actuality_notify_event();
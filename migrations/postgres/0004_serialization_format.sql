@@ -0,0 +1,7 @@
+ALTER TABLE events
+    ADD COLUMN format TEXT NOT NULL DEFAULT 'json',
+    ALTER COLUMN payload TYPE BYTEA USING convert_to(payload::TEXT, 'UTF8');
+
+ALTER TABLE snapshots
+    ADD COLUMN format TEXT NOT NULL DEFAULT 'json',
+    ALTER COLUMN payload TYPE BYTEA USING convert_to(payload::TEXT, 'UTF8');
@@ -0,0 +1,5 @@
+ALTER TABLE events
+    ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
+
+ALTER TABLE snapshots
+    ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
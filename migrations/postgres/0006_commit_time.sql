@@ -0,0 +1,5 @@
+ALTER TABLE events
+    ADD COLUMN committed_at TIMESTAMPTZ;
+
+ALTER TABLE events
+    ALTER COLUMN committed_at SET DEFAULT now();
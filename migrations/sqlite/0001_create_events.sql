@@ -0,0 +1,12 @@
+CREATE TABLE events
+(
+    position       INTEGER PRIMARY KEY AUTOINCREMENT,
+    aggregate_type TEXT    NOT NULL,
+    aggregate_id   TEXT    NOT NULL,
+    sequence       INTEGER NOT NULL CHECK (sequence >= 0),
+    event_type     TEXT    NOT NULL,
+    event_version  TEXT    NOT NULL,
+    payload        TEXT    NOT NULL,
+    metadata       TEXT    NOT NULL,
+    UNIQUE (aggregate_type, aggregate_id, sequence)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE snapshots
+(
+    aggregate_type   TEXT    NOT NULL,
+    aggregate_id     TEXT    NOT NULL,
+    last_sequence    INTEGER NOT NULL CHECK (last_sequence >= 0),
+    snapshot_version INTEGER NOT NULL CHECK (snapshot_version >= 0),
+    payload          TEXT    NOT NULL,
+    PRIMARY KEY (aggregate_type, aggregate_id)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE events ADD COLUMN format TEXT NOT NULL DEFAULT 'json';
+UPDATE events SET payload = CAST(payload AS BLOB);
+
+ALTER TABLE snapshots ADD COLUMN format TEXT NOT NULL DEFAULT 'json';
+UPDATE snapshots SET payload = CAST(payload AS BLOB);
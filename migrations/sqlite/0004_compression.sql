@@ -0,0 +1,3 @@
+ALTER TABLE events ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
+
+ALTER TABLE snapshots ADD COLUMN compression TEXT NOT NULL DEFAULT 'none';
@@ -0,0 +1 @@
+ALTER TABLE events ADD COLUMN committed_at TEXT;
@@ -4,6 +4,7 @@ pub mod error;
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::fmt;
 
 use crate::DomainEvent;
 
@@ -19,6 +20,7 @@ use crate::DomainEvent;
 /// ```rust
 /// # use actuality::doc::setup::{CustomerEvent, CustomerError, CustomerCommand, CustomerService};
 /// # use actuality::{Aggregate, AggregateError};
+/// # use actuality::event::EventMetadata;
 /// # use serde::{Serialize,Deserialize};
 /// # use async_trait::async_trait;
 /// #[derive(Default,Serialize,Deserialize)]
@@ -33,6 +35,7 @@ use crate::DomainEvent;
 ///     type Event = CustomerEvent;
 ///     type Error = CustomerError;
 ///     type Services = CustomerService;
+///     type Metadata = EventMetadata;
 ///
 ///
 ///     fn aggregate_type() -> String { "customer".to_string() }
@@ -76,6 +79,16 @@ pub trait Aggregate: Default + Serialize + DeserializeOwned + Sync + Send {
     type Error: std::error::Error;
     /// The external services required for the logic within the Aggregate
     type Services: Send + Sync;
+    /// The metadata committed alongside the events, e.g., audit or tracing information.
+    /// [EventMetadata](crate::event::EventMetadata) may be used where a map of strings suffices.
+    ///
+    /// Events persisted before the metadata type changed are deserialized into the new type, so
+    /// any added fields should be optional or `#[serde(default)]`.
+    ///
+    /// Associated types may not yet have a default, so implementations written before this type
+    /// was added should declare `type Metadata = EventMetadata;`, the metadata previously
+    /// committed with every event.
+    type Metadata: Serialize + DeserializeOwned + Clone + Default + fmt::Debug + Send + Sync;
     /// The aggregate type is used as the unique identifier for this aggregate and its events.
     /// This is used for persisting the events and snapshots to a database.
     fn aggregate_type() -> String;
@@ -102,6 +115,7 @@ pub trait Aggregate: Default + Serialize + DeserializeOwned + Sync + Send {
     /// #     type Event = CustomerEvent;
     /// #     type Error = CustomerError;
     /// #     type Services = CustomerService;
+    /// #     type Metadata = actuality::event::EventMetadata;
     /// #     fn aggregate_type() -> String { "customer".to_string() }
     /// async fn handle(&self, command: Self::Command, service: &Self::Services) -> Result<Vec<Self::Event>, Self::Error> {
     ///     match command {
@@ -155,6 +169,7 @@ pub trait Aggregate: Default + Serialize + DeserializeOwned + Sync + Send {
     /// #     type Event = CustomerEvent;
     /// #     type Error = CustomerError;
     /// #     type Services = CustomerService;
+    /// #     type Metadata = actuality::event::EventMetadata;
     /// #     fn aggregate_type() -> String { "customer".to_string() }
     /// # async fn handle(&self, command: Self::Command, service: &Self::Services) -> Result<Vec<Self::Event>, Self::Error> {
     /// # Ok(vec![])
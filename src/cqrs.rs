@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use crate::query::Query;
 use crate::store::EventStore;
 use crate::Aggregate;
@@ -53,11 +51,11 @@ where
     /// let cqrs = Cqrs::new(store, queries, service);
     /// ```
     /// For production uses a
-    /// [persistent event store](https://docs.rs/cqrs-es/latest/cqrs_es/persist/struct.PersistedEventStore.html)
-    /// using a backing database is needed, such as in the available persistence crates:
-    /// - [PostgreSQL](https://www.postgresql.org/) - [postgres-es](https://crates.io/crates/postgres-es)
-    /// - [MySQL](https://www.mysql.com/) - [mysql-es](https://crates.io/crates/mysql-es)
-    /// - [DynamoDb](https://aws.amazon.com/dynamodb/) - [dynamo-es](https://crates.io/crates/dynamo-es)
+    /// [persistent event store](crate::persist::PersistedEventStore)
+    /// using a backing database is needed, such as one of the included repositories:
+    /// - [PostgreSQL](https://www.postgresql.org/) - `persist::postgres`, with the `postgres` feature
+    /// - [SQLite](https://www.sqlite.org/) - `persist::sqlite`, with the `sqlite` feature
+    /// - a local directory - `persist::FileEventRepository`
     ///
     pub fn new(
         store: ES,
@@ -130,7 +128,7 @@ where
         aggregate_id: &str,
         command: A::Command,
     ) -> Result<(), AggregateError<A::Error>> {
-        self.execute_with_metadata(aggregate_id, command, HashMap::new())
+        self.execute_with_metadata(aggregate_id, command, A::Metadata::default())
             .await
     }
 
@@ -138,7 +136,7 @@ where
     /// in this way is the only way to make changes to
     /// the state of an aggregate in CQRS.
     ///
-    /// A `Hashmap<String,String>` is supplied with any contextual information that should be
+    /// The aggregate's `Metadata` is supplied with any contextual information that should be
     /// associated with this change. This metadata will be attached to any produced events and is
     /// meant to assist in debugging and auditing. Common information might include:
     /// - time of commit
@@ -172,7 +170,7 @@ where
         &self,
         aggregate_id: &str,
         command: A::Command,
-        metadata: HashMap<String, String>,
+        metadata: A::Metadata,
     ) -> Result<(), AggregateError<A::Error>> {
         let aggregate_context = self.store.load_aggregate(aggregate_id).await?;
         let aggregate = aggregate_context.aggregate();
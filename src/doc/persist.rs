@@ -1,15 +1,14 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 
 use crate::ReplayStream;
 use crate::persist::{
     PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot, ViewContext,
     ViewRepository,
 };
-use crate::{Aggregate, EventEnvelope, View};
+use crate::{Aggregate, EventEnvelope, MultiSourceView, View};
 
-use crate::doc::setup::MyAggregate;
+use crate::doc::setup::{Customer, MyAggregate};
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MyView;
@@ -51,6 +50,62 @@ impl ViewRepository<MyView, MyAggregate> for MyViewRepository {
     }
 }
 
+pub enum MyDashboardEvent {
+    Customer(EventEnvelope<Customer>),
+    Other(EventEnvelope<MyAggregate>),
+}
+
+impl From<EventEnvelope<Customer>> for MyDashboardEvent {
+    fn from(event: EventEnvelope<Customer>) -> Self {
+        MyDashboardEvent::Customer(event)
+    }
+}
+
+impl From<EventEnvelope<MyAggregate>> for MyDashboardEvent {
+    fn from(event: EventEnvelope<MyAggregate>) -> Self {
+        MyDashboardEvent::Other(event)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MyDashboard;
+
+impl MultiSourceView<MyDashboardEvent> for MyDashboard {
+    fn update(&mut self, _event: &MyDashboardEvent) {
+        todo!()
+    }
+}
+
+pub struct MyDashboardRepository;
+
+impl MyDashboardRepository {
+    pub fn new(_db: MyDatabaseConnection) -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl ViewRepository<MyDashboard, MyDashboardEvent> for MyDashboardRepository {
+    async fn load(&self, _view_id: &str) -> Result<Option<MyDashboard>, PersistenceError> {
+        todo!()
+    }
+
+    async fn load_with_context(
+        &self,
+        _view_id: &str,
+    ) -> Result<Option<(MyDashboard, ViewContext)>, PersistenceError> {
+        todo!()
+    }
+
+    async fn update_view(
+        &self,
+        _view: MyDashboard,
+        _context: ViewContext,
+    ) -> Result<(), PersistenceError> {
+        todo!()
+    }
+}
+
 pub struct MyEventIterator;
 impl Iterator for MyEventIterator {
     type Item = Result<SerializedEvent, PersistenceError>;
@@ -95,7 +150,7 @@ impl PersistedEventRepository for MyEventRepository {
     async fn persist<A: Aggregate>(
         &self,
         _events: &[SerializedEvent],
-        _snapshot_update: Option<(String, Value, usize)>,
+        _snapshot_update: Option<SerializedSnapshot>,
     ) -> Result<(), PersistenceError> {
         todo!()
     }
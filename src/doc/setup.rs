@@ -1,12 +1,11 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use std::fmt::{Display, Formatter};
 
 use crate::persist::{
     PersistedEventRepository, PersistenceError, ReplayStream, SerializedEvent, SerializedSnapshot,
 };
-use crate::{Aggregate, DomainEvent, EventEnvelope, query::Query};
+use crate::{Aggregate, DomainEvent, EventEnvelope, EventMetadata, query::Query};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum MyEvents {
@@ -36,6 +35,7 @@ impl Aggregate for MyAggregate {
     type Event = MyEvents;
     type Error = MyUserError;
     type Services = MyService;
+    type Metadata = EventMetadata;
 
     fn aggregate_type() -> String {
         "MyAggregate".to_string()
@@ -96,6 +96,7 @@ impl Aggregate for Customer {
     type Event = CustomerEvent;
     type Error = CustomerError;
     type Services = CustomerService;
+    type Metadata = EventMetadata;
 
     fn aggregate_type() -> String {
         "Customer".to_string()
@@ -247,7 +248,7 @@ impl PersistedEventRepository for MyRepository {
     async fn persist<A: Aggregate>(
         &self,
         _events: &[SerializedEvent],
-        _snapshot_update: Option<(String, Value, usize)>,
+        _snapshot_update: Option<SerializedSnapshot>,
     ) -> Result<(), PersistenceError> {
         todo!()
     }
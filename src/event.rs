@@ -17,6 +17,9 @@ use uuid::Uuid;
 
 pub type SequenceNumber = usize;
 
+/// Event metadata as a map of names to values, for an aggregate without structured metadata.
+pub type EventMetadata = std::collections::HashMap<String, String>;
+
 pub trait Event {
 
 }
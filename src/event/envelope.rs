@@ -29,5 +29,5 @@ where
     pub payload: A::Event,
     /// Additional metadata for use in auditing, logging or debugging purposes.
     /// Example, relevant environment variable names and values.
-    pub metadata: std::collections::HashMap<String, String>,
+    pub metadata: A::Metadata,
 }
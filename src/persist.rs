@@ -1,33 +1,68 @@
 //! Common persistence mechanisms.
 //!
-//! This module is used alongside one of the available repository crates:
-//! - [postgres-es](https://crates.io/crates/postgres-es)
-//! - [mysql-es](https://crates.io/crates/mysql-es)
-//! - [dynamo-es](https://crates.io/crates/dynamo-es)
-//!
+//! This module is used with one of the included repositories:
+//! - `FileEventRepository` - where no database is available
+//! - `postgres` - a [PostgreSQL](https://www.postgresql.org/) database, with the `postgres` feature
+//! - `sqlite` - a [SQLite](https://www.sqlite.org/) database, with the `sqlite` feature
 //!
 //!
+pub use compression::Compression;
 pub use context::EventStoreAggregateContext;
+pub use encryption::{DataKey, EncryptedFields, InMemoryKeyStore, KeyStore};
 pub use error::PersistenceError;
+pub use event_filter::EventFilter;
 pub use event_repository::PersistedEventRepository;
 pub use event_store::PersistedEventStore;
-pub use event_stream::{ReplayStream,ReplayFeed};
-pub use generic_query::{GenericQuery, QueryErrorHandler};
-pub use replay::{QueryReplay};
+pub use event_stream::{EnvelopeStream, ReplayFeed, ReplayStream};
+pub use file_repository::{FileEventRepository, FsyncPolicy};
+pub use generic_query::{GenericQuery, QueryErrorHandler, UpdateRetryPolicy, ViewIdSelector};
+pub use memory_view_repository::MemoryViewRepository;
+pub use migration::{EventMigration, EventTransformFunc, MigrationReport};
+pub use multi_source_query::MultiSourceQuery;
+pub use rebuild::ViewRebuild;
+pub use replay::{
+    CancellationToken, QueryReplay, ReplayErrorPolicy, ReplayProgress, ReplayProgressHandler,
+    ReplayReport,
+};
+#[cfg(feature = "schema")]
+pub use schema::{BreakingChange, EventSchema, SchemaRegistry};
 pub use serialized_event::{SerializedEvent, SerializedSnapshot};
+pub use serializer::{
+    EventSerializer, JsonSerializer, PostcardSerializer, RkyvSerializer, SerializationFormat,
+};
 pub use upcaster::{
-    EventUpcaster, SemanticVersion, SemanticVersionError, SemanticVersionEventUpcaster,
-    SemanticVersionEventUpcasterFunc,
+    AppliedUpcaster, DropEventUpcaster, EventUpcaster, MoveEventUpcaster, RenameEventUpcaster,
+    SemanticVersion, SemanticVersionError, SemanticVersionEventUpcaster,
+    SemanticVersionEventUpcasterFunc, UpcasterRegistry, UpcasterRegistryError, VersionedUpcaster,
 };
+pub use view_cache::ViewCacheConfig;
+pub use view_query::{FieldCondition, QueryableViewRepository, SortOrder, ViewPage, ViewQuery};
 pub use view_repository::{ViewContext, ViewRepository};
 
+mod compression;
 mod context;
+mod encryption;
 mod error;
+mod event_filter;
 mod event_repository;
 mod event_store;
 pub mod event_stream;
+mod file_repository;
 mod generic_query;
+mod memory_view_repository;
+mod migration;
+mod multi_source_query;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod rebuild;
 mod replay;
+#[cfg(feature = "schema")]
+mod schema;
 mod serialized_event;
+mod serializer;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 mod upcaster;
+mod view_cache;
+mod view_query;
 mod view_repository;
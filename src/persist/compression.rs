@@ -0,0 +1,189 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::persist::PersistenceError;
+
+/// Compression level used for zstd, its default.
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// Identifies the codec a payload was compressed with, stored alongside each event and
+/// snapshot so that uncompressed payloads, or those written with another codec, remain
+/// readable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compression {
+    /// The payload is stored as serialized.
+    #[default]
+    None,
+    /// [Zstandard](https://facebook.github.io/zstd/), enabled with the `zstd` feature.
+    Zstd,
+    /// [LZ4](https://lz4.org/), enabled with the `lz4` feature. Faster than zstd but with a
+    /// lower compression ratio.
+    Lz4,
+}
+
+impl Compression {
+    /// The tag stored with each payload, e.g., `zstd`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        }
+    }
+
+    /// Fails unless the codec's feature is enabled, so that a store cannot be configured to
+    /// write payloads it is unable to compress.
+    pub(crate) fn check_enabled(&self) -> Result<(), PersistenceError> {
+        match self {
+            Compression::None => Ok(()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(()),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(()),
+            #[allow(unreachable_patterns)]
+            _ => Err(PersistenceError::UnknownError(self.feature_required())),
+        }
+    }
+
+    pub(crate) fn compress(&self, payload: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+        match self {
+            Compression::None => Ok(payload.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::encode_all(payload, ZSTD_LEVEL)
+                .map_err(|err| PersistenceError::UnknownError(Box::new(err))),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(payload)),
+            #[allow(unreachable_patterns)]
+            _ => Err(PersistenceError::UnknownError(self.feature_required())),
+        }
+    }
+
+    pub(crate) fn decompress(&self, payload: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+        match self {
+            Compression::None => Ok(payload.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::decode_all(payload)
+                .map_err(|err| PersistenceError::DeserializationError(Box::new(err))),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(payload)
+                .map_err(|err| PersistenceError::DeserializationError(Box::new(err))),
+            #[allow(unreachable_patterns)]
+            _ => Err(PersistenceError::DeserializationError(
+                self.feature_required(),
+            )),
+        }
+    }
+
+    fn feature_required(&self) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        format!("the '{}' feature is required for {} payloads", self, self).into()
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = PersistenceError;
+
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        match tag {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "lz4" => Ok(Compression::Lz4),
+            _ => Err(PersistenceError::DeserializationError(
+                format!("unknown compression '{}'", tag).into(),
+            )),
+        }
+    }
+}
+
+/// Compresses payloads of at least `threshold` bytes with the configured codec, smaller
+/// payloads gaining little and being left as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CompressionPolicy {
+    pub(crate) compression: Compression,
+    pub(crate) threshold: usize,
+}
+
+impl CompressionPolicy {
+    /// Returns the codec to apply to a payload of the given size.
+    pub(crate) fn codec_for(&self, payload: &[u8]) -> Compression {
+        if payload.len() < self.threshold {
+            Compression::None
+        } else {
+            self.compression
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::persist::compression::CompressionPolicy;
+    use crate::persist::Compression;
+
+    #[test]
+    #[cfg(all(feature = "zstd", feature = "lz4"))]
+    fn codecs_round_trip() {
+        let payload = "some repetitive payload ".repeat(100).into_bytes();
+        for compression in [Compression::None, Compression::Zstd, Compression::Lz4] {
+            let compressed = compression.compress(&payload).unwrap();
+            if compression != Compression::None {
+                assert!(compressed.len() < payload.len());
+            }
+            assert_eq!(payload, compression.decompress(&compressed).unwrap());
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "zstd", feature = "lz4"))]
+    fn corrupt_payloads() {
+        assert!(Compression::Zstd.decompress(b"not zstd").is_err());
+        assert!(Compression::Lz4.decompress(b"not lz4").is_err());
+    }
+
+    #[test]
+    fn enabled_codecs() {
+        assert!(Compression::None.check_enabled().is_ok());
+        assert_eq!(
+            cfg!(feature = "zstd"),
+            Compression::Zstd.check_enabled().is_ok()
+        );
+        assert_eq!(
+            cfg!(feature = "lz4"),
+            Compression::Lz4.check_enabled().is_ok()
+        );
+    }
+
+    #[test]
+    fn threshold() {
+        let policy = CompressionPolicy {
+            compression: Compression::Zstd,
+            threshold: 4,
+        };
+        assert_eq!(Compression::None, policy.codec_for(b"abc"));
+        assert_eq!(Compression::Zstd, policy.codec_for(b"abcd"));
+        assert_eq!(
+            Compression::None,
+            CompressionPolicy::default().codec_for(b"abcd")
+        );
+    }
+
+    #[test]
+    fn compression_tags() {
+        for compression in [Compression::None, Compression::Zstd, Compression::Lz4] {
+            assert_eq!(
+                compression,
+                Compression::from_str(compression.as_str()).unwrap()
+            );
+        }
+        assert!(Compression::from_str("gzip").is_err());
+    }
+}
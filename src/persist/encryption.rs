@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{AeadCore, Aes256Gcm, KeyInit, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::{json, Value};
+
+use crate::persist::{PersistenceError, SerializationFormat, SerializedEvent};
+
+/// The key identifying an encrypted field value within a JSON payload.
+const ENCRYPTED_TAG: &str = "$encrypted";
+
+/// Length of the AES-GCM nonce prepended to each ciphertext.
+const NONCE_LEN: usize = 12;
+
+/// A 256 bit AES-GCM key used to encrypt the personal data of a single subject.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DataKey([u8; 32]);
+
+impl DataKey {
+    /// Generates a new random key.
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Creates a key from its raw bytes, e.g., as loaded from an external key store.
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    /// The raw bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Debug for DataKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DataKey(..)")
+    }
+}
+
+/// Holds the data keys used to encrypt personal data within events, one key per subject.
+///
+/// Deleting a subject's key crypto-shreds their data: events remain in the event store but the
+/// encrypted fields can no longer be read and are replaced with a placeholder when loaded.
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    /// Returns the key for the subject, creating one if none exists.
+    async fn get_or_create_key(&self, subject: &str) -> Result<DataKey, PersistenceError>;
+
+    /// Returns the key for the subject, or `None` if it was never created or has been deleted.
+    async fn get_key(&self, subject: &str) -> Result<Option<DataKey>, PersistenceError>;
+
+    /// Permanently deletes the key for the subject.
+    async fn delete_key(&self, subject: &str) -> Result<(), PersistenceError>;
+}
+
+/// A `KeyStore` holding keys in memory, for testing or where keys are not required to outlive
+/// the process.
+#[derive(Default)]
+pub struct InMemoryKeyStore {
+    keys: Mutex<HashMap<String, DataKey>>,
+}
+
+#[async_trait]
+impl KeyStore for InMemoryKeyStore {
+    async fn get_or_create_key(&self, subject: &str) -> Result<DataKey, PersistenceError> {
+        // uninteresting unwrap: a poisoned lock means a panic occurred while holding it
+        let mut keys = self.keys.lock().unwrap();
+        Ok(keys
+            .entry(subject.to_string())
+            .or_insert_with(DataKey::generate)
+            .clone())
+    }
+
+    async fn get_key(&self, subject: &str) -> Result<Option<DataKey>, PersistenceError> {
+        Ok(self.keys.lock().unwrap().get(subject).cloned())
+    }
+
+    async fn delete_key(&self, subject: &str) -> Result<(), PersistenceError> {
+        self.keys.lock().unwrap().remove(subject);
+        Ok(())
+    }
+}
+
+/// Marks the fields of an event type's JSON payload that hold personal data and should be
+/// encrypted, identified by [JSON pointers](https://datatracker.ietf.org/doc/html/rfc6901).
+///
+/// ```
+/// use actuality::persist::EncryptedFields;
+/// use serde_json::json;
+///
+/// let fields = EncryptedFields::new("CustomerRegistered", &["/email", "/address/street"])
+///     .with_subject("/customer_id")
+///     .with_placeholder(json!(""));
+/// ```
+#[derive(Clone, Debug)]
+pub struct EncryptedFields {
+    event_type: String,
+    fields: Vec<String>,
+    subject: Option<String>,
+    placeholder: Value,
+}
+
+impl EncryptedFields {
+    /// Marks the fields of the event type, the data is encrypted with the key of the aggregate
+    /// instance the event belongs to unless configured otherwise.
+    pub fn new(event_type: &str, fields: &[&str]) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            subject: None,
+            placeholder: json!("[redacted]"),
+        }
+    }
+
+    /// Identifies the subject that the data belongs to by a field within the payload.
+    pub fn with_subject(self, subject: &str) -> Self {
+        Self {
+            subject: Some(subject.to_string()),
+            ..self
+        }
+    }
+
+    /// Sets the value substituted for fields whose key has been deleted, `"[redacted]"` by
+    /// default. This must deserialize into the field's type.
+    pub fn with_placeholder(self, placeholder: Value) -> Self {
+        Self {
+            placeholder,
+            ..self
+        }
+    }
+
+    fn subject(
+        &self,
+        event: &SerializedEvent,
+        payload: &Value,
+    ) -> Result<String, PersistenceError> {
+        let pointer = match &self.subject {
+            None => return Ok(event.aggregate_id.clone()),
+            Some(pointer) => pointer,
+        };
+        match payload.pointer(pointer) {
+            Some(Value::String(subject)) => Ok(subject.clone()),
+            Some(Value::Number(subject)) => Ok(subject.to_string()),
+            _ => Err(PersistenceError::UnknownError(
+                format!("{} has no subject at '{}'", event.event_type, pointer).into(),
+            )),
+        }
+    }
+}
+
+/// Encrypts and decrypts the marked fields of serialized events.
+pub(crate) struct FieldEncryption {
+    key_store: Arc<dyn KeyStore>,
+    fields: HashMap<String, EncryptedFields>,
+}
+
+impl FieldEncryption {
+    pub(crate) fn new(key_store: Arc<dyn KeyStore>, fields: Vec<EncryptedFields>) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|fields| (fields.event_type.clone(), fields))
+            .collect();
+        Self { key_store, fields }
+    }
+
+    pub(crate) async fn encrypt_events(
+        &self,
+        events: Vec<SerializedEvent>,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut result = Vec::with_capacity(events.len());
+        for event in events {
+            result.push(self.encrypt_event(event).await?);
+        }
+        Ok(result)
+    }
+
+    async fn encrypt_event(
+        &self,
+        event: SerializedEvent,
+    ) -> Result<SerializedEvent, PersistenceError> {
+        let marked = match self.fields.get(&event.event_type) {
+            None => return Ok(event),
+            Some(marked) => marked,
+        };
+        if event.format != SerializationFormat::Json {
+            return Err(PersistenceError::UnknownError(
+                format!(
+                    "{} has encrypted fields, which are only supported for json payloads",
+                    event.event_type
+                )
+                .into(),
+            ));
+        }
+        let mut payload: Value = serde_json::from_slice(&event.payload)?;
+        let subject = marked.subject(&event, &payload)?;
+        let key = self.key_store.get_or_create_key(&subject).await?;
+        for field in &marked.fields {
+            if let Some(value) = payload.pointer_mut(field) {
+                *value = encrypt_value(&key, &subject, value)?;
+            }
+        }
+        Ok(SerializedEvent {
+            payload: serde_json::to_vec(&payload)?,
+            ..event
+        })
+    }
+
+    /// Decrypts the marked fields of each event, substituting the placeholder for fields
+    /// whose subject's key has been deleted.
+    pub(crate) async fn decrypt_events(
+        &self,
+        events: Vec<SerializedEvent>,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut keys: HashMap<String, Option<DataKey>> = HashMap::new();
+        let mut result = Vec::with_capacity(events.len());
+        for event in events {
+            let marked = match self.fields.get(&event.event_type) {
+                Some(marked) if event.format == SerializationFormat::Json => marked,
+                _ => {
+                    result.push(event);
+                    continue;
+                }
+            };
+            let mut payload: Value = serde_json::from_slice(&event.payload)?;
+            for field in &marked.fields {
+                let value = match payload.pointer_mut(field) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let (subject, data) = match encrypted_parts(value) {
+                    Some(parts) => parts,
+                    None => continue,
+                };
+                if !keys.contains_key(&subject) {
+                    let key = self.key_store.get_key(&subject).await?;
+                    keys.insert(subject.clone(), key);
+                }
+                *value = match &keys[&subject] {
+                    Some(key) => decrypt_value(key, &subject, &data)?,
+                    None => marked.placeholder.clone(),
+                };
+            }
+            result.push(SerializedEvent {
+                payload: serde_json::to_vec(&payload)?,
+                ..event
+            });
+        }
+        Ok(result)
+    }
+}
+
+fn cipher(key: &DataKey) -> Aes256Gcm {
+    Aes256Gcm::new(key.as_bytes().into())
+}
+
+/// Encrypts a value, binding it to the subject, as
+/// `{"$encrypted": {"subject": .., "data": base64(nonce + ciphertext)}}`.
+fn encrypt_value(key: &DataKey, subject: &str, value: &Value) -> Result<Value, PersistenceError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(value)?;
+    let ciphertext = cipher(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: subject.as_bytes(),
+            },
+        )
+        .map_err(|_| PersistenceError::UnknownError("unable to encrypt field".into()))?;
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(json!({
+        ENCRYPTED_TAG: {
+            "subject": subject,
+            "data": STANDARD.encode(data),
+        }
+    }))
+}
+
+fn decrypt_value(key: &DataKey, subject: &str, data: &str) -> Result<Value, PersistenceError> {
+    let data = STANDARD
+        .decode(data)
+        .map_err(|err| PersistenceError::DeserializationError(Box::new(err)))?;
+    if data.len() < NONCE_LEN {
+        return Err(PersistenceError::DeserializationError(
+            "encrypted field is truncated".into(),
+        ));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher(key)
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: subject.as_bytes(),
+            },
+        )
+        .map_err(|_| PersistenceError::DeserializationError("unable to decrypt field".into()))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Returns the subject and data of an encrypted field value.
+fn encrypted_parts(value: &Value) -> Option<(String, String)> {
+    let encrypted = value.get(ENCRYPTED_TAG)?;
+    let subject = encrypted.get("subject")?.as_str()?;
+    let data = encrypted.get("data")?.as_str()?;
+    Some((subject.to_string(), data.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json::{json, Value};
+
+    use crate::persist::encryption::FieldEncryption;
+    use crate::persist::{
+        EncryptedFields, InMemoryKeyStore, KeyStore, PersistenceError, SerializationFormat,
+        SerializedEvent,
+    };
+
+    fn registered(customer_id: &str, email: &str) -> SerializedEvent {
+        SerializedEvent::new(
+            "account-1".to_string(),
+            1,
+            "Account".to_string(),
+            "CustomerRegistered".to_string(),
+            "1.0".to_string(),
+            serde_json::to_vec(&json!({
+                "customer_id": customer_id,
+                "email": email,
+                "address": {"street": "1 Main St", "city": "Springfield"},
+            }))
+            .unwrap(),
+            json!({}),
+        )
+    }
+
+    fn payload(event: &SerializedEvent) -> Value {
+        serde_json::from_slice(&event.payload).unwrap()
+    }
+
+    fn encryption(key_store: Arc<InMemoryKeyStore>) -> FieldEncryption {
+        FieldEncryption::new(
+            key_store,
+            vec![
+                EncryptedFields::new("CustomerRegistered", &["/email", "/address/street"])
+                    .with_subject("/customer_id"),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn encrypt_and_decrypt() {
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let encryption = encryption(key_store.clone());
+        let event = registered("customer-1", "alice@example.com");
+
+        let encrypted = encryption
+            .encrypt_events(vec![event.clone()])
+            .await
+            .unwrap();
+        let stored = payload(&encrypted[0]);
+        assert_eq!("customer-1", stored["customer_id"]);
+        assert_eq!("Springfield", stored["address"]["city"]);
+        assert_eq!("customer-1", stored["email"]["$encrypted"]["subject"]);
+        assert!(!String::from_utf8_lossy(&encrypted[0].payload).contains("alice"));
+        assert!(key_store.get_key("customer-1").await.unwrap().is_some());
+
+        let decrypted = encryption.decrypt_events(encrypted).await.unwrap();
+        assert_eq!(payload(&event), payload(&decrypted[0]));
+    }
+
+    #[tokio::test]
+    async fn crypto_shredding() {
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let encryption = encryption(key_store.clone());
+        let encrypted = encryption
+            .encrypt_events(vec![
+                registered("customer-1", "alice@example.com"),
+                registered("customer-2", "bob@example.com"),
+            ])
+            .await
+            .unwrap();
+
+        key_store.delete_key("customer-1").await.unwrap();
+        let decrypted = encryption.decrypt_events(encrypted).await.unwrap();
+        let shredded = payload(&decrypted[0]);
+        assert_eq!("[redacted]", shredded["email"]);
+        assert_eq!("[redacted]", shredded["address"]["street"]);
+        assert_eq!("Springfield", shredded["address"]["city"]);
+        assert_eq!("bob@example.com", payload(&decrypted[1])["email"]);
+    }
+
+    #[tokio::test]
+    async fn tampered_fields() {
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let encryption = encryption(key_store.clone());
+        let encrypted = encryption
+            .encrypt_events(vec![registered("customer-1", "alice@example.com")])
+            .await
+            .unwrap();
+        let mut stored = payload(&encrypted[0]);
+        stored["email"]["$encrypted"]["subject"] = json!("customer-2");
+        key_store.get_or_create_key("customer-2").await.unwrap();
+        let tampered = SerializedEvent {
+            payload: serde_json::to_vec(&stored).unwrap(),
+            ..encrypted[0].clone()
+        };
+
+        let result = encryption.decrypt_events(vec![tampered]).await;
+        assert!(matches!(
+            result,
+            Err(PersistenceError::DeserializationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn requires_json() {
+        let encryption = encryption(Arc::new(InMemoryKeyStore::default()));
+        let event = registered("customer-1", "alice@example.com")
+            .with_format(SerializationFormat::Postcard);
+        assert!(encryption.encrypt_events(vec![event]).await.is_err());
+    }
+}
@@ -56,6 +56,12 @@ impl From<serde_json::Error> for PersistenceError {
     }
 }
 
+impl From<std::io::Error> for PersistenceError {
+    fn from(err: std::io::Error) -> Self {
+        PersistenceError::UnknownError(Box::new(err))
+    }
+}
+
 impl From<tokio::sync::mpsc::error::SendError<Result<SerializedEvent, PersistenceError>>>
     for PersistenceError
 {
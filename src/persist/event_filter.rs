@@ -0,0 +1,173 @@
+use std::ops::{Bound, RangeBounds};
+
+use chrono::{DateTime, Utc};
+
+use crate::persist::SerializedEvent;
+
+/// Selects the committed events of an aggregate type to stream, e.g., to replay a bounded
+/// window of events to a single query.
+///
+/// Every configured condition must hold for an event to be selected, an unconfigured condition
+/// selects all events. Event types are matched as they were committed, before any upcasting.
+///
+/// ```
+/// use actuality::persist::EventFilter;
+///
+/// let filter = EventFilter::default()
+///     .with_aggregate_ids(["order-1", "order-2"])
+///     .with_event_types(["ItemAdded", "ItemRemoved"])
+///     .with_sequences(5..);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    pub(crate) aggregate_ids: Option<Vec<String>>,
+    pub(crate) event_types: Option<Vec<String>>,
+    pub(crate) min_sequence: Option<usize>,
+    pub(crate) max_sequence: Option<usize>,
+    pub(crate) min_position: Option<i64>,
+    pub(crate) max_position: Option<i64>,
+    pub(crate) committed_from: Option<DateTime<Utc>>,
+    pub(crate) committed_until: Option<DateTime<Utc>>,
+}
+
+impl EventFilter {
+    /// Selects only the events of these aggregate instances.
+    pub fn with_aggregate_ids<I, S>(self, aggregate_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            aggregate_ids: Some(aggregate_ids.into_iter().map(Into::into).collect()),
+            ..self
+        }
+    }
+
+    /// Selects only events of these types.
+    pub fn with_event_types<I, S>(self, event_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            event_types: Some(event_types.into_iter().map(Into::into).collect()),
+            ..self
+        }
+    }
+
+    /// Selects only events with a sequence, within their aggregate instance, in this range.
+    pub fn with_sequences(self, sequences: impl RangeBounds<usize>) -> Self {
+        let (min_sequence, max_sequence) = inclusive_bounds(sequences, 0, usize::MAX);
+        Self {
+            min_sequence,
+            max_sequence,
+            ..self
+        }
+    }
+
+    /// Selects only events with a position, within the repository's global ordering of events,
+    /// in this range.
+    ///
+    /// Positions are only known to repositories with a global ordering, e.g., the `sqlite` and
+    /// `postgres` repositories, other repositories returning an error when streaming.
+    pub fn with_positions(self, positions: impl RangeBounds<i64>) -> Self {
+        let (min_position, max_position) = inclusive_bounds(positions, i64::MIN, i64::MAX);
+        Self {
+            min_position,
+            max_position,
+            ..self
+        }
+    }
+
+    /// Selects only events committed at or after `from` and before `until`.
+    ///
+    /// Commit times are only recorded by the `sqlite` and `postgres` repositories, other
+    /// repositories returning an error when streaming. Events committed with either repository
+    /// before the schema migration recording commit times have no commit time and are never
+    /// selected.
+    pub fn with_committed_between(self, from: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        Self {
+            committed_from: Some(from),
+            committed_until: Some(until),
+            ..self
+        }
+    }
+
+    /// Whether the filter selects by position or commit time, which are not held by a
+    /// `SerializedEvent` and so must be selected by the repository.
+    pub(crate) fn requires_repository(&self) -> bool {
+        self.min_position.is_some()
+            || self.max_position.is_some()
+            || self.committed_from.is_some()
+            || self.committed_until.is_some()
+    }
+
+    /// Whether the event is selected by the aggregate id, event type and sequence conditions.
+    pub(crate) fn matches(&self, event: &SerializedEvent) -> bool {
+        self.aggregate_ids
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&event.aggregate_id))
+            && self
+                .event_types
+                .as_ref()
+                .is_none_or(|types| types.contains(&event.event_type))
+            && self.min_sequence.is_none_or(|min| event.sequence >= min)
+            && self.max_sequence.is_none_or(|max| event.sequence <= max)
+    }
+}
+
+/// Converts a range to optional inclusive bounds, `None` where the range is unbounded.
+///
+/// A range excluding the minimum or maximum value of `T` as its only value, e.g.,
+/// `(Excluded(usize::MAX), Unbounded)`, is empty but its bounds are not representable in `T`,
+/// such a range is converted to the bounds `max..=min`, which select nothing.
+fn inclusive_bounds<T, R>(range: R, min: T, max: T) -> (Option<T>, Option<T>)
+where
+    T: Copy + PartialEq + std::ops::Add<Output = T> + std::ops::Sub<Output = T> + From<u8>,
+    R: RangeBounds<T>,
+{
+    let lower = match range.start_bound() {
+        Bound::Included(start) => Some(*start),
+        Bound::Excluded(start) if *start == max => return (Some(max), Some(min)),
+        Bound::Excluded(start) => Some(*start + T::from(1)),
+        Bound::Unbounded => None,
+    };
+    let upper = match range.end_bound() {
+        Bound::Included(end) => Some(*end),
+        Bound::Excluded(end) if *end == min => return (Some(max), Some(min)),
+        Bound::Excluded(end) => Some(*end - T::from(1)),
+        Bound::Unbounded => None,
+    };
+    (lower, upper)
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Bound;
+
+    use crate::persist::event_store::shared_test::{test_serialized_event, TestEvents};
+    use crate::persist::EventFilter;
+
+    #[test]
+    fn sequences_excluding_max() {
+        let filter =
+            EventFilter::default().with_sequences((Bound::Excluded(usize::MAX), Bound::Unbounded));
+        assert_eq!(Some(usize::MAX), filter.min_sequence);
+        assert_eq!(Some(0), filter.max_sequence);
+        let mut event = test_serialized_event(1, TestEvents::SomethingWasDone);
+        assert!(!filter.matches(&event));
+        event.sequence = usize::MAX;
+        assert!(!filter.matches(&event));
+    }
+
+    #[test]
+    fn positions_excluding_min() {
+        let filter = EventFilter::default().with_positions(..i64::MIN);
+        assert_eq!(Some(i64::MAX), filter.min_position);
+        assert_eq!(Some(i64::MIN), filter.max_position);
+
+        let filter = EventFilter::default().with_positions(2..5);
+        assert_eq!(Some(2), filter.min_position);
+        assert_eq!(Some(4), filter.max_position);
+    }
+}
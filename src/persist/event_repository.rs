@@ -1,8 +1,7 @@
 use crate::persist::event_stream::ReplayStream;
-use crate::persist::{PersistenceError, SerializedEvent, SerializedSnapshot};
+use crate::persist::{EventFilter, PersistenceError, SerializedEvent, SerializedSnapshot};
 use crate::Aggregate;
 use async_trait::async_trait;
-use serde_json::Value;
 
 /// Handles the database access needed for operation of a PersistedSnapshotStore.
 #[async_trait]
@@ -28,10 +27,14 @@ pub trait PersistedEventRepository: Send + Sync {
     ) -> Result<Option<SerializedSnapshot>, PersistenceError>;
 
     /// Commits the updated aggregate and accompanying events.
+    ///
+    /// A snapshot update should only be stored if the previously stored snapshot version is
+    /// one less than `current_snapshot`, or none is stored and `current_snapshot` is 1,
+    /// otherwise an `OptimisticLockError` is returned.
     async fn persist<A: Aggregate>(
         &self,
         events: &[SerializedEvent],
-        snapshot_update: Option<(String, Value, usize)>,
+        snapshot_update: Option<SerializedSnapshot>,
     ) -> Result<(), PersistenceError>;
 
     /// Streams all events for an aggregate instance.
@@ -42,4 +45,23 @@ pub trait PersistedEventRepository: Send + Sync {
 
     /// Streams all events for an aggregate type.
     async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError>;
+
+    /// Streams the events for an aggregate type selected by the filter.
+    ///
+    /// By default all events of the aggregate type are streamed and filtered as they are
+    /// received, repositories able to select events themselves should override this. Filters
+    /// selecting by position or commit time return an error by default, as neither is held by
+    /// a `SerializedEvent`.
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &EventFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        if filter.requires_repository() {
+            return Err(PersistenceError::UnknownError(
+                "this repository cannot select events by position or commit time".into(),
+            ));
+        }
+        let stream = self.stream_all_events::<A>().await?;
+        Ok(stream.with_filter(filter.clone()))
+    }
 }
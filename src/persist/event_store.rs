@@ -1,12 +1,17 @@
-use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use serde_json::Value;
 
-use crate::persist::serialized_event::{deserialize_events, serialize_events};
+use crate::persist::compression::CompressionPolicy;
+use crate::persist::encryption::FieldEncryption;
+use crate::persist::serialized_event::{
+    deserialize_events, deserialize_snapshot, serialize_events,
+};
 use crate::persist::{
-    EventStoreAggregateContext, EventUpcaster, PersistedEventRepository, SerializedEvent,
+    Compression, EncryptedFields, EventSerializer, EventStoreAggregateContext, EventUpcaster,
+    JsonSerializer, KeyStore, PersistedEventRepository, PersistenceError, SerializedEvent,
+    SerializedSnapshot,
 };
 use crate::{Aggregate, AggregateError, EventEnvelope, EventStore};
 
@@ -81,6 +86,10 @@ where
     repo: R,
     storage: SourceOfTruth,
     event_upcasters: Option<Vec<Box<dyn EventUpcaster>>>,
+    event_serializer: Box<dyn EventSerializer<A::Event>>,
+    snapshot_serializer: Box<dyn EventSerializer<A>>,
+    compression: CompressionPolicy,
+    encryption: Option<FieldEncryption>,
     _phantom: PhantomData<A>,
 }
 
@@ -109,6 +118,10 @@ where
             repo,
             storage: SourceOfTruth::EventStore,
             event_upcasters: None,
+            event_serializer: Box::new(JsonSerializer),
+            snapshot_serializer: Box::new(JsonSerializer),
+            compression: CompressionPolicy::default(),
+            encryption: None,
             _phantom: PhantomData,
         }
     }
@@ -134,6 +147,10 @@ where
             repo,
             storage: SourceOfTruth::AggregateStore,
             event_upcasters: None,
+            event_serializer: Box::new(JsonSerializer),
+            snapshot_serializer: Box::new(JsonSerializer),
+            compression: CompressionPolicy::default(),
+            encryption: None,
             _phantom: PhantomData,
         }
     }
@@ -157,6 +174,10 @@ where
             repo,
             storage: SourceOfTruth::Snapshot(snapshot_size),
             event_upcasters: None,
+            event_serializer: Box::new(JsonSerializer),
+            snapshot_serializer: Box::new(JsonSerializer),
+            compression: CompressionPolicy::default(),
+            encryption: None,
             _phantom: PhantomData,
         }
     }
@@ -168,12 +189,114 @@ where
     /// E.g., an upcaster for version 0.2.3 should be placed before an upcaster for version 0.2.4
     pub fn with_upcasters(self, event_upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
         Self {
-            repo: self.repo,
-            storage: self.storage,
             event_upcasters: Some(event_upcasters),
-            _phantom: Default::default(),
+            ..self
+        }
+    }
+
+    /// Configures the serializer used for committed events and snapshots, JSON by default.
+    ///
+    /// Previously stored payloads are read according to the format they were written in, so
+    /// the serializer may be changed for an existing store.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyEventRepository;
+    /// use actuality::persist::{PersistedEventStore, PostcardSerializer};
+    ///
+    /// # fn config(repo: MyEventRepository) {
+    /// let store = PersistedEventStore::<MyEventRepository, MyAggregate>::new_event_store(repo)
+    ///     .with_serializer(PostcardSerializer);
+    /// # }
+    /// ```
+    pub fn with_serializer<S>(self, serializer: S) -> Self
+    where
+        S: EventSerializer<A::Event> + EventSerializer<A> + Clone + 'static,
+    {
+        Self {
+            event_serializer: Box::new(serializer.clone()),
+            snapshot_serializer: Box::new(serializer),
+            ..self
         }
     }
+
+    /// Configures the event store to compress serialized event payloads and snapshots of at
+    /// least `threshold` bytes, payloads are stored uncompressed by default.
+    ///
+    /// The codec is recorded with each payload, so previously stored payloads remain readable
+    /// whether they were compressed or not. Compressed events must be decompressed before
+    /// they can be upcast, this is done automatically when loading.
+    ///
+    /// Returns an error if the codec's cargo feature, `zstd` or `lz4`, is not enabled.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyEventRepository;
+    /// use actuality::persist::{Compression, PersistedEventStore, PersistenceError};
+    ///
+    /// # fn config(repo: MyEventRepository) -> Result<(), PersistenceError> {
+    /// let store = PersistedEventStore::<MyEventRepository, MyAggregate>::new_snapshot_store(repo, 100)
+    ///     .with_compression(Compression::Zstd, 1024)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_compression(
+        self,
+        compression: Compression,
+        threshold: usize,
+    ) -> Result<Self, PersistenceError> {
+        compression.check_enabled()?;
+        Ok(Self {
+            compression: CompressionPolicy {
+                compression,
+                threshold,
+            },
+            ..self
+        })
+    }
+
+    /// Configures the event store to encrypt the marked fields of committed events, each with
+    /// a key held in the `KeyStore` for the subject the data belongs to.
+    ///
+    /// Deleting a subject's key renders their encrypted fields unreadable, these are replaced
+    /// with a placeholder when events are loaded. Only JSON payloads may hold encrypted fields.
+    ///
+    /// Returns an error for a snapshot or aggregate store, as the serialized aggregate is not
+    /// encrypted and would retain the data of a subject whose key was deleted.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyEventRepository;
+    /// use std::sync::Arc;
+    /// use actuality::persist::{
+    ///     EncryptedFields, InMemoryKeyStore, PersistedEventStore, PersistenceError,
+    /// };
+    ///
+    /// # fn config(repo: MyEventRepository) -> Result<(), PersistenceError> {
+    /// let key_store = Arc::new(InMemoryKeyStore::default());
+    /// let store = PersistedEventStore::<MyEventRepository, MyAggregate>::new_event_store(repo)
+    ///     .with_encryption(
+    ///         key_store.clone(),
+    ///         vec![EncryptedFields::new("CustomerRegistered", &["/email", "/phone"])],
+    ///     )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_encryption(
+        self,
+        key_store: Arc<dyn KeyStore>,
+        fields: Vec<EncryptedFields>,
+    ) -> Result<Self, PersistenceError> {
+        if !matches!(self.storage, SourceOfTruth::EventStore) {
+            return Err(PersistenceError::UnknownError(
+                "encryption requires an event store, snapshots are not encrypted".into(),
+            ));
+        }
+        Ok(Self {
+            encryption: Some(FieldEncryption::new(key_store, fields)),
+            ..self
+        })
+    }
 }
 
 #[async_trait]
@@ -189,10 +312,7 @@ where
         aggregate_id: &str,
     ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
         let serialized_events = self.repo.get_events::<A>(aggregate_id).await?;
-        Ok(deserialize_events(
-            serialized_events,
-            &self.event_upcasters,
-        )?)
+        Ok(self.deserialize_events(serialized_events).await?)
     }
 
     async fn load_aggregate(
@@ -206,7 +326,9 @@ where
             _ => {
                 let snapshot = self.repo.get_snapshot::<A>(aggregate_id).await?;
                 match snapshot {
-                    Some(snapshot) => snapshot.try_into()?,
+                    Some(snapshot) => {
+                        deserialize_snapshot(snapshot, Some(self.snapshot_serializer.as_ref()))?
+                    }
                     None => EventStoreAggregateContext::context_for(aggregate_id, false),
                 }
             }
@@ -218,7 +340,7 @@ where
                     .repo
                     .get_last_events::<A>(aggregate_id, context.current_sequence)
                     .await?;
-                deserialize_events(serialized_events, &self.event_upcasters)?
+                self.deserialize_events(serialized_events).await?
             }
             SourceOfTruth::AggregateStore => {
                 vec![]
@@ -236,7 +358,7 @@ where
         &self,
         events: Vec<A::Event>,
         context: EventStoreAggregateContext<A>,
-        metadata: HashMap<String, String>,
+        metadata: A::Metadata,
     ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
         let aggregate_id = context.aggregate_id.clone();
         let last_sequence = context.current_sequence;
@@ -244,19 +366,26 @@ where
         let commit_snapshot_to_event = self
             .storage
             .commit_snapshot_with_addl_events(context.current_sequence, events.len());
-        let snapshot_update: Option<(Value, usize)> = if commit_snapshot_to_event == 0 {
+        let snapshot_update: Option<SerializedSnapshot> = if commit_snapshot_to_event == 0 {
             None
         } else {
             match self.storage {
                 SourceOfTruth::EventStore => None,
-                _ => Self::update_snapshot_with_events(&events, context, commit_snapshot_to_event)?,
+                _ => self.update_snapshot_with_events(&events, context, commit_snapshot_to_event)?,
             }
         };
         let event_type = ""; //context.event_type;
         let system_id = "";  //context.system_id;
         let wrapped_events = self.wrap_events(&aggregate_id, event_type, last_sequence, system_id, events, metadata);
-        let serialized_events: Vec<SerializedEvent> = serialize_events(&wrapped_events)?;
-        let snapshot_update = snapshot_update.map(|s| (aggregate_id, s.0, s.1));
+        let mut serialized_events =
+            serialize_events(&wrapped_events, self.event_serializer.as_ref())?;
+        if let Some(encryption) = &self.encryption {
+            serialized_events = encryption.encrypt_events(serialized_events).await?;
+        }
+        let serialized_events = serialized_events
+            .into_iter()
+            .map(|event| event.compress(&self.compression))
+            .collect::<Result<Vec<SerializedEvent>, _>>()?;
         self.repo
             .persist::<A>(&serialized_events, snapshot_update)
             .await?;
@@ -269,11 +398,34 @@ where
     A: Aggregate + Send + Sync,
     R: PersistedEventRepository,
 {
+    /// Decompresses, decrypts and upcasts the serialized events before deserializing them.
+    async fn deserialize_events(
+        &self,
+        events: Vec<SerializedEvent>,
+    ) -> Result<Vec<EventEnvelope<A>>, PersistenceError> {
+        let events = match &self.encryption {
+            None => events,
+            Some(encryption) => {
+                let events = events
+                    .into_iter()
+                    .map(SerializedEvent::decompress)
+                    .collect::<Result<Vec<_>, _>>()?;
+                encryption.decrypt_events(events).await?
+            }
+        };
+        deserialize_events(
+            events,
+            &self.event_upcasters,
+            self.event_serializer.as_ref(),
+        )
+    }
+
     fn update_snapshot_with_events(
+        &self,
         events: &[<A as Aggregate>::Event],
         mut context: EventStoreAggregateContext<A>,
         commit_snapshot_to_event: usize,
-    ) -> Result<Option<(Value, usize)>, AggregateError<A::Error>> {
+    ) -> Result<Option<SerializedSnapshot>, AggregateError<A::Error>> {
         let mut i = 0;
         for event in events.iter().cloned() {
             i += 1;
@@ -286,8 +438,15 @@ where
             Some(val) => val + 1,
             None => 1,
         };
-        let payload = serde_json::to_value(context.aggregate)?;
-        Ok(Some((payload, next_snapshot)))
+        let snapshot = SerializedSnapshot {
+            aggregate_id: context.aggregate_id,
+            format: self.snapshot_serializer.format(),
+            compression: Compression::None,
+            aggregate: self.snapshot_serializer.serialize(&context.aggregate)?,
+            current_sequence: context.current_sequence,
+            current_snapshot: next_snapshot,
+        };
+        Ok(Some(snapshot.compress(&self.compression)?))
     }
 
     /// Method to wrap a set of events with the additional metadata needed for persistence and publishing
@@ -298,7 +457,7 @@ where
         last_sequence: usize,
         system_id: &str,
         resultant_events: Vec<A::Event>,
-        base_metadata: HashMap<String, String>,
+        base_metadata: A::Metadata,
     ) -> Vec<EventEnvelope<A>> {
         let mut sequence = last_sequence;
         let mut wrapped_events: Vec<EventEnvelope<A>> = Vec::new();
@@ -324,21 +483,32 @@ where
 
 #[cfg(test)]
 pub(crate) mod shared_test {
-    use std::collections::HashMap;
     use std::fmt::{Display, Formatter};
+    use std::fs;
+    use std::path::PathBuf;
     use std::sync::Mutex;
 
     use async_trait::async_trait;
     use serde::{Deserialize, Serialize};
-    use serde_json::Value;
 
     use crate::persist::event_stream::ReplayStream;
     use crate::persist::{
-        PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot,
+        Compression, PersistedEventRepository, PersistenceError, SerializationFormat,
+        SerializedEvent, SerializedSnapshot,
     };
     use crate::{Aggregate, DomainEvent};
 
-    #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+    #[derive(
+        Debug,
+        Serialize,
+        Deserialize,
+        PartialEq,
+        Clone,
+        rkyv::Archive,
+        rkyv::Serialize,
+        rkyv::Deserialize,
+    )]
+    #[archive(check_bytes)]
     pub(crate) enum TestEvents {
         Started,
         SomethingWasDone,
@@ -382,6 +552,13 @@ pub(crate) mod shared_test {
     #[derive(Clone)]
     pub struct TestService;
 
+    #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+    #[serde(default)]
+    pub(crate) struct TestMetadata {
+        pub(crate) user: Option<String>,
+        pub(crate) attempt: u32,
+    }
+
     #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
     pub(crate) struct TestAggregate {
         pub(crate) something_happened: usize,
@@ -393,6 +570,7 @@ pub(crate) mod shared_test {
         type Event = TestEvents;
         type Error = TestError;
         type Services = TestService;
+        type Metadata = TestMetadata;
 
         fn aggregate_type() -> String {
             "TestAggregate".to_string()
@@ -431,7 +609,7 @@ pub(crate) mod shared_test {
         last_events_result: Mutex<Option<Result<Vec<SerializedEvent>, PersistenceError>>>,
         snapshot_result: Mutex<Option<Result<Option<SerializedSnapshot>, PersistenceError>>>,
         persist_check: Mutex<
-            Option<Box<dyn FnOnce(&[SerializedEvent], Option<(String, Value, usize)>) + Send>>,
+            Option<Box<dyn FnOnce(&[SerializedEvent], Option<SerializedSnapshot>) + Send>>,
         >,
     }
 
@@ -467,7 +645,7 @@ pub(crate) mod shared_test {
         }
         pub(crate) fn with_commit(
             test_function: Box<
-                dyn FnOnce(&[SerializedEvent], Option<(String, Value, usize)>) + Send,
+                dyn FnOnce(&[SerializedEvent], Option<SerializedSnapshot>) + Send,
             >,
         ) -> Self {
             Self {
@@ -503,7 +681,7 @@ pub(crate) mod shared_test {
         async fn persist<A: Aggregate>(
             &self,
             events: &[SerializedEvent],
-            snapshot_update: Option<(String, Value, usize)>,
+            snapshot_update: Option<SerializedSnapshot>,
         ) -> Result<(), PersistenceError> {
             let test = self.persist_check.lock().unwrap().take().unwrap();
             test(events, snapshot_update);
@@ -538,7 +716,7 @@ pub(crate) mod shared_test {
     pub(crate) fn test_serialized_event(seq: usize, event: TestEvents) -> SerializedEvent {
         let event_type = event.event_type();
         let event_version = event.event_version();
-        let payload = serde_json::to_value(&event).unwrap();
+        let payload = serde_json::to_vec(&event).unwrap();
         SerializedEvent::new(
             TEST_AGGREGATE_ID.to_string(),
             seq,
@@ -546,22 +724,79 @@ pub(crate) mod shared_test {
             event_type,
             event_version,
             payload,
-            serde_json::to_value(HashMap::<String, String>::new()).unwrap(),
+            serde_json::to_value(TestMetadata::default()).unwrap(),
         )
     }
+
+    pub(crate) fn test_serialized_snapshot(
+        aggregate_id: &str,
+        something_happened: usize,
+        current_sequence: usize,
+        current_snapshot: usize,
+    ) -> SerializedSnapshot {
+        SerializedSnapshot {
+            aggregate_id: aggregate_id.to_string(),
+            format: SerializationFormat::Json,
+            compression: Compression::None,
+            aggregate: serde_json::to_vec(&TestAggregate { something_happened }).unwrap(),
+            current_sequence,
+            current_snapshot,
+        }
+    }
+
+    /// A unique directory under the system temp directory, removed on drop.
+    pub(crate) struct TestDirectory(pub(crate) PathBuf);
+
+    impl TestDirectory {
+        pub(crate) fn new() -> Self {
+            let path = std::env::temp_dir().join(format!("actuality-{}", rand::random::<u64>()));
+            Self(path)
+        }
+
+        pub(crate) fn segment(&self, segment: u64) -> PathBuf {
+            self.0
+                .join("TestAggregate")
+                .join(format!("{:020}.log", segment))
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
 }
 
 #[cfg(test)]
 mod event_store_test {
-    use std::collections::HashMap;
+    use std::sync::Arc;
 
+    use serde_json::{json, Value};
+
+    use crate::persist::encryption::FieldEncryption;
     use crate::persist::event_store::shared_test::{
-        test_serialized_event, MockRepo, TestAggregate, TestEvents, EVENT_VERSION,
+        test_serialized_event, MockRepo, TestAggregate, TestEvents, TestMetadata, EVENT_VERSION,
         TEST_AGGREGATE_ID,
     };
-    use crate::persist::{EventStoreAggregateContext, PersistedEventStore, PersistenceError};
+    use crate::persist::{
+        Compression, EncryptedFields, EventSerializer, EventStoreAggregateContext,
+        InMemoryKeyStore, KeyStore, PersistedEventStore, PersistenceError, PostcardSerializer,
+        SemanticVersionEventUpcaster, SerializationFormat, SerializedEvent,
+    };
     use crate::{AggregateError, DomainEvent, EventStore};
 
+    fn postcard_event(seq: usize, event: TestEvents) -> SerializedEvent {
+        let mut serialized = test_serialized_event(seq, event.clone());
+        serialized.payload = PostcardSerializer.serialize(&event).unwrap();
+        serialized.with_format(SerializationFormat::Postcard)
+    }
+
+    /// Encrypts the entire payload of `SomethingWasDone`, which is replaced by `Started` once
+    /// shredded.
+    fn encrypted_fields() -> Vec<EncryptedFields> {
+        vec![EncryptedFields::new("SomethingWasDone", &[""]).with_placeholder(json!("Started"))]
+    }
+
     #[tokio::test]
     async fn load() {
         let repo = MockRepo::with_events(Ok(vec![test_serialized_event(
@@ -576,6 +811,92 @@ mod event_store_test {
         assert_eq!(EVENT_VERSION, event.payload.event_version());
     }
 
+    #[tokio::test]
+    async fn load_mixed_formats() {
+        let repo = MockRepo::with_events(Ok(vec![
+            test_serialized_event(1, TestEvents::Started),
+            postcard_event(2, TestEvents::SomethingWasDone),
+        ]));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_serializer(PostcardSerializer);
+        let events = store.load_events(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(TestEvents::Started, events[0].payload);
+        assert_eq!(TestEvents::SomethingWasDone, events[1].payload);
+    }
+
+    #[tokio::test]
+    async fn upcast_requires_json() {
+        let repo = MockRepo::with_events(Ok(vec![postcard_event(1, TestEvents::SomethingWasDone)]));
+        let upcaster =
+            SemanticVersionEventUpcaster::new("SomethingWasDone", "2.0", Box::new(|value| value));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_upcasters(vec![Box::new(upcaster)]);
+        let result = store.load_events(TEST_AGGREGATE_ID).await;
+        assert!(matches!(
+            result,
+            Err(AggregateError::DeserializationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "lz4")]
+    async fn upcast_compressed() {
+        let event = test_serialized_event(1, TestEvents::SomethingWasDone);
+        let event = SerializedEvent {
+            payload: Compression::Lz4.compress(&event.payload).unwrap(),
+            ..event.with_compression(Compression::Lz4)
+        };
+        let repo = MockRepo::with_events(Ok(vec![event]));
+        let upcaster =
+            SemanticVersionEventUpcaster::new("SomethingWasDone", "2.0", Box::new(|value| value));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_upcasters(vec![Box::new(upcaster)]);
+        let events = store.load_events(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(TestEvents::SomethingWasDone, events[0].payload);
+    }
+
+    #[tokio::test]
+    async fn load_encrypted() {
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let encrypted = FieldEncryption::new(key_store.clone(), encrypted_fields())
+            .encrypt_events(vec![test_serialized_event(1, TestEvents::SomethingWasDone)])
+            .await
+            .unwrap();
+
+        let repo = MockRepo::with_events(Ok(encrypted.clone()));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_encryption(key_store.clone(), encrypted_fields())
+            .unwrap();
+        let events = store.load_events(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(TestEvents::SomethingWasDone, events[0].payload);
+
+        key_store.delete_key(TEST_AGGREGATE_ID).await.unwrap();
+        let repo = MockRepo::with_events(Ok(encrypted));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_encryption(key_store, encrypted_fields())
+            .unwrap();
+        let events = store.load_events(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(TestEvents::Started, events[0].payload);
+    }
+
+    #[test]
+    fn encryption_requires_event_store() {
+        for store in [
+            PersistedEventStore::<MockRepo, TestAggregate>::new_snapshot_store(
+                MockRepo::with_events(Ok(vec![])),
+                2,
+            ),
+            PersistedEventStore::<MockRepo, TestAggregate>::new_aggregate_store(
+                MockRepo::with_events(Ok(vec![])),
+            ),
+        ] {
+            let key_store = Arc::new(InMemoryKeyStore::default());
+            assert!(store
+                .with_encryption(key_store, encrypted_fields())
+                .is_err());
+        }
+    }
+
     #[tokio::test]
     async fn load_error() {
         let repo = MockRepo::with_events(Err(PersistenceError::OptimisticLockError));
@@ -628,6 +949,43 @@ mod event_store_test {
         }
     }
 
+    #[tokio::test]
+    async fn commit_encrypted() {
+        let repo = MockRepo::with_commit(Box::new(|events, _| {
+            let started: Value = serde_json::from_slice(&events[0].payload).unwrap();
+            assert_eq!(json!("Started"), started);
+            let something_was_done: Value = serde_json::from_slice(&events[1].payload).unwrap();
+            assert_eq!(
+                json!(TEST_AGGREGATE_ID),
+                something_was_done["$encrypted"]["subject"]
+            );
+        }));
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo)
+            .with_encryption(key_store.clone(), encrypted_fields())
+            .unwrap();
+        let context = EventStoreAggregateContext {
+            aggregate_id: TEST_AGGREGATE_ID.to_string(),
+            aggregate: TestAggregate::default(),
+            current_sequence: 0,
+            current_snapshot: None,
+        };
+        let event_envelopes = store
+            .commit(
+                vec![TestEvents::Started, TestEvents::SomethingWasDone],
+                context,
+                TestMetadata::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(TestEvents::SomethingWasDone, event_envelopes[1].payload);
+        assert!(key_store
+            .get_key(TEST_AGGREGATE_ID)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
     #[tokio::test]
     async fn commit() {
         let repo = MockRepo::with_commit(Box::new(|events, snapshot_update| {
@@ -653,7 +1011,7 @@ mod event_store_test {
                     TestEvents::SomethingWasDone,
                 ],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
@@ -666,20 +1024,56 @@ mod event_store_test {
             event_envelopes.get(2).unwrap().payload
         );
     }
+
+    #[tokio::test]
+    async fn commit_metadata() {
+        let repo = MockRepo::with_commit(Box::new(|events, _| {
+            assert_eq!(json!({"user": "ops", "attempt": 2}), events[0].metadata);
+        }));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo);
+        let context = EventStoreAggregateContext {
+            aggregate_id: TEST_AGGREGATE_ID.to_string(),
+            aggregate: TestAggregate::default(),
+            current_sequence: 0,
+            current_snapshot: None,
+        };
+        let metadata = TestMetadata {
+            user: Some("ops".to_string()),
+            attempt: 2,
+        };
+        let event_envelopes = store
+            .commit(vec![TestEvents::Started], context, metadata.clone())
+            .await
+            .unwrap();
+        assert_eq!(metadata, event_envelopes[0].metadata);
+    }
+
+    #[tokio::test]
+    async fn load_metadata() {
+        let mut recorded = test_serialized_event(1, TestEvents::Started);
+        recorded.metadata = json!({"user": "ops"});
+        let mut legacy = test_serialized_event(2, TestEvents::SomethingWasDone);
+        legacy.metadata = json!({"time": "2021-03-18T12:32:45.930Z"});
+        let repo = MockRepo::with_events(Ok(vec![recorded, legacy]));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo);
+        let events = store.load_events(TEST_AGGREGATE_ID).await.unwrap();
+        assert_eq!(Some("ops".to_string()), events[0].metadata.user);
+        assert_eq!(TestMetadata::default(), events[1].metadata);
+    }
 }
 
 #[cfg(test)]
 pub(crate) mod snapshotted_store_test {
-    use std::collections::HashMap;
 
-    use serde_json::json;
+    use serde_json::{json, Value};
 
     use crate::persist::event_store::shared_test::{
-        test_serialized_event, MockRepo, TestAggregate, TestEvents, EVENT_VERSION,
+        test_serialized_event, MockRepo, TestAggregate, TestEvents, TestMetadata, EVENT_VERSION,
         TEST_AGGREGATE_ID,
     };
     use crate::persist::{
-        EventStoreAggregateContext, PersistedEventStore, PersistenceError, SerializedSnapshot,
+        Compression, EventSerializer, EventStoreAggregateContext, PersistedEventStore,
+        PersistenceError, PostcardSerializer, SerializationFormat, SerializedSnapshot,
     };
     use crate::{AggregateError, DomainEvent, EventStore};
 
@@ -725,7 +1119,9 @@ pub(crate) mod snapshotted_store_test {
             Ok(vec![test_serialized_event(4, TestEvents::SomethingWasDone)]),
             Ok(Some(SerializedSnapshot {
                 aggregate_id: TEST_AGGREGATE_ID.to_string(),
-                aggregate: serde_json::to_value(TestAggregate {
+                format: SerializationFormat::Json,
+                compression: Compression::None,
+                aggregate: serde_json::to_vec(&TestAggregate {
                     something_happened: 3,
                 })
                 .unwrap(),
@@ -775,7 +1171,7 @@ pub(crate) mod snapshotted_store_test {
             current_snapshot: Some(0),
         };
         let event_envelopes = store
-            .commit(vec![TestEvents::Started], context, HashMap::default())
+            .commit(vec![TestEvents::Started], context, TestMetadata::default())
             .await
             .unwrap();
         assert_eq!(1, event_envelopes.len());
@@ -805,7 +1201,7 @@ pub(crate) mod snapshotted_store_test {
             .commit(
                 vec![TestEvents::SomethingWasDone],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
@@ -815,6 +1211,101 @@ pub(crate) mod snapshotted_store_test {
         assert_eq!(TestEvents::SomethingWasDone, event.payload);
     }
 
+    #[tokio::test]
+    async fn commit_with_serializer() {
+        let repo = MockRepo::with_commit(Box::new(|events, snapshot_update| {
+            let event = &events[0];
+            assert_eq!(SerializationFormat::Postcard, event.format);
+            assert_eq!(
+                TestEvents::SomethingWasDone,
+                PostcardSerializer.deserialize(&event.payload).unwrap()
+            );
+
+            let snapshot_update = snapshot_update.unwrap();
+            assert_eq!(SerializationFormat::Postcard, snapshot_update.format);
+            assert_eq!(2, snapshot_update.current_sequence);
+            assert_eq!(
+                TestAggregate {
+                    something_happened: 1
+                },
+                PostcardSerializer
+                    .deserialize(&snapshot_update.aggregate)
+                    .unwrap()
+            );
+        }));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_snapshot_store(repo, 2)
+            .with_serializer(PostcardSerializer);
+        let context = EventStoreAggregateContext {
+            aggregate_id: TEST_AGGREGATE_ID.to_string(),
+            aggregate: TestAggregate::default(),
+            current_sequence: 1,
+            current_snapshot: Some(0),
+        };
+        store
+            .commit(
+                vec![TestEvents::SomethingWasDone],
+                context,
+                TestMetadata::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn compression_requires_feature() {
+        let repo = MockRepo::with_events(Ok(vec![]));
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_event_store(repo);
+        let result = store.with_compression(Compression::Zstd, 0);
+        assert_eq!(cfg!(feature = "zstd"), result.is_ok());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "lz4")]
+    async fn commit_with_compression() {
+        let repo = MockRepo::with_commit(Box::new(|events, snapshot_update| {
+            assert_eq!(Compression::None, events[0].compression);
+            let event = &events[1];
+            assert_eq!(Compression::Lz4, event.compression);
+            let payload = Compression::Lz4.decompress(&event.payload).unwrap();
+            assert_eq!(
+                TestEvents::SomethingWasDone,
+                serde_json::from_slice::<TestEvents>(&payload).unwrap()
+            );
+
+            let snapshot_update = snapshot_update.unwrap();
+            assert_eq!(Compression::Lz4, snapshot_update.compression);
+            let aggregate = Compression::Lz4
+                .decompress(&snapshot_update.aggregate)
+                .unwrap();
+            assert_eq!(
+                TestAggregate {
+                    something_happened: 1
+                },
+                serde_json::from_slice(&aggregate).unwrap()
+            );
+        }));
+        let threshold = serde_json::to_vec(&TestEvents::SomethingWasDone)
+            .unwrap()
+            .len();
+        let store = PersistedEventStore::<MockRepo, TestAggregate>::new_snapshot_store(repo, 2)
+            .with_compression(Compression::Lz4, threshold)
+            .unwrap();
+        let context = EventStoreAggregateContext {
+            aggregate_id: TEST_AGGREGATE_ID.to_string(),
+            aggregate: TestAggregate::default(),
+            current_sequence: 0,
+            current_snapshot: Some(0),
+        };
+        store
+            .commit(
+                vec![TestEvents::Started, TestEvents::SomethingWasDone],
+                context,
+                TestMetadata::default(),
+            )
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn commit_three_events() {
         let repo = MockRepo::with_commit(Box::new(|events, snapshot_update| {
@@ -824,9 +1315,9 @@ pub(crate) mod snapshotted_store_test {
             assert_eq!(3, event.sequence);
 
             let snapshot_update = snapshot_update.unwrap();
-            let aggregate_id = snapshot_update.0;
-            let aggregate = snapshot_update.1;
-            let snapshot_version = snapshot_update.2;
+            let aggregate_id = snapshot_update.aggregate_id;
+            let aggregate: Value = serde_json::from_slice(&snapshot_update.aggregate).unwrap();
+            let snapshot_version = snapshot_update.current_snapshot;
             assert_eq!(TEST_AGGREGATE_ID, aggregate_id.as_str());
             assert_eq!(1, snapshot_version);
             assert_eq!(
@@ -851,7 +1342,7 @@ pub(crate) mod snapshotted_store_test {
                     TestEvents::SomethingWasDone,
                 ],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
@@ -874,9 +1365,9 @@ pub(crate) mod snapshotted_store_test {
             assert_eq!(3, event.sequence);
 
             let snapshot_update = snapshot_update.unwrap();
-            let aggregate_id = snapshot_update.0;
-            let aggregate = snapshot_update.1;
-            let snapshot_version = snapshot_update.2;
+            let aggregate_id = snapshot_update.aggregate_id;
+            let aggregate: Value = serde_json::from_slice(&snapshot_update.aggregate).unwrap();
+            let snapshot_version = snapshot_update.current_snapshot;
             assert_eq!(TEST_AGGREGATE_ID, aggregate_id.as_str());
             assert_eq!(2, snapshot_version);
             assert_eq!(
@@ -897,7 +1388,7 @@ pub(crate) mod snapshotted_store_test {
             .commit(
                 vec![TestEvents::SomethingWasDone, TestEvents::SomethingWasDone],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
@@ -919,9 +1410,9 @@ pub(crate) mod snapshotted_store_test {
             assert_eq!(5, event.sequence);
 
             let snapshot_update = snapshot_update.unwrap();
-            let aggregate_id = snapshot_update.0;
-            let aggregate = snapshot_update.1;
-            let snapshot_version = snapshot_update.2;
+            let aggregate_id = snapshot_update.aggregate_id;
+            let aggregate: Value = serde_json::from_slice(&snapshot_update.aggregate).unwrap();
+            let snapshot_version = snapshot_update.current_snapshot;
             assert_eq!(TEST_AGGREGATE_ID, aggregate_id.as_str());
             assert_eq!(1, snapshot_version);
             assert_eq!(
@@ -948,7 +1439,7 @@ pub(crate) mod snapshotted_store_test {
                     TestEvents::SomethingWasDone,
                 ],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
@@ -964,16 +1455,16 @@ pub(crate) mod snapshotted_store_test {
 
 #[cfg(test)]
 pub(crate) mod aggregate_store_test {
-    use std::collections::HashMap;
 
-    use serde_json::json;
+    use serde_json::{json, Value};
 
     use crate::persist::event_store::shared_test::{
-        test_serialized_event, MockRepo, TestAggregate, TestEvents, EVENT_VERSION,
+        test_serialized_event, MockRepo, TestAggregate, TestEvents, TestMetadata, EVENT_VERSION,
         TEST_AGGREGATE_ID,
     };
     use crate::persist::{
-        EventStoreAggregateContext, PersistedEventStore, PersistenceError, SerializedSnapshot,
+        Compression, EventStoreAggregateContext, PersistedEventStore, PersistenceError,
+        SerializationFormat, SerializedSnapshot,
     };
     use crate::{AggregateError, DomainEvent, EventStore};
 
@@ -1017,7 +1508,9 @@ pub(crate) mod aggregate_store_test {
     async fn load_aggregate_existing() {
         let repo = MockRepo::with_snapshot(Ok(Some(SerializedSnapshot {
             aggregate_id: TEST_AGGREGATE_ID.to_string(),
-            aggregate: serde_json::to_value(TestAggregate {
+            format: SerializationFormat::Json,
+            compression: Compression::None,
+            aggregate: serde_json::to_vec(&TestAggregate {
                 something_happened: 3,
             })
             .unwrap(),
@@ -1057,9 +1550,9 @@ pub(crate) mod aggregate_store_test {
             assert_eq!(3, event.sequence);
 
             let snapshot_update = snapshot_update.unwrap();
-            let aggregate_id = snapshot_update.0;
-            let aggregate = snapshot_update.1;
-            let snapshot_version = snapshot_update.2;
+            let aggregate_id = snapshot_update.aggregate_id;
+            let aggregate: Value = serde_json::from_slice(&snapshot_update.aggregate).unwrap();
+            let snapshot_version = snapshot_update.current_snapshot;
             assert_eq!(TEST_AGGREGATE_ID, aggregate_id.as_str());
             assert_eq!(1, snapshot_version);
             assert_eq!(
@@ -1084,7 +1577,7 @@ pub(crate) mod aggregate_store_test {
                     TestEvents::SomethingWasDone,
                 ],
                 context,
-                HashMap::default(),
+                TestMetadata::default(),
             )
             .await
             .unwrap();
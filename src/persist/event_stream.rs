@@ -1,30 +1,344 @@
-use crate::persist::{PersistenceError, SerializedEvent};
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::Stream;
+
+use crate::persist::encryption::FieldEncryption;
+use crate::persist::serialized_event::deserialize_event;
+use crate::persist::upcaster::upcast_event;
+use crate::persist::{
+    EncryptedFields, EventFilter, EventSerializer, EventUpcaster, KeyStore, PersistenceError,
+    SerializedEvent,
+};
 use crate::{Aggregate, EventEnvelope};
 use tokio::sync::mpsc::{Receiver, Sender};
 /// Accesses a domain event stream for a particular aggregate.
 ///
+/// Events are buffered up to the queue size, beyond which the repository waits for the
+/// consumer, so a slow consumer holds back the reading of events rather than accumulating them.
+///
 /// _Note: design expected to change after [implemention of RFC 2996](https://github.com/rust-lang/rust/issues/79024)._
 pub struct ReplayStream {
     queue: Receiver<Result<SerializedEvent, PersistenceError>>,
+    upcasters: Arc<Vec<Box<dyn EventUpcaster>>>,
+    /// Upcasted events not yet returned, where an upcaster split a single event.
+    pending: VecDeque<SerializedEvent>,
+    /// Selects the events returned, where the repository could not select them itself.
+    filter: Option<EventFilter>,
+    encryption: Option<Arc<FieldEncryption>>,
+    /// The event being decrypted, held in a `Mutex` only so that the stream remains `Sync`.
+    decrypting: Option<Mutex<Decrypting>>,
 }
 
+type Decrypting =
+    Pin<Box<dyn Future<Output = Result<Vec<SerializedEvent>, PersistenceError>> + Send>>;
+
 impl ReplayStream {
     /// Creates a new `ReplayStream` that will buffer events up to the `queue_size`.
     pub fn new(queue_size: usize) -> (ReplayFeed, Self) {
         let (sender, queue) = tokio::sync::mpsc::channel(queue_size);
-        (ReplayFeed { sender }, Self { queue })
+        let stream = Self {
+            queue,
+            upcasters: Default::default(),
+            pending: VecDeque::new(),
+            filter: None,
+            encryption: None,
+            decrypting: None,
+        };
+        (ReplayFeed { sender }, stream)
+    }
+
+    /// Configures the stream to upcast events as they are received, in the same manner as
+    /// `PersistedEventStore::with_upcasters`.
+    pub fn with_upcasters(self, upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        self.with_shared_upcasters(Arc::new(upcasters))
+    }
+
+    /// Configures the stream with upcasters shared with other streams, as when a `QueryReplay`
+    /// opens a stream for each replay.
+    pub(crate) fn with_shared_upcasters(self, upcasters: Arc<Vec<Box<dyn EventUpcaster>>>) -> Self {
+        Self { upcasters, ..self }
+    }
+
+    /// Configures the stream to decrypt the marked fields of events as they are received, in the
+    /// same manner as `PersistedEventStore::with_encryption`. Fields whose subject's key has
+    /// been deleted are replaced with the placeholder.
+    pub fn with_encryption(
+        self,
+        key_store: Arc<dyn KeyStore>,
+        fields: Vec<EncryptedFields>,
+    ) -> Self {
+        self.with_shared_encryption(Arc::new(FieldEncryption::new(key_store, fields)))
+    }
+
+    /// Configures the stream with the field encryption shared with other streams, as when a
+    /// `QueryReplay` opens a stream for each replay.
+    pub(crate) fn with_shared_encryption(self, encryption: Arc<FieldEncryption>) -> Self {
+        Self {
+            encryption: Some(encryption),
+            ..self
+        }
+    }
+
+    /// Configures the stream to skip events not selected by the filter, for repositories that
+    /// stream all events of the aggregate type to be filtered.
+    pub(crate) fn with_filter(self, filter: EventFilter) -> Self {
+        Self {
+            filter: Some(filter),
+            ..self
+        }
     }
 
     /// Receive the next event or error in the stream, if no event is available this will block.
+    ///
+    /// Events dropped by an upcaster are skipped, events split by an upcaster are returned in
+    /// turn.
     pub async fn next<A: Aggregate>(
         &mut self,
     ) -> Option<Result<EventEnvelope<A>, PersistenceError>> {
-        self.queue.recv().await.map(|result| match result {
-            Ok(event) => match TryInto::try_into(event) {
-                Ok(event) => Ok(event),
-                Err(err) => Err(err),
-            },
-            Err(err) => Err(err),
+        self.next_deserialized(None).await
+    }
+
+    /// Receive the next event as with `next`, deserialized with the serializer configured with
+    /// `PersistedEventStore::with_serializer`. JSON and postcard payloads are read without it,
+    /// rkyv payloads require an `RkyvSerializer`.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// use actuality::persist::{PostcardSerializer, ReplayStream};
+    ///
+    /// async fn replay(mut stream: ReplayStream) {
+    ///     while let Some(event) = stream.next_with::<MyAggregate>(&PostcardSerializer).await {
+    ///         println!("event {} received", event.unwrap().sequence);
+    ///     }
+    /// }
+    /// ```
+    pub async fn next_with<A: Aggregate>(
+        &mut self,
+        serializer: &dyn EventSerializer<A::Event>,
+    ) -> Option<Result<EventEnvelope<A>, PersistenceError>> {
+        self.next_deserialized(Some(serializer)).await
+    }
+
+    /// Receive the next event, deserialized with the serializer if any.
+    pub(crate) async fn next_deserialized<A: Aggregate>(
+        &mut self,
+        serializer: Option<&dyn EventSerializer<A::Event>>,
+    ) -> Option<Result<EventEnvelope<A>, PersistenceError>> {
+        let event = self.next_serialized().await?;
+        Some(event.and_then(|event| deserialize_event(event, serializer)))
+    }
+
+    /// Receive up to `max` events, waiting only for the first, the remainder being those
+    /// already buffered. An empty batch marks the end of the stream.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::ReplayStream;
+    /// async fn replay(mut stream: ReplayStream) {
+    ///     loop {
+    ///         let batch = stream.next_batch::<MyAggregate>(100).await;
+    ///         if batch.is_empty() {
+    ///             break;
+    ///         }
+    ///         println!("{} events received", batch.len());
+    ///     }
+    /// }
+    /// ```
+    pub async fn next_batch<A: Aggregate>(
+        &mut self,
+        max: usize,
+    ) -> Vec<Result<EventEnvelope<A>, PersistenceError>> {
+        self.batch(max, None).await
+    }
+
+    /// Receive up to `max` events as with `next_batch`, deserialized with the serializer as
+    /// with `next_with`.
+    pub async fn next_batch_with<A: Aggregate>(
+        &mut self,
+        max: usize,
+        serializer: &dyn EventSerializer<A::Event>,
+    ) -> Vec<Result<EventEnvelope<A>, PersistenceError>> {
+        self.batch(max, Some(serializer)).await
+    }
+
+    async fn batch<A: Aggregate>(
+        &mut self,
+        max: usize,
+        serializer: Option<&dyn EventSerializer<A::Event>>,
+    ) -> Vec<Result<EventEnvelope<A>, PersistenceError>> {
+        let mut batch = Vec::new();
+        if max == 0 {
+            return batch;
+        }
+        match self.next_deserialized::<A>(serializer).await {
+            Some(event) => batch.push(event),
+            None => return batch,
+        }
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        while batch.len() < max {
+            match self.poll_next_serialized(&mut cx) {
+                Poll::Ready(Some(event)) => {
+                    batch.push(event.and_then(|event| deserialize_event(event, serializer)))
+                }
+                Poll::Ready(None) | Poll::Pending => break,
+            }
+        }
+        batch
+    }
+
+    /// The number of events received from the repository and not yet returned.
+    pub fn buffered(&self) -> usize {
+        self.queue.len() + self.pending.len()
+    }
+
+    /// Converts the stream into a `futures::Stream` of events, for use with the combinators of
+    /// `StreamExt`, e.g., `chunks` or `buffer_unordered`.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::ReplayStream;
+    /// use futures::StreamExt;
+    ///
+    /// async fn replay(stream: ReplayStream) {
+    ///     let mut batches = stream.into_envelopes::<MyAggregate>().chunks(100);
+    ///     while let Some(batch) = batches.next().await {
+    ///         println!("{} events received", batch.len());
+    ///     }
+    /// }
+    /// ```
+    pub fn into_envelopes<A: Aggregate>(self) -> EnvelopeStream<A> {
+        EnvelopeStream {
+            stream: self,
+            serializer: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Converts the stream into a `futures::Stream` of events as with `into_envelopes`,
+    /// deserialized with the serializer as with `next_with`.
+    pub fn into_envelopes_with<A, S>(self, serializer: S) -> EnvelopeStream<A>
+    where
+        A: Aggregate,
+        S: EventSerializer<A::Event> + 'static,
+    {
+        EnvelopeStream {
+            stream: self,
+            serializer: Some(Box::new(serializer)),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Receive the next upcasted event without deserializing it.
+    pub(crate) async fn next_serialized(
+        &mut self,
+    ) -> Option<Result<SerializedEvent, PersistenceError>> {
+        poll_fn(|cx| self.poll_next_serialized(cx)).await
+    }
+
+    fn poll_next_serialized(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<SerializedEvent, PersistenceError>>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if let Some(decrypting) = &mut self.decrypting {
+                let result = match decrypting.get_mut().unwrap().as_mut().poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                };
+                self.decrypting = None;
+                match result.and_then(|events| self.upcast_events(events)) {
+                    Ok(events) => self.pending.extend(events),
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                }
+                continue;
+            }
+            let event = match self.queue.poll_recv(cx) {
+                Poll::Ready(Some(Ok(event))) => event,
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if self
+                .filter
+                .as_ref()
+                .is_some_and(|filter| !filter.matches(&event))
+            {
+                continue;
+            }
+            if let Some(encryption) = &self.encryption {
+                let event = match event.decompress() {
+                    Ok(event) => event,
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                };
+                let encryption = encryption.clone();
+                self.decrypting = Some(Mutex::new(Box::pin(async move {
+                    encryption.decrypt_events(vec![event]).await
+                })));
+                continue;
+            }
+            match self.upcast(event) {
+                Ok(events) => self.pending.extend(events),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        if self.upcasters.is_empty() {
+            return Ok(vec![event]);
+        }
+        upcast_event(event.decompress()?, &self.upcasters)
+    }
+
+    fn upcast_events(
+        &self,
+        events: Vec<SerializedEvent>,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut result = Vec::with_capacity(events.len());
+        for event in events {
+            result.extend(self.upcast(event)?);
+        }
+        Ok(result)
+    }
+}
+
+/// A `ReplayStream` deserializing events as a `futures::Stream`, created by
+/// `ReplayStream::into_envelopes`.
+pub struct EnvelopeStream<A: Aggregate> {
+    stream: ReplayStream,
+    serializer: Option<Box<dyn EventSerializer<A::Event>>>,
+    _phantom: PhantomData<fn() -> A>,
+}
+
+impl<A: Aggregate> EnvelopeStream<A> {
+    /// The number of events received from the repository and not yet returned.
+    pub fn buffered(&self) -> usize {
+        self.stream.buffered()
+    }
+
+    /// Returns the underlying `ReplayStream`.
+    pub fn into_inner(self) -> ReplayStream {
+        self.stream
+    }
+}
+
+impl<A: Aggregate> Stream for EnvelopeStream<A> {
+    type Item = Result<EventEnvelope<A>, PersistenceError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let serializer = this.serializer.as_deref();
+        this.stream.poll_next_serialized(cx).map(|event| {
+            event.map(|event| event.and_then(|event| deserialize_event(event, serializer)))
         })
     }
 }
@@ -46,3 +360,179 @@ impl ReplayFeed {
 }
 #[test]
 fn test() {}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use crate::persist::encryption::FieldEncryption;
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, TestAggregate, TestEvents, TEST_AGGREGATE_ID,
+    };
+    use crate::persist::{
+        DropEventUpcaster, EncryptedFields, EventSerializer, EventUpcaster, InMemoryKeyStore,
+        KeyStore, PersistenceError, ReplayStream, RkyvSerializer, SerializationFormat,
+        SerializedEvent,
+    };
+
+    struct RepeatUpcaster;
+
+    impl EventUpcaster for RepeatUpcaster {
+        fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+            event_type == "SomethingWasDone"
+        }
+
+        fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+            Ok(vec![event.clone(), event])
+        }
+    }
+
+    #[tokio::test]
+    async fn upcast_stream() {
+        let (mut feed, stream) = ReplayStream::new(10);
+        let mut stream = stream.with_upcasters(vec![
+            Box::new(DropEventUpcaster::new("Started")),
+            Box::new(RepeatUpcaster),
+        ]);
+        feed.push(Ok(test_serialized_event(1, TestEvents::Started)))
+            .await
+            .unwrap();
+        feed.push(Ok(test_serialized_event(2, TestEvents::SomethingWasDone)))
+            .await
+            .unwrap();
+        drop(feed);
+
+        for _ in 0..2 {
+            let event = stream.next::<TestAggregate>().await.unwrap().unwrap();
+            assert_eq!(TestEvents::SomethingWasDone, event.payload);
+            assert_eq!(2, event.sequence);
+        }
+        assert!(stream.next::<TestAggregate>().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn batches() {
+        let (mut feed, mut stream) = ReplayStream::new(10);
+        for sequence in 1..=5 {
+            feed.push(Ok(test_serialized_event(
+                sequence,
+                TestEvents::SomethingWasDone,
+            )))
+            .await
+            .unwrap();
+        }
+        assert_eq!(5, stream.buffered());
+
+        let batch = stream.next_batch::<TestAggregate>(3).await;
+        let sequences: Vec<usize> = batch.into_iter().map(|e| e.unwrap().sequence).collect();
+        assert_eq!(vec![1, 2, 3], sequences);
+        assert_eq!(2, stream.buffered());
+
+        // only buffered events are taken beyond the first
+        let batch = stream.next_batch::<TestAggregate>(3).await;
+        assert_eq!(2, batch.len());
+        drop(feed);
+        assert!(stream.next_batch::<TestAggregate>(3).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn envelope_stream() {
+        let (mut feed, stream) = ReplayStream::new(10);
+        let consumer = tokio::spawn(async move {
+            stream
+                .into_envelopes::<TestAggregate>()
+                .map(|event| event.unwrap().sequence)
+                .chunks(2)
+                .collect::<Vec<_>>()
+                .await
+        });
+        for sequence in 1..=5 {
+            feed.push(Ok(test_serialized_event(
+                sequence,
+                TestEvents::SomethingWasDone,
+            )))
+            .await
+            .unwrap();
+        }
+        drop(feed);
+        assert_eq!(
+            vec![vec![1, 2], vec![3, 4], vec![5]],
+            consumer.await.unwrap()
+        );
+    }
+
+    fn rkyv_event(sequence: usize) -> SerializedEvent {
+        let event = test_serialized_event(sequence, TestEvents::SomethingWasDone);
+        SerializedEvent {
+            payload: RkyvSerializer
+                .serialize(&TestEvents::SomethingWasDone)
+                .unwrap(),
+            ..event.with_format(SerializationFormat::Rkyv)
+        }
+    }
+
+    #[tokio::test]
+    async fn rkyv_stream() {
+        let (mut feed, mut stream) = ReplayStream::new(10);
+        for sequence in 1..=3 {
+            feed.push(Ok(rkyv_event(sequence))).await.unwrap();
+        }
+        drop(feed);
+
+        let event = stream
+            .next_with::<TestAggregate>(&RkyvSerializer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(TestEvents::SomethingWasDone, event.payload);
+        let batch = stream
+            .next_batch_with::<TestAggregate>(1, &RkyvSerializer)
+            .await;
+        assert_eq!(2, batch[0].as_ref().unwrap().sequence);
+        let events: Vec<_> = stream
+            .into_envelopes_with::<TestAggregate, _>(RkyvSerializer)
+            .collect()
+            .await;
+        assert_eq!(3, events[0].as_ref().unwrap().sequence);
+
+        // rkyv payloads cannot be read without the serializer
+        let (mut feed, mut stream) = ReplayStream::new(10);
+        feed.push(Ok(rkyv_event(1))).await.unwrap();
+        let result = stream.next::<TestAggregate>().await.unwrap();
+        assert!(matches!(
+            result,
+            Err(PersistenceError::DeserializationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn encrypted_stream() {
+        let fields = || {
+            vec![EncryptedFields::new("SomethingWasDone", &[""]).with_placeholder(json!("Started"))]
+        };
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let events = FieldEncryption::new(key_store.clone(), fields())
+            .encrypt_events(vec![
+                test_serialized_event(1, TestEvents::SomethingWasDone),
+                test_serialized_event(2, TestEvents::SomethingWasDone),
+            ])
+            .await
+            .unwrap();
+
+        let (mut feed, stream) = ReplayStream::new(10);
+        let mut stream = stream.with_encryption(key_store.clone(), fields());
+        feed.push(Ok(events[0].clone())).await.unwrap();
+        let event = stream.next::<TestAggregate>().await.unwrap().unwrap();
+        assert_eq!(TestEvents::SomethingWasDone, event.payload);
+
+        // once the key is deleted the placeholder is returned
+        key_store.delete_key(TEST_AGGREGATE_ID).await.unwrap();
+        feed.push(Ok(events[1].clone())).await.unwrap();
+        drop(feed);
+        let events: Vec<_> = stream.into_envelopes::<TestAggregate>().collect().await;
+        assert_eq!(TestEvents::Started, events[0].as_ref().unwrap().payload);
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::persist::event_stream::ReplayStream;
+use crate::persist::{
+    PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot,
+};
+use crate::Aggregate;
+
+use record::SnapshotRecord;
+use segment::{encode_file_name, EventLog, LogSnapshot, RecordLocation};
+
+mod record;
+mod segment;
+
+const DEFAULT_MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+const STREAM_QUEUE_SIZE: usize = 100;
+
+/// Controls when appended events are flushed from the operating system cache to disk.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FsyncPolicy {
+    /// Every commit is synced before it is acknowledged, no acknowledged commit is lost on a
+    /// crash or power failure.
+    #[default]
+    Always,
+    /// Sync after the given number of commits, a power failure may lose up to that many
+    /// acknowledged commits.
+    EveryCommits(usize),
+    /// Leave flushing to the operating system, acknowledged commits survive a process crash
+    /// but may be lost on power failure.
+    Never,
+}
+
+/// A durable, file based `PersistedEventRepository` for deployments without a database.
+///
+/// Each aggregate type is stored in its own directory below the root directory, containing:
+/// - append-only segment files holding length-prefixed, checksummed event records
+/// - a `snapshots` directory holding the latest snapshot of each aggregate instance
+///
+/// An index of the events for each aggregate instance is built when an aggregate type is first
+/// accessed, at the same time any torn or unacknowledged writes left at the end of the segments
+/// by a crash are truncated.
+///
+/// ```
+/// # use actuality::doc::setup::{MyAggregate, MyService};
+/// use actuality::Cqrs;
+/// use actuality::persist::{FileEventRepository, FsyncPolicy, PersistedEventStore};
+///
+/// # fn config() {
+/// let repo = FileEventRepository::new("/var/lib/my-service/events")
+///     .with_fsync_policy(FsyncPolicy::EveryCommits(10));
+/// let store = PersistedEventStore::<FileEventRepository, MyAggregate>::new_snapshot_store(repo, 100);
+/// let cqrs = Cqrs::new(store, vec![], MyService);
+/// # }
+/// ```
+pub struct FileEventRepository {
+    directory: PathBuf,
+    fsync_policy: FsyncPolicy,
+    max_segment_size: u64,
+    logs: Mutex<HashMap<String, Arc<Mutex<Option<EventLog>>>>>,
+}
+
+impl FileEventRepository {
+    /// Creates a new `FileEventRepository` storing events below the provided directory, which
+    /// will be created if it does not exist.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            fsync_policy: FsyncPolicy::default(),
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            logs: Default::default(),
+        }
+    }
+
+    /// Configures when committed events are synced to disk, defaults to `FsyncPolicy::Always`.
+    pub fn with_fsync_policy(self, fsync_policy: FsyncPolicy) -> Self {
+        Self {
+            fsync_policy,
+            ..self
+        }
+    }
+
+    /// Configures the size in bytes after which a new segment file is started,
+    /// defaults to 64 MiB.
+    pub fn with_max_segment_size(self, max_segment_size: u64) -> Self {
+        Self {
+            max_segment_size,
+            ..self
+        }
+    }
+
+    /// Runs the function against the log of the aggregate type from a blocking task, opening
+    /// the log on first use. A log is reopened, and so recovered from its segments, after any
+    /// I/O error.
+    async fn with_log<A, T, F>(&self, f: F) -> Result<T, PersistenceError>
+    where
+        A: Aggregate,
+        T: Send + 'static,
+        F: FnOnce(&mut EventLog) -> Result<T, PersistenceError> + Send + 'static,
+    {
+        let aggregate_type = A::aggregate_type();
+        let directory = self.directory.join(encode_file_name(&aggregate_type));
+        // uninteresting unwrap: the map is never locked across a panic
+        let log = self
+            .logs
+            .lock()
+            .unwrap()
+            .entry(aggregate_type)
+            .or_default()
+            .clone();
+        tokio::task::spawn_blocking(move || {
+            // uninteresting unwrap: a poisoned lock means a panic occurred while appending
+            let mut log = log.lock().unwrap();
+            let opened = match log.as_mut() {
+                Some(opened) => opened,
+                None => log.insert(EventLog::open(directory)?),
+            };
+            let result = f(opened);
+            if matches!(result, Err(PersistenceError::UnknownError(_))) {
+                *log = None;
+            }
+            result
+        })
+        .await
+        .map_err(|err| PersistenceError::UnknownError(Box::new(err)))?
+    }
+
+    /// Reads the events at the provided locations, or all events if none are provided, into a
+    /// `ReplayStream` from a blocking task.
+    fn stream(snapshot: LogSnapshot, locations: Option<Vec<RecordLocation>>) -> ReplayStream {
+        let (mut feed, stream) = ReplayStream::new(STREAM_QUEUE_SIZE);
+        let handle = tokio::runtime::Handle::current();
+        tokio::task::spawn_blocking(move || {
+            let mut open = true;
+            let mut push = |event| {
+                open = handle.block_on(feed.push(Ok(event))).is_ok();
+                open
+            };
+            let result = match locations {
+                None => snapshot.for_each(&mut push),
+                Some(locations) => snapshot.for_each_location(&locations, &mut push),
+            };
+            if let Err(err) = result {
+                if open {
+                    let _ = handle.block_on(feed.push(Err(err)));
+                }
+            }
+        });
+        stream
+    }
+}
+
+#[async_trait]
+impl PersistedEventRepository for FileEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.get_last_events::<A>(aggregate_id, 0).await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let aggregate_id = aggregate_id.to_string();
+        let (snapshot, locations) = self
+            .with_log::<A, _, _>(move |log| {
+                Ok((log.snapshot(), log.locations(&aggregate_id, last_sequence)))
+            })
+            .await?;
+        tokio::task::spawn_blocking(move || snapshot.read_locations(&locations))
+            .await
+            .map_err(|err| PersistenceError::UnknownError(Box::new(err)))?
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let aggregate_id = aggregate_id.to_string();
+        self.with_log::<A, _, _>(move |log| log.get_snapshot(&aggregate_id))
+            .await
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<SerializedSnapshot>,
+    ) -> Result<(), PersistenceError> {
+        let events = events.to_vec();
+        let max_segment_size = self.max_segment_size;
+        let fsync_policy = self.fsync_policy.clone();
+        self.with_log::<A, _, _>(move |log| {
+            let snapshot = snapshot_update.map(SnapshotRecord::from);
+            if let Some(snapshot) = &snapshot {
+                log.check_snapshot(snapshot)?;
+            }
+            if !events.is_empty() {
+                log.append(&events, max_segment_size, &fsync_policy)?;
+            }
+            if let Some(snapshot) = snapshot {
+                log.put_snapshot(snapshot, &fsync_policy)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let aggregate_id = aggregate_id.to_string();
+        let (snapshot, locations) = self
+            .with_log::<A, _, _>(move |log| Ok((log.snapshot(), log.locations(&aggregate_id, 0))))
+            .await?;
+        Ok(Self::stream(snapshot, Some(locations)))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let snapshot = self.with_log::<A, _, _>(|log| Ok(log.snapshot())).await?;
+        Ok(Self::stream(snapshot, None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, test_serialized_snapshot, TestAggregate, TestDirectory, TestEvents,
+        TEST_AGGREGATE_ID,
+    };
+    use crate::persist::{
+        Compression, FileEventRepository, FsyncPolicy, PersistedEventRepository, PersistenceError,
+        SerializationFormat, SerializedEvent, SerializedSnapshot,
+    };
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        let mut event = test_serialized_event(sequence, TestEvents::SomethingWasDone);
+        event.aggregate_id = aggregate_id.to_string();
+        event
+    }
+
+    fn file_len(path: &Path) -> u64 {
+        fs::metadata(path).unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn persist_and_load() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1), event("other", 1)], None)
+            .await
+            .unwrap();
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 2), event(TEST_AGGREGATE_ID, 3)],
+            None,
+        )
+        .await
+        .unwrap();
+
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![
+                event(TEST_AGGREGATE_ID, 1),
+                event(TEST_AGGREGATE_ID, 2),
+                event(TEST_AGGREGATE_ID, 3)
+            ],
+            events
+        );
+        let events = repo
+            .get_last_events::<TestAggregate>(TEST_AGGREGATE_ID, 2)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 3)], events);
+
+        // reopening rebuilds the index from the segments
+        let repo = FileEventRepository::new(&directory.0);
+        let events = repo.get_events::<TestAggregate>("other").await.unwrap();
+        assert_eq!(vec![event("other", 1)], events);
+    }
+
+    #[tokio::test]
+    async fn binary_payloads() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0);
+        let event = event(TEST_AGGREGATE_ID, 1)
+            .with_format(SerializationFormat::Postcard)
+            .with_compression(Compression::Zstd);
+        let event = SerializedEvent {
+            payload: vec![0, 159, 146, 150],
+            ..event
+        };
+        let snapshot = || SerializedSnapshot {
+            compression: Compression::Lz4,
+            aggregate: vec![0, 159, 146, 150],
+            ..test_serialized_snapshot(TEST_AGGREGATE_ID, 1, 1, 1)
+        };
+        repo.persist::<TestAggregate>(std::slice::from_ref(&event), Some(snapshot()))
+            .await
+            .unwrap();
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event], events);
+        let stored = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(Some(snapshot()), stored);
+    }
+
+    #[tokio::test]
+    async fn optimistic_lock() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0).with_fsync_policy(FsyncPolicy::Never);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let result = repo
+            .persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 3)], None)
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(1, events.len());
+    }
+
+    #[tokio::test]
+    async fn recover_torn_write() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+        let committed_len = file_len(&directory.segment(1));
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 2), event(TEST_AGGREGATE_ID, 3)],
+            None,
+        )
+        .await
+        .unwrap();
+        drop(repo);
+
+        // simulate a crash part way through writing the second commit
+        let segment = directory.segment(1);
+        let file = OpenOptions::new().write(true).open(&segment).unwrap();
+        file.set_len(file_len(&segment) - 3).unwrap();
+        drop(file);
+
+        let repo = FileEventRepository::new(&directory.0);
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 1)], events);
+        assert_eq!(committed_len, file_len(&segment));
+
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 2)], None)
+            .await
+            .unwrap();
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(2, events.len());
+    }
+
+    #[tokio::test]
+    async fn recover_corrupted_tail() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+        drop(repo);
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(directory.segment(1))
+            .unwrap();
+        file.write_all(&[12, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12])
+            .unwrap();
+        drop(file);
+
+        let repo = FileEventRepository::new(&directory.0);
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 1)], events);
+    }
+
+    #[tokio::test]
+    async fn segment_rollover_and_stream_all() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0).with_max_segment_size(1);
+        for sequence in 1..=3 {
+            repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, sequence)], None)
+                .await
+                .unwrap();
+            repo.persist::<TestAggregate>(&[event("other", sequence)], None)
+                .await
+                .unwrap();
+        }
+        assert!(directory.segment(6).exists());
+
+        let repo = FileEventRepository::new(&directory.0);
+        let mut stream = repo.stream_all_events::<TestAggregate>().await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            let event = event.unwrap();
+            found.push((event.aggregate_id, event.sequence));
+        }
+        assert_eq!(
+            vec![
+                (TEST_AGGREGATE_ID.to_string(), 1),
+                ("other".to_string(), 1),
+                (TEST_AGGREGATE_ID.to_string(), 2),
+                ("other".to_string(), 2),
+                (TEST_AGGREGATE_ID.to_string(), 3),
+                ("other".to_string(), 3),
+            ],
+            found
+        );
+
+        let mut stream = repo.stream_events::<TestAggregate>("other").await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            found.push(event.unwrap().sequence);
+        }
+        assert_eq!(vec![1, 2, 3], found);
+    }
+
+    #[tokio::test]
+    async fn snapshots() {
+        let directory = TestDirectory::new();
+        let repo = FileEventRepository::new(&directory.0);
+        let aggregate_id = "snapshot/../aggregate";
+        assert_eq!(
+            None,
+            repo.get_snapshot::<TestAggregate>(aggregate_id)
+                .await
+                .unwrap()
+        );
+
+        repo.persist::<TestAggregate>(
+            &[event(aggregate_id, 1), event(aggregate_id, 2)],
+            Some(test_serialized_snapshot(aggregate_id, 2, 2, 1)),
+        )
+        .await
+        .unwrap();
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(aggregate_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(aggregate_id, snapshot.aggregate_id);
+        assert_eq!(
+            serde_json::to_vec(&json!({"something_happened": 2})).unwrap(),
+            snapshot.aggregate
+        );
+        assert_eq!(2, snapshot.current_sequence);
+        assert_eq!(1, snapshot.current_snapshot);
+
+        repo.persist::<TestAggregate>(&[], Some(test_serialized_snapshot(aggregate_id, 3, 2, 2)))
+            .await
+            .unwrap();
+        let repo = FileEventRepository::new(&directory.0);
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(aggregate_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_vec(&json!({"something_happened": 3})).unwrap(),
+            snapshot.aggregate
+        );
+        assert_eq!(2, snapshot.current_sequence);
+        assert_eq!(2, snapshot.current_snapshot);
+
+        // a stale snapshot version is rejected, along with the events committed with it
+        let result = repo
+            .persist::<TestAggregate>(
+                &[event(aggregate_id, 3)],
+                Some(test_serialized_snapshot(aggregate_id, 3, 3, 2)),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let result = repo
+            .persist::<TestAggregate>(&[], Some(test_serialized_snapshot(aggregate_id, 3, 3, 4)))
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let events = repo
+            .get_events::<TestAggregate>(aggregate_id)
+            .await
+            .unwrap();
+        assert_eq!(2, events.len());
+
+        repo.persist::<TestAggregate>(
+            &[event(aggregate_id, 3)],
+            Some(test_serialized_snapshot(aggregate_id, 3, 3, 3)),
+        )
+        .await
+        .unwrap();
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(aggregate_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(3, snapshot.current_sequence);
+        assert_eq!(3, snapshot.current_snapshot);
+    }
+}
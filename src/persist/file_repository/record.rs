@@ -0,0 +1,219 @@
+use std::convert::TryFrom;
+use std::io::{self, Read};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::persist::{
+    Compression, PersistenceError, SerializationFormat, SerializedEvent, SerializedSnapshot,
+};
+
+/// Size of the length and checksum header that precedes every record body.
+pub(crate) const HEADER_LEN: usize = 8;
+
+/// The on-disk form of a `SerializedEvent`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EventRecord {
+    pub(crate) aggregate_id: String,
+    pub(crate) sequence: usize,
+    pub(crate) aggregate_type: String,
+    pub(crate) event_type: String,
+    pub(crate) event_version: String,
+    pub(crate) format: SerializationFormat,
+    pub(crate) compression: Compression,
+    pub(crate) payload: Vec<u8>,
+    /// The metadata as JSON, postcard being unable to hold an arbitrary `Value`.
+    pub(crate) metadata: String,
+    /// Set on the last event of each commit. Events following the final marker in a segment
+    /// were never acknowledged and are discarded during recovery.
+    pub(crate) commit: bool,
+}
+
+impl EventRecord {
+    pub(crate) fn new(event: &SerializedEvent, commit: bool) -> Result<Self, PersistenceError> {
+        Ok(Self {
+            aggregate_id: event.aggregate_id.clone(),
+            sequence: event.sequence,
+            aggregate_type: event.aggregate_type.clone(),
+            event_type: event.event_type.clone(),
+            event_version: event.event_version.clone(),
+            format: event.format,
+            compression: event.compression,
+            payload: event.payload.clone(),
+            metadata: serde_json::to_string(&event.metadata)?,
+            commit,
+        })
+    }
+}
+
+impl TryFrom<EventRecord> for SerializedEvent {
+    type Error = PersistenceError;
+
+    fn try_from(record: EventRecord) -> Result<Self, Self::Error> {
+        Ok(SerializedEvent::new(
+            record.aggregate_id,
+            record.sequence,
+            record.aggregate_type,
+            record.event_type,
+            record.event_version,
+            record.payload,
+            serde_json::from_str(&record.metadata)?,
+        )
+        .with_format(record.format)
+        .with_compression(record.compression))
+    }
+}
+
+/// The on-disk form of a `SerializedSnapshot`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SnapshotRecord {
+    pub(crate) aggregate_id: String,
+    pub(crate) format: SerializationFormat,
+    pub(crate) compression: Compression,
+    pub(crate) aggregate: Vec<u8>,
+    pub(crate) current_sequence: usize,
+    pub(crate) current_snapshot: usize,
+}
+
+impl From<SerializedSnapshot> for SnapshotRecord {
+    fn from(snapshot: SerializedSnapshot) -> Self {
+        SnapshotRecord {
+            aggregate_id: snapshot.aggregate_id,
+            format: snapshot.format,
+            compression: snapshot.compression,
+            aggregate: snapshot.aggregate,
+            current_sequence: snapshot.current_sequence,
+            current_snapshot: snapshot.current_snapshot,
+        }
+    }
+}
+
+impl From<SnapshotRecord> for SerializedSnapshot {
+    fn from(record: SnapshotRecord) -> Self {
+        SerializedSnapshot {
+            aggregate_id: record.aggregate_id,
+            format: record.format,
+            compression: record.compression,
+            aggregate: record.aggregate,
+            current_sequence: record.current_sequence,
+            current_snapshot: record.current_snapshot,
+        }
+    }
+}
+
+/// The result of reading a single record from a file.
+pub(crate) enum Frame {
+    /// A complete record body whose checksum matched.
+    Complete(Vec<u8>),
+    /// A partially written record or one whose checksum did not match.
+    Torn,
+    /// No further records are available.
+    End,
+}
+
+/// Frames a record as `[body length: u32][crc32 of body: u32][body]`, little endian, with the
+/// body encoded as postcard.
+pub(crate) fn encode<T: Serialize>(record: &T) -> Result<Vec<u8>, PersistenceError> {
+    let body = postcard::to_allocvec(record)
+        .map_err(|err| PersistenceError::UnknownError(Box::new(err)))?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    frame.extend_from_slice(&body);
+    Ok(frame)
+}
+
+pub(crate) fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, PersistenceError> {
+    postcard::from_bytes(body).map_err(|err| PersistenceError::DeserializationError(Box::new(err)))
+}
+
+/// Reads the next framed record, verifying its checksum. A record claiming to be longer than
+/// the `remaining` bytes of the file is torn, its body is never allocated.
+pub(crate) fn read_frame<R: Read>(reader: &mut R, remaining: u64) -> io::Result<Frame> {
+    let mut header = [0u8; HEADER_LEN];
+    match read_fully(reader, &mut header)? {
+        0 => return Ok(Frame::End),
+        HEADER_LEN => {}
+        _ => return Ok(Frame::Torn),
+    }
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if (HEADER_LEN + len) as u64 > remaining {
+        return Ok(Frame::Torn);
+    }
+    let mut body = vec![0u8; len];
+    if read_fully(reader, &mut body)? != len {
+        return Ok(Frame::Torn);
+    }
+    if crc32fast::hash(&body) != checksum {
+        return Ok(Frame::Torn);
+    }
+    Ok(Frame::Complete(body))
+}
+
+fn read_fully<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::persist::file_repository::record::{decode, encode, read_frame, Frame, HEADER_LEN};
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = encode(&"some record").unwrap();
+        let len = frame.len() as u64;
+        let mut reader = Cursor::new(frame);
+        match read_frame(&mut reader, len).unwrap() {
+            Frame::Complete(body) => assert_eq!("some record", decode::<String>(&body).unwrap()),
+            _ => panic!("expected a complete frame"),
+        }
+        assert!(matches!(read_frame(&mut reader, 0).unwrap(), Frame::End));
+    }
+
+    #[test]
+    fn torn_frames() {
+        let frame = encode(&"some record").unwrap();
+
+        let mut partial_header = Cursor::new(frame[..HEADER_LEN - 1].to_vec());
+        assert!(matches!(
+            read_frame(&mut partial_header, HEADER_LEN as u64 - 1).unwrap(),
+            Frame::Torn
+        ));
+
+        let mut partial_body = Cursor::new(frame[..frame.len() - 1].to_vec());
+        assert!(matches!(
+            read_frame(&mut partial_body, frame.len() as u64 - 1).unwrap(),
+            Frame::Torn
+        ));
+
+        let mut corrupted = frame.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xff;
+        let len = corrupted.len() as u64;
+        assert!(matches!(
+            read_frame(&mut Cursor::new(corrupted), len).unwrap(),
+            Frame::Torn
+        ));
+
+        // an oversize length in a corrupted header is torn rather than allocated
+        let mut oversize = frame;
+        oversize[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let len = oversize.len() as u64;
+        assert!(matches!(
+            read_frame(&mut Cursor::new(oversize), len).unwrap(),
+            Frame::Torn
+        ));
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::persist::file_repository::record::{
+    decode, encode, read_frame, EventRecord, Frame, SnapshotRecord, HEADER_LEN,
+};
+use crate::persist::file_repository::FsyncPolicy;
+use crate::persist::{PersistenceError, SerializedEvent, SerializedSnapshot};
+
+const SEGMENT_EXTENSION: &str = "log";
+const SNAPSHOT_DIRECTORY: &str = "snapshots";
+const SNAPSHOT_EXTENSION: &str = "snap";
+
+/// The position of a committed event within the segment files.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RecordLocation {
+    pub(crate) segment: u64,
+    pub(crate) offset: u64,
+    pub(crate) sequence: usize,
+}
+
+/// A consistent view of the committed segment data, used to read events without holding
+/// the repository lock.
+pub(crate) struct LogSnapshot {
+    directory: PathBuf,
+    segments: Vec<(u64, u64)>,
+}
+
+/// The append-only event log for a single aggregate type.
+///
+/// Events are appended to the active (highest numbered) segment, a new segment is started
+/// once the active segment exceeds the configured maximum size. The per-aggregate index is
+/// held in memory and rebuilt by scanning the segments when the log is opened.
+pub(crate) struct EventLog {
+    directory: PathBuf,
+    segments: Vec<u64>,
+    active: File,
+    active_len: u64,
+    index: HashMap<String, Vec<RecordLocation>>,
+    unsynced_commits: usize,
+}
+
+impl EventLog {
+    /// Opens the log within the directory, truncating any torn or unacknowledged writes
+    /// found at the end of the active segment.
+    pub(crate) fn open(directory: PathBuf) -> Result<Self, PersistenceError> {
+        fs::create_dir_all(directory.join(SNAPSHOT_DIRECTORY))?;
+        let mut segments = list_segments(&directory)?;
+        if segments.is_empty() {
+            open_segment(&directory, 1)?;
+            segments.push(1);
+        }
+        let mut index: HashMap<String, Vec<RecordLocation>> = HashMap::new();
+        let mut active_len = 0;
+        let last = segments.len() - 1;
+        for (i, segment) in segments.iter().enumerate() {
+            let path = segment_path(&directory, *segment);
+            let scan = scan_segment(&path, *segment)?;
+            if scan.committed_len != scan.file_len {
+                if i != last {
+                    return Err(corrupted(&path));
+                }
+                let file = OpenOptions::new().write(true).open(&path)?;
+                file.set_len(scan.committed_len)?;
+                file.sync_all()?;
+            }
+            for (aggregate_id, location) in scan.locations {
+                index.entry(aggregate_id).or_default().push(location);
+            }
+            active_len = scan.committed_len;
+        }
+        let active = open_segment(&directory, segments[last])?;
+        Ok(Self {
+            directory,
+            segments,
+            active,
+            active_len,
+            index,
+            unsynced_commits: 0,
+        })
+    }
+
+    /// The highest committed sequence for an aggregate instance, zero if no events exist.
+    pub(crate) fn last_sequence(&self, aggregate_id: &str) -> usize {
+        self.index
+            .get(aggregate_id)
+            .and_then(|locations| locations.last())
+            .map(|location| location.sequence)
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn locations(
+        &self,
+        aggregate_id: &str,
+        after_sequence: usize,
+    ) -> Vec<RecordLocation> {
+        match self.index.get(aggregate_id) {
+            None => vec![],
+            Some(locations) => locations
+                .iter()
+                .filter(|location| location.sequence > after_sequence)
+                .copied()
+                .collect(),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> LogSnapshot {
+        let last = self.segments.len() - 1;
+        let segments = self
+            .segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                let len = if i == last { self.active_len } else { u64::MAX };
+                (*segment, len)
+            })
+            .collect();
+        LogSnapshot {
+            directory: self.directory.clone(),
+            segments,
+        }
+    }
+
+    /// Appends the events as a single commit, failing with an `OptimisticLockError` if any
+    /// aggregate instance has moved past the sequence the events were created against.
+    pub(crate) fn append(
+        &mut self,
+        events: &[SerializedEvent],
+        max_segment_size: u64,
+        fsync_policy: &FsyncPolicy,
+    ) -> Result<(), PersistenceError> {
+        let mut expected: HashMap<&str, usize> = HashMap::new();
+        for event in events {
+            let next = expected
+                .entry(event.aggregate_id.as_str())
+                .or_insert_with(|| self.last_sequence(&event.aggregate_id) + 1);
+            if event.sequence != *next {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+            *next += 1;
+        }
+        if self.active_len >= max_segment_size {
+            self.roll_segment()?;
+        }
+        let segment = *self.segments.last().unwrap();
+        let mut buffer = Vec::new();
+        let mut locations = Vec::with_capacity(events.len());
+        for (i, event) in events.iter().enumerate() {
+            let commit = i == events.len() - 1;
+            locations.push((
+                event.aggregate_id.clone(),
+                RecordLocation {
+                    segment,
+                    offset: self.active_len + buffer.len() as u64,
+                    sequence: event.sequence,
+                },
+            ));
+            buffer.extend(encode(&EventRecord::new(event, commit)?)?);
+        }
+        if let Err(err) = self
+            .active
+            .write_all(&buffer)
+            .map_err(PersistenceError::from)
+            .and_then(|_| self.sync(fsync_policy))
+        {
+            // Removes the unacknowledged write, should this fail the repository reopens the log
+            // and so recovers the offsets and index from the segment.
+            self.active.set_len(self.active_len)?;
+            return Err(err);
+        }
+        self.active_len += buffer.len() as u64;
+        for (aggregate_id, location) in locations {
+            self.index.entry(aggregate_id).or_default().push(location);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_snapshot(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let path = self.snapshot_path(aggregate_id);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let len = file.metadata()?.len();
+        match read_frame(&mut BufReader::new(file), len)? {
+            Frame::Complete(body) => Ok(Some(decode::<SnapshotRecord>(&body)?.into())),
+            _ => Err(corrupted(&path)),
+        }
+    }
+
+    /// Fails with an `OptimisticLockError` unless the snapshot replaces the stored version.
+    pub(crate) fn check_snapshot(&self, record: &SnapshotRecord) -> Result<(), PersistenceError> {
+        let stored = self
+            .get_snapshot(&record.aggregate_id)?
+            .map_or(0, |snapshot| snapshot.current_snapshot);
+        if stored + 1 != record.current_snapshot {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+
+    /// Replaces the snapshot for an aggregate instance by writing a temporary file and
+    /// renaming it over the previous snapshot.
+    pub(crate) fn put_snapshot(
+        &self,
+        record: SnapshotRecord,
+        fsync_policy: &FsyncPolicy,
+    ) -> Result<(), PersistenceError> {
+        let path = self.snapshot_path(&record.aggregate_id);
+        let temp_path = path.with_extension("tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&encode(&record)?)?;
+        if *fsync_policy != FsyncPolicy::Never {
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+
+    fn sync(&mut self, fsync_policy: &FsyncPolicy) -> Result<(), PersistenceError> {
+        self.unsynced_commits += 1;
+        let sync = match fsync_policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EveryCommits(commits) => self.unsynced_commits >= *commits,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            self.active.sync_data()?;
+            self.unsynced_commits = 0;
+        }
+        Ok(())
+    }
+
+    fn roll_segment(&mut self) -> Result<(), PersistenceError> {
+        self.active.sync_all()?;
+        let next = self.segments.last().unwrap() + 1;
+        self.active = open_segment(&self.directory, next)?;
+        self.segments.push(next);
+        self.active_len = 0;
+        self.unsynced_commits = 0;
+        Ok(())
+    }
+
+    fn snapshot_path(&self, aggregate_id: &str) -> PathBuf {
+        self.directory.join(SNAPSHOT_DIRECTORY).join(format!(
+            "{}.{}",
+            encode_file_name(aggregate_id),
+            SNAPSHOT_EXTENSION
+        ))
+    }
+}
+
+impl LogSnapshot {
+    /// Reads the events at the given locations, in order.
+    pub(crate) fn read_locations(
+        &self,
+        locations: &[RecordLocation],
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let mut events = Vec::with_capacity(locations.len());
+        self.for_each_location(locations, |event| {
+            events.push(event);
+            true
+        })?;
+        Ok(events)
+    }
+
+    /// Calls the provided function with the event at each location, in order, until the
+    /// function returns `false`.
+    pub(crate) fn for_each_location<F>(
+        &self,
+        locations: &[RecordLocation],
+        mut f: F,
+    ) -> Result<(), PersistenceError>
+    where
+        F: FnMut(SerializedEvent) -> bool,
+    {
+        let mut files: HashMap<u64, (File, u64)> = HashMap::new();
+        for location in locations {
+            let (file, file_len) = match files.entry(location.segment) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let file = File::open(segment_path(&self.directory, location.segment))?;
+                    let file_len = file.metadata()?.len();
+                    entry.insert((file, file_len))
+                }
+            };
+            file.seek(SeekFrom::Start(location.offset))?;
+            let remaining = file_len.saturating_sub(location.offset);
+            if !f(self.read_event(file, location.segment, remaining)?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the provided function with every committed event in the log, in the order they
+    /// were appended, until the function returns `false`.
+    pub(crate) fn for_each<F>(&self, mut f: F) -> Result<(), PersistenceError>
+    where
+        F: FnMut(SerializedEvent) -> bool,
+    {
+        for (segment, len) in &self.segments {
+            let file = File::open(segment_path(&self.directory, *segment))?;
+            let mut remaining = file.metadata()?.len().min(*len);
+            let mut reader = BufReader::new(file.take(*len));
+            loop {
+                match read_frame(&mut reader, remaining)? {
+                    Frame::Complete(body) => {
+                        remaining -= (body.len() + HEADER_LEN) as u64;
+                        let record: EventRecord = decode(&body)?;
+                        if !f(record.try_into()?) {
+                            return Ok(());
+                        }
+                    }
+                    Frame::End => break,
+                    Frame::Torn => return Err(corrupted(&segment_path(&self.directory, *segment))),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_event(
+        &self,
+        file: &mut File,
+        segment: u64,
+        remaining: u64,
+    ) -> Result<SerializedEvent, PersistenceError> {
+        match read_frame(file, remaining)? {
+            Frame::Complete(body) => decode::<EventRecord>(&body)?.try_into(),
+            _ => Err(corrupted(&segment_path(&self.directory, segment))),
+        }
+    }
+}
+
+struct SegmentScan {
+    locations: Vec<(String, RecordLocation)>,
+    committed_len: u64,
+    file_len: u64,
+}
+
+/// Reads every record in a segment, returning the locations of committed events along with
+/// the length of the segment up to the end of the last complete commit.
+fn scan_segment(path: &Path, segment: u64) -> Result<SegmentScan, PersistenceError> {
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut locations = Vec::new();
+    let mut pending = Vec::new();
+    let mut offset = 0;
+    let mut committed_len = 0;
+    while let Frame::Complete(body) = read_frame(&mut reader, file_len - offset)? {
+        let record: EventRecord = decode(&body)?;
+        pending.push((
+            record.aggregate_id,
+            RecordLocation {
+                segment,
+                offset,
+                sequence: record.sequence,
+            },
+        ));
+        offset += (body.len() + HEADER_LEN) as u64;
+        if record.commit {
+            locations.append(&mut pending);
+            committed_len = offset;
+        }
+    }
+    Ok(SegmentScan {
+        locations,
+        committed_len,
+        file_len,
+    })
+}
+
+fn list_segments(directory: &Path) -> Result<Vec<u64>, PersistenceError> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(segment) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+fn open_segment(directory: &Path, segment: u64) -> Result<File, PersistenceError> {
+    Ok(OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(directory, segment))?)
+}
+
+fn segment_path(directory: &Path, segment: u64) -> PathBuf {
+    directory.join(format!("{:020}.{}", segment, SEGMENT_EXTENSION))
+}
+
+fn corrupted(path: &Path) -> PersistenceError {
+    PersistenceError::DeserializationError(Box::new(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupted record found in {}", path.display()),
+    )))
+}
+
+/// Escapes any character that is not safe to use within a file name.
+pub(crate) fn encode_file_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
@@ -1,20 +1,38 @@
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
-use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+use crate::persist::view_cache::ViewCache;
+use crate::persist::{
+    PersistenceError, QueryableViewRepository, ViewCacheConfig, ViewContext, ViewPage, ViewQuery,
+    ViewRepository,
+};
 use crate::{Aggregate, EventEnvelope, Query, View};
 
+/// Events dispatched to a `GenericQuery` while a `ViewRebuild` is in progress, with the id of the
+/// view they were dispatched to.
+pub(crate) type RebuildEvents<A> = Vec<(String, Vec<EventEnvelope<A>>)>;
+
 /// A simple query and view repository. This is used both to act as a `Query` for processing events
 /// and to return materialized views.
+///
+/// The view repository may be replaced by a rebuilt one with a
+/// [ViewRebuild](crate::persist::ViewRebuild), while the query continues to process events.
 pub struct GenericQuery<R, V, A>
 where
     R: ViewRepository<V, A>,
     V: View<A>,
     A: Aggregate,
 {
-    view_repository: Arc<R>,
+    view_repository: RwLock<Arc<R>>,
+    rebuild: Mutex<Option<RebuildEvents<A>>>,
+    cache: Option<ViewCache<R, V, A>>,
+    retry_policy: UpdateRetryPolicy,
+    view_ids: Option<Arc<ViewIdSelector<EventEnvelope<A>>>>,
     error_handler: Option<Box<QueryErrorHandler>>,
     phantom: PhantomData<(V, A)>,
 }
@@ -39,7 +57,11 @@ where
     /// ```
     pub fn new(view_repository: Arc<R>) -> Self {
         GenericQuery {
-            view_repository,
+            view_repository: RwLock::new(view_repository),
+            rebuild: Mutex::new(None),
+            cache: None,
+            retry_policy: UpdateRetryPolicy::default(),
+            view_ids: None,
             error_handler: None,
             phantom: Default::default(),
         }
@@ -67,6 +89,126 @@ where
         self.error_handler = Some(error_handler);
     }
 
+    /// Configures how updates to a view are retried on an `OptimisticLockError`, by default they
+    /// are retried three times.
+    ///
+    /// _Example: Retrying concurrent updates up to ten times._
+    /// ```
+    /// # use std::time::Duration;
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::{MyViewRepository, MyView};
+    /// # use actuality::persist::{GenericQuery, UpdateRetryPolicy};
+    /// # fn config(query: GenericQuery<MyViewRepository, MyView, MyAggregate>) {
+    /// let query = query.with_retry_policy(
+    ///     UpdateRetryPolicy::default()
+    ///         .with_max_retries(10)
+    ///         .with_backoff(Duration::from_millis(5), Duration::from_millis(500)),
+    /// );
+    /// # }
+    /// ```
+    pub fn with_retry_policy(self, retry_policy: UpdateRetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Configures the ids of the views updated by each event, by default the view with the id
+    /// of the event's aggregate instance. An event may update any number of views, each loaded
+    /// and saved with the events selecting it, in the order they were dispatched.
+    ///
+    /// _Example: Totals per tenant alongside the view of each aggregate instance._
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::{MyViewRepository, MyView};
+    /// # use actuality::persist::GenericQuery;
+    /// # fn config(query: GenericQuery<MyViewRepository, MyView, MyAggregate>) {
+    /// let query = query.with_view_ids(|event| {
+    ///     let mut view_ids = vec![event.aggregate_id.clone()];
+    ///     if let Some(tenant) = event.metadata.get("tenant") {
+    ///         view_ids.push(format!("tenant-{}", tenant));
+    ///     }
+    ///     view_ids
+    /// });
+    /// # }
+    /// ```
+    pub fn with_view_ids<F>(self, view_ids: F) -> Self
+    where
+        F: Fn(&EventEnvelope<A>) -> Vec<String> + Send + Sync + 'static,
+    {
+        Self {
+            view_ids: Some(Arc::new(view_ids)),
+            ..self
+        }
+    }
+
+    /// Selects the views updated by the same events as those of the other query.
+    pub(crate) fn with_view_ids_of(self, query: &Self) -> Self {
+        Self {
+            view_ids: query.view_ids.clone(),
+            ..self
+        }
+    }
+
+    /// Configures a write-behind cache of views, coalescing the updates of several dispatches to
+    /// a view into a single write to the view repository.
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::{MyViewRepository, MyView};
+    /// # use actuality::persist::{GenericQuery, ViewCacheConfig};
+    /// # async fn config(repo: Arc<MyViewRepository>) {
+    /// let query = Arc::new(
+    ///     GenericQuery::<MyViewRepository, MyView, MyAggregate>::new(repo).with_view_cache(
+    ///         ViewCacheConfig::default().with_flush_interval(Duration::from_millis(500)),
+    ///     ),
+    /// );
+    /// GenericQuery::spawn_cache_flush(&query);
+    /// # }
+    /// ```
+    pub fn with_view_cache(self, config: ViewCacheConfig) -> Self {
+        Self {
+            cache: Some(ViewCache::new(config)),
+            ..self
+        }
+    }
+
+    /// Writes any views updated in the view cache to the view repository, e.g., before the
+    /// application stops.
+    pub async fn flush(&self) {
+        if let Some(cache) = &self.cache {
+            for error in cache.flush(&self.retry_policy).await {
+                self.handle_error(error);
+            }
+        }
+    }
+
+    /// Spawns a task flushing the view cache at its flush interval, so that updated views are
+    /// written while no events are dispatched. The task ends once the query is dropped, none
+    /// is spawned without a view cache or flush interval.
+    pub fn spawn_cache_flush(query: &Arc<Self>) -> Option<JoinHandle<()>>
+    where
+        R: 'static,
+        V: 'static,
+        A: 'static,
+    {
+        let interval = query.cache.as_ref()?.flush_interval()?;
+        let query = Arc::downgrade(query);
+        Some(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                match query.upgrade() {
+                    Some(query) => query.flush().await,
+                    None => return,
+                }
+            }
+        }))
+    }
+
     /// Loads and deserializes a view based on the provided view id.
     /// Use this method to load a materialized view when requested by a user.
     ///
@@ -79,7 +221,18 @@ where
     /// # }
     /// ```
     pub async fn load(&self, view_id: &str) -> Option<V> {
-        match self.view_repository.load_with_context(view_id).await {
+        let view_repository = self.view_repository();
+        if let Some(cache) = &self.cache {
+            match cache.load(&view_repository, view_id).await {
+                Some(Ok(view)) => return Some(view),
+                Some(Err(e)) => {
+                    self.handle_error(e);
+                    return None;
+                }
+                None => {}
+            }
+        }
+        match view_repository.load_with_context(view_id).await {
             Ok(option) => option.map(|(view, _)| view),
             Err(e) => {
                 self.handle_error(e);
@@ -88,29 +241,89 @@ where
         }
     }
 
-    async fn load_mut(&self, view_id: String) -> Result<(V, ViewContext), PersistenceError> {
-        match self.view_repository.load_with_context(&view_id).await? {
-            None => {
-                let view_context = ViewContext::new(view_id, 0);
-                Ok((Default::default(), view_context))
-            }
-            Some((view, context)) => Ok((view, context)),
-        }
+    /// The view repository currently read from and updated.
+    pub fn view_repository(&self) -> Arc<R> {
+        self.view_repository.read().unwrap().clone()
     }
 
-    pub(crate) async fn apply_events(
+    async fn apply_events_to(
         &self,
+        view_repository: &R,
         view_id: &str,
         events: &[EventEnvelope<A>],
     ) -> Result<(), PersistenceError> {
-        let (mut view, view_context) = self.load_mut(view_id.to_string()).await?;
-        for event in events {
-            view.update(event);
-        }
-        self.view_repository.update_view(view, view_context).await?;
+        update_view_with_retry(
+            view_repository,
+            &self.retry_policy,
+            view_id,
+            None,
+            events,
+            V::update,
+        )
+        .await?;
         Ok(())
     }
 
+    /// Starts recording the events dispatched to the query, returning false if a rebuild is
+    /// already in progress.
+    pub(crate) async fn begin_rebuild(&self) -> bool {
+        let mut rebuild = self.rebuild.lock().await;
+        if rebuild.is_some() {
+            return false;
+        }
+        *rebuild = Some(Vec::new());
+        true
+    }
+
+    /// Takes the events dispatched since the rebuild began or the events were last taken.
+    pub(crate) async fn take_rebuild_events(&self) -> RebuildEvents<A> {
+        match self.rebuild.lock().await.as_mut() {
+            Some(events) => std::mem::take(events),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stops recording dispatched events without replacing the view repository.
+    pub(crate) async fn abort_rebuild(&self) {
+        *self.rebuild.lock().await = None;
+    }
+
+    /// Passes the remaining recorded events to `catch_up` and replaces the view repository,
+    /// holding back any events dispatched meanwhile so that none are missed by the rebuilt
+    /// repository. Returns the replaced view repository, the rebuild is abandoned on error.
+    pub(crate) async fn complete_rebuild<F, Fut>(
+        &self,
+        view_repository: Arc<R>,
+        catch_up: F,
+    ) -> Result<Arc<R>, PersistenceError>
+    where
+        F: FnOnce(RebuildEvents<A>) -> Fut,
+        Fut: std::future::Future<Output = Result<(), PersistenceError>>,
+    {
+        let mut rebuild = self.rebuild.lock().await;
+        let events = rebuild.take().unwrap_or_default();
+        catch_up(events).await?;
+        self.flush().await;
+        let replaced =
+            std::mem::replace(&mut *self.view_repository.write().unwrap(), view_repository);
+        Ok(replaced)
+    }
+
+    /// Groups the events by the views they update, keeping the order in which each view is first
+    /// updated.
+    fn select_views(&self, view_id: &str, events: &[EventEnvelope<A>]) -> RebuildEvents<A> {
+        match &self.view_ids {
+            Some(view_ids) => select_views(view_ids.as_ref(), events)
+                .into_iter()
+                .map(|(view_id, selected)| {
+                    let events = selected.into_iter().map(|i| events[i].clone()).collect();
+                    (view_id, events)
+                })
+                .collect(),
+            None => vec![(view_id.to_string(), events.to_vec())],
+        }
+    }
+
     fn handle_error(&self, error: PersistenceError) {
         if let Some(handler) = &self.error_handler {
             (handler)(error);
@@ -118,6 +331,36 @@ where
     }
 }
 
+impl<R, V, A> GenericQuery<R, V, A>
+where
+    R: QueryableViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Returns a page of the views selected by the query, writing any views updated in the view
+    /// cache beforehand. Use this method to list or search views when requested by a user.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyView;
+    /// # use actuality::persist::{GenericQuery, MemoryViewRepository, PersistenceError};
+    /// use actuality::persist::{SortOrder, ViewQuery};
+    ///
+    /// # async fn config(
+    /// #     query: GenericQuery<MemoryViewRepository<MyView, MyAggregate>, MyView, MyAggregate>,
+    /// # ) -> Result<(), PersistenceError> {
+    /// let page = query
+    ///     .query_views(&ViewQuery::default().with_sort("name", SortOrder::Ascending).with_limit(50))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_views(&self, query: &ViewQuery) -> Result<ViewPage<V>, PersistenceError> {
+        self.flush().await;
+        self.view_repository().query_views(query).await
+    }
+}
+
 #[async_trait]
 impl<R, V, A> Query<A> for GenericQuery<R, V, A>
 where
@@ -126,11 +369,178 @@ where
     A: Aggregate,
 {
     async fn dispatch(&self, view_id: &str, events: &[EventEnvelope<A>]) {
-        match self.apply_events(view_id, events).await {
-            Ok(_) => {}
-            Err(err) => self.handle_error(err),
+        let view_repository = {
+            let mut rebuild = self.rebuild.lock().await;
+            if let Some(rebuild_events) = rebuild.as_mut() {
+                rebuild_events.push((view_id.to_string(), events.to_vec()));
+            }
+            self.view_repository()
         };
+        for (view_id, events) in self.select_views(view_id, events) {
+            if let Some(cache) = &self.cache {
+                let errors = cache
+                    .apply(&view_repository, &self.retry_policy, &view_id, &events)
+                    .await;
+                for error in errors {
+                    self.handle_error(error);
+                }
+                continue;
+            }
+            if let Err(err) = self
+                .apply_events_to(&view_repository, &view_id, &events)
+                .await
+            {
+                self.handle_error(err);
+            }
+        }
+    }
+}
+
+/// How a `GenericQuery` retries updating a view on an `OptimisticLockError`, as when another
+/// process dispatches events to the same view.
+///
+/// Before each retry the view is reloaded and the events reapplied, waiting beforehand for a
+/// backoff that doubles with each retry, from the initial to the maximum backoff.
+#[derive(Debug, Clone)]
+pub struct UpdateRetryPolicy {
+    pub(crate) max_retries: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for UpdateRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl UpdateRetryPolicy {
+    /// A policy passing every `OptimisticLockError` to the error handler without retrying.
+    pub fn never() -> Self {
+        Self::default().with_max_retries(0)
+    }
+
+    /// Configures the number of retries before the error is passed to the error handler,
+    /// defaults to three.
+    pub fn with_max_retries(self, max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            ..self
+        }
+    }
+
+    /// Configures the wait before the first retry, doubling for each further retry up to the
+    /// maximum, defaults to 10 milliseconds and one second.
+    pub fn with_backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    pub(crate) fn backoff(&self, retry: usize) -> Duration {
+        let factor = 2u32.saturating_pow(retry.min(u32::MAX as usize) as u32);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// The outcome of an update made by `update_view_with_retry`.
+pub(crate) struct UpdatedView {
+    /// The version of the view written.
+    pub(crate) version: i64,
+    /// Whether the view was reloaded to retry the update, such that the view provided was not
+    /// the one written.
+    pub(crate) reloaded: bool,
+}
+
+/// Writes the view updated with the events, loading the view and applying the events with
+/// `update` unless provided with the updated view and its version. On an `OptimisticLockError`
+/// the view is reloaded and the events reapplied, as allowed by the retry policy.
+///
+/// The repository is that of a `GenericQuery` of aggregate `A`, applying `EventEnvelope<A>`s, or
+/// of a `MultiSourceQuery` of events `E`, where `A` and `E` are the same.
+pub(crate) async fn update_view_with_retry<R, V, A, E>(
+    view_repository: &R,
+    retry_policy: &UpdateRetryPolicy,
+    view_id: &str,
+    updated: Option<(V, i64)>,
+    events: &[E],
+    update: fn(&mut V, &E),
+) -> Result<UpdatedView, PersistenceError>
+where
+    R: ViewRepository<V, A>,
+    V: Default,
+{
+    let (mut view, mut version) = match updated {
+        Some(updated) => updated,
+        None => load_and_apply(view_repository, view_id, events, update).await?,
+    };
+    let mut retries = 0;
+    loop {
+        let context = ViewContext::new(view_id.to_string(), version);
+        match view_repository.update_view(view, context).await {
+            Ok(_) => {
+                return Ok(UpdatedView {
+                    version: version + 1,
+                    reloaded: retries > 0,
+                })
+            }
+            Err(PersistenceError::OptimisticLockError) if retries < retry_policy.max_retries => {
+                tokio::time::sleep(retry_policy.backoff(retries)).await;
+                retries += 1;
+                (view, version) = load_and_apply(view_repository, view_id, events, update).await?;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn load_and_apply<R, V, A, E>(
+    view_repository: &R,
+    view_id: &str,
+    events: &[E],
+    update: fn(&mut V, &E),
+) -> Result<(V, i64), PersistenceError>
+where
+    R: ViewRepository<V, A>,
+    V: Default,
+{
+    let (mut view, version) = match view_repository.load_with_context(view_id).await? {
+        None => (V::default(), 0),
+        Some((view, context)) => (view, context.version),
+    };
+    for event in events {
+        update(&mut view, event);
+    }
+    Ok((view, version))
+}
+
+/// Groups the indices of the events by the ids of the views they update, keeping the order in
+/// which each view is first updated.
+pub(crate) fn select_views<E>(
+    view_ids: &ViewIdSelector<E>,
+    events: &[E],
+) -> Vec<(String, Vec<usize>)> {
+    let mut selected: Vec<(String, Vec<usize>)> = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        for view_id in view_ids(event) {
+            match selected
+                .iter_mut()
+                .find(|(selected_id, _)| *selected_id == view_id)
+            {
+                Some((_, indices)) => indices.push(i),
+                None => selected.push((view_id, vec![i])),
+            }
+        }
     }
+    selected
 }
 
 /// A convenience type for query error handlers.
@@ -153,3 +563,176 @@ where
 /// }
 /// ```
 pub type QueryErrorHandler = dyn Fn(PersistenceError) + Send + Sync + 'static;
+
+/// Selects the ids of the views a query updates with an event, see [GenericQuery::with_view_ids]
+/// and [MultiSourceQuery::with_view_ids](crate::persist::MultiSourceQuery::with_view_ids).
+///
+/// The event is an `EventEnvelope<A>` for a `GenericQuery` of aggregate `A`.
+pub type ViewIdSelector<E> = dyn Fn(&E) -> Vec<String> + Send + Sync + 'static;
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+
+    use crate::doc::setup::{MyAggregate, MyEvents};
+    use crate::persist::{
+        GenericQuery, MemoryViewRepository, PersistenceError, UpdateRetryPolicy, ViewCacheConfig,
+        ViewContext, ViewRepository,
+    };
+    use crate::{EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct CountView {
+        sequences: Vec<usize>,
+    }
+
+    impl View<MyAggregate> for CountView {
+        fn update(&mut self, event: &EventEnvelope<MyAggregate>) {
+            self.sequences.push(event.sequence);
+        }
+    }
+
+    /// A view repository where another writer updates the view just before each of the next
+    /// `conflicts` updates.
+    #[derive(Default)]
+    struct ContendedRepository {
+        views: MemoryViewRepository<CountView, MyAggregate>,
+        conflicts: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl ViewRepository<CountView, MyAggregate> for ContendedRepository {
+        async fn load(&self, view_id: &str) -> Result<Option<CountView>, PersistenceError> {
+            self.views.load(view_id).await
+        }
+
+        async fn load_with_context(
+            &self,
+            view_id: &str,
+        ) -> Result<Option<(CountView, ViewContext)>, PersistenceError> {
+            self.views.load_with_context(view_id).await
+        }
+
+        async fn update_view(
+            &self,
+            view: CountView,
+            context: ViewContext,
+        ) -> Result<(), PersistenceError> {
+            let conflict = self.conflicts.lock().unwrap().pop();
+            if let Some(sequence) = conflict {
+                let view_id = &context.view_instance_id;
+                let (mut stored, stored_context) = self
+                    .views
+                    .load_with_context(view_id)
+                    .await?
+                    .unwrap_or_else(|| {
+                        (CountView::default(), ViewContext::new(view_id.clone(), 0))
+                    });
+                stored.sequences.push(sequence);
+                self.views.update_view(stored, stored_context).await?;
+            }
+            self.views.update_view(view, context).await
+        }
+    }
+
+    type CountQuery = GenericQuery<ContendedRepository, CountView, MyAggregate>;
+
+    fn event(sequence: usize) -> EventEnvelope<MyAggregate> {
+        EventEnvelope {
+            aggregate_id: "view-A".to_string(),
+            event_type: "SomethingWasDone".to_string(),
+            sequence,
+            system_id: "".to_string(),
+            payload: MyEvents::SomethingWasDone,
+            metadata: Default::default(),
+        }
+    }
+
+    fn contended_query(
+        conflicts: Vec<usize>,
+        retry_policy: UpdateRetryPolicy,
+    ) -> (CountQuery, Arc<Mutex<Vec<String>>>) {
+        let repo = Arc::new(ContendedRepository {
+            conflicts: Mutex::new(conflicts),
+            ..Default::default()
+        });
+        let errors: Arc<Mutex<Vec<String>>> = Default::default();
+        let handler_errors = errors.clone();
+        let mut query = CountQuery::new(repo).with_retry_policy(
+            retry_policy.with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        );
+        query.use_error_handler(Box::new(move |e| {
+            handler_errors.lock().unwrap().push(e.to_string())
+        }));
+        (query, errors)
+    }
+
+    #[tokio::test]
+    async fn retry_update() {
+        let (query, errors) = contended_query(vec![101, 100], UpdateRetryPolicy::default());
+        query.dispatch("view-A", &[event(1), event(2)]).await;
+
+        let view = query.load("view-A").await.unwrap();
+        assert_eq!(vec![100, 101, 1, 2], view.sequences);
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_exhausted() {
+        let policy = UpdateRetryPolicy::default().with_max_retries(1);
+        let (query, errors) = contended_query(vec![101, 100], policy);
+        query.dispatch("view-A", &[event(1)]).await;
+
+        let view = query.load("view-A").await.unwrap();
+        assert_eq!(vec![100, 101], view.sequences);
+        assert_eq!(vec!["optimistic lock error"], *errors.lock().unwrap());
+
+        let (query, errors) = contended_query(vec![100], UpdateRetryPolicy::never());
+        query.dispatch("view-A", &[event(1)]).await;
+        assert_eq!(1, errors.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn retry_cached_update() {
+        let (query, errors) = contended_query(vec![100], UpdateRetryPolicy::default());
+        let query = query.with_view_cache(ViewCacheConfig::default().without_flush_interval());
+        query.dispatch("view-A", &[event(1)]).await;
+        query.dispatch("view-A", &[event(2)]).await;
+        query.flush().await;
+        assert_eq!(
+            vec![100, 1, 2],
+            query.load("view-A").await.unwrap().sequences
+        );
+
+        // the reloaded view is cached once updated again
+        query.dispatch("view-A", &[event(3)]).await;
+        query.flush().await;
+        assert_eq!(
+            vec![100, 1, 2, 3],
+            query.load("view-A").await.unwrap().sequences
+        );
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn select_view_ids() {
+        let (query, errors) = contended_query(vec![], UpdateRetryPolicy::default());
+        let query = query.with_view_ids(|event| match event.sequence % 3 {
+            0 => vec![],
+            1 => vec!["odd".to_string(), "all".to_string()],
+            _ => vec!["all".to_string()],
+        });
+        query
+            .dispatch("view-A", &[event(1), event(2), event(3), event(4)])
+            .await;
+
+        assert_eq!(vec![1, 2, 4], query.load("all").await.unwrap().sequences);
+        assert_eq!(vec![1, 4], query.load("odd").await.unwrap().sequences);
+        assert!(query.load("view-A").await.is_none());
+        assert!(errors.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::persist::{
+    PersistenceError, QueryableViewRepository, ViewContext, ViewPage, ViewQuery, ViewRepository,
+};
+use crate::{Aggregate, View};
+
+/// Simple in-memory view repository useful for application development and testing purposes,
+/// for use in backing a `GenericQuery` or a `MultiSourceQuery`.
+///
+/// Views are held serialized, as they would be by a database, and may be listed and searched
+/// as a `QueryableViewRepository`.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use actuality::doc::setup::MyAggregate;
+/// # use actuality::doc::persist::MyView;
+/// use actuality::persist::{GenericQuery, MemoryViewRepository};
+///
+/// let repo = Arc::new(MemoryViewRepository::<MyView, MyAggregate>::default());
+/// let query = GenericQuery::new(repo);
+/// ```
+pub struct MemoryViewRepository<V, A> {
+    views: RwLock<BTreeMap<String, (Value, i64)>>,
+    phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> Default for MemoryViewRepository<V, A> {
+    fn default() -> Self {
+        Self {
+            views: Default::default(),
+            phantom: Default::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl<V, A> ViewRepository<V, A> for MemoryViewRepository<V, A>
+where
+    V: Serialize + DeserializeOwned + Send + Sync,
+    A: Send + Sync,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        let views = self.views.read().unwrap();
+        match views.get(view_id) {
+            None => Ok(None),
+            Some((payload, version)) => {
+                let view = serde_json::from_value(payload.clone())?;
+                Ok(Some((
+                    view,
+                    ViewContext::new(view_id.to_string(), *version),
+                )))
+            }
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        let payload = serde_json::to_value(&view)?;
+        let mut views = self.views.write().unwrap();
+        let current = views
+            .get(&context.view_instance_id)
+            .map_or(0, |(_, version)| *version);
+        if current != context.version {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        views.insert(context.view_instance_id, (payload, context.version + 1));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<V, A> QueryableViewRepository<V, A> for MemoryViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn query_views(&self, query: &ViewQuery) -> Result<ViewPage<V>, PersistenceError> {
+        let views: Vec<(String, Value)> = self
+            .views
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(view_id, (payload, _))| (view_id.clone(), payload.clone()))
+            .collect();
+        query.page(views)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::doc::setup::{MyAggregate, MyEvents};
+    use crate::persist::{
+        FieldCondition, GenericQuery, MemoryViewRepository, PersistenceError, SortOrder,
+        ViewContext, ViewQuery, ViewRepository,
+    };
+    use crate::{EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct CountView {
+        events: usize,
+    }
+
+    impl View<MyAggregate> for CountView {
+        fn update(&mut self, _event: &EventEnvelope<MyAggregate>) {
+            self.events += 1;
+        }
+    }
+
+    fn event(aggregate_id: &str) -> EventEnvelope<MyAggregate> {
+        EventEnvelope {
+            aggregate_id: aggregate_id.to_string(),
+            event_type: "SomethingWasDone".to_string(),
+            sequence: 1,
+            system_id: "".to_string(),
+            payload: MyEvents::SomethingWasDone,
+            metadata: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_views() {
+        let repo = MemoryViewRepository::<CountView, MyAggregate>::default();
+        assert!(repo.load("view-A").await.unwrap().is_none());
+
+        repo.update_view(
+            CountView { events: 1 },
+            ViewContext::new("view-A".to_string(), 0),
+        )
+        .await
+        .unwrap();
+        let (view, context) = repo.load_with_context("view-A").await.unwrap().unwrap();
+        assert_eq!(CountView { events: 1 }, view);
+        assert_eq!(1, context.version);
+
+        let result = repo
+            .update_view(
+                CountView { events: 2 },
+                ViewContext::new("view-A".to_string(), 0),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+    }
+
+    #[tokio::test]
+    async fn query_views() {
+        let repo = Arc::new(MemoryViewRepository::<CountView, MyAggregate>::default());
+        let query = GenericQuery::new(repo);
+        query
+            .dispatch("view-A", &[event("view-A"), event("view-A")])
+            .await;
+        query.dispatch("view-B", &[event("view-B")]).await;
+        query
+            .dispatch("view-C", &[event("view-C"), event("view-C")])
+            .await;
+
+        let view_query = ViewQuery::default()
+            .with_condition("events", FieldCondition::GreaterThan(1.into()))
+            .with_sort("events", SortOrder::Descending)
+            .with_limit(1);
+        let page = query.query_views(&view_query).await.unwrap();
+        assert_eq!(
+            vec![("view-A".to_string(), CountView { events: 2 })],
+            page.views
+        );
+
+        let view_query = view_query.with_cursor(page.next_cursor.unwrap());
+        let page = query.query_views(&view_query).await.unwrap();
+        assert_eq!(
+            vec![("view-C".to_string(), CountView { events: 2 })],
+            page.views
+        );
+        assert!(page.next_cursor.is_none());
+    }
+}
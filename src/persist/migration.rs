@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use crate::persist::compression::CompressionPolicy;
+use crate::persist::upcaster::upcast_event;
+use crate::persist::{EventUpcaster, PersistedEventRepository, PersistenceError, SerializedEvent};
+use crate::Aggregate;
+
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// A helper type for the transformation functions applied by an `EventMigration`, returning the
+/// events that replace the event provided.
+pub type EventTransformFunc =
+    dyn Fn(SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> + Send + Sync;
+
+/// Applies a transformation function to every event.
+struct EventTransform(Box<EventTransformFunc>);
+
+impl EventUpcaster for EventTransform {
+    fn can_upcast(&self, _event_type: &str, _event_version: &str) -> bool {
+        true
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        (self.0)(event)
+    }
+}
+
+/// The outcome of a completed `EventMigration`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The number of aggregate instances written to the target.
+    pub aggregates: usize,
+    /// The number of events read from the source.
+    pub events_read: usize,
+    /// The number of events written to the target.
+    pub events_written: usize,
+}
+
+/// Permanently migrates the events of an aggregate type into another repository, so that
+/// settled upcasters need no longer be applied on every read.
+///
+/// Each event is passed through the configured upcasters and transformation functions, in the
+/// order they were configured, and written to the target in batches. Events are renumbered where
+/// upcasters split or drop events so that each aggregate instance's sequence remains contiguous.
+/// The source sequences are checked as they are read and the migrated events are read back from
+/// the target to verify their count and sequences.
+///
+/// Snapshots are not migrated, they are rebuilt from the migrated events as aggregates are
+/// loaded. Payloads are migrated as stored, any encrypted fields remaining encrypted and
+/// upcasted payloads being compressed again with the codec they were stored with.
+///
+/// ```
+/// # use actuality::doc::setup::{MyAggregate, MyRepository};
+/// use actuality::persist::{EventMigration, RenameEventUpcaster};
+///
+/// async fn migrate(source: MyRepository, target: MyRepository) {
+///     let migration = EventMigration::new(source, target).with_upcasters(vec![Box::new(
+///         RenameEventUpcaster::new("SomethingDone", "SomethingWasDone"),
+///     )]);
+///     let report = migration.migrate::<MyAggregate>().await.unwrap();
+///     println!("migrated {} events", report.events_written);
+/// }
+/// ```
+pub struct EventMigration<S, T>
+where
+    S: PersistedEventRepository,
+    T: PersistedEventRepository,
+{
+    source: S,
+    target: T,
+    upcasters: Vec<Box<dyn EventUpcaster>>,
+    batch_size: usize,
+}
+
+impl<S, T> EventMigration<S, T>
+where
+    S: PersistedEventRepository,
+    T: PersistedEventRepository,
+{
+    /// Creates a migration reading events from the source repository and writing them to the
+    /// target, which should hold no events for the aggregate types migrated.
+    pub fn new(source: S, target: T) -> Self {
+        Self {
+            source,
+            target,
+            upcasters: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Configures the migration to upcast events, typically with the upcasters configured on
+    /// the `PersistedEventStore` which are no longer needed once the migration is complete.
+    pub fn with_upcasters(mut self, upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        self.upcasters.extend(upcasters);
+        self
+    }
+
+    /// Configures the migration to transform every event with the provided function, returning
+    /// the events replacing it.
+    ///
+    /// _Example: Removing a field from every event._
+    /// ```
+    /// # use actuality::doc::setup::MyRepository;
+    /// # use actuality::persist::{EventMigration, SerializedEvent};
+    /// # fn config(migration: EventMigration<MyRepository, MyRepository>) {
+    /// let migration = migration.with_transform(Box::new(|event: SerializedEvent| {
+    ///     let mut payload: serde_json::Value = serde_json::from_slice(&event.payload)?;
+    ///     if let Some(fields) = payload.as_object_mut() {
+    ///         fields.remove("legacy_reference");
+    ///     }
+    ///     let payload = serde_json::to_vec(&payload)?;
+    ///     Ok(vec![SerializedEvent { payload, ..event }])
+    /// }));
+    /// # }
+    /// ```
+    pub fn with_transform(mut self, transform: Box<EventTransformFunc>) -> Self {
+        self.upcasters.push(Box::new(EventTransform(transform)));
+        self
+    }
+
+    /// Sets the number of events written to the target at a time, 100 by default.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Migrates the events of the aggregate type into the target repository.
+    pub async fn migrate<A: Aggregate>(&self) -> Result<MigrationReport, PersistenceError> {
+        self.migrate_to::<A, A>().await
+    }
+
+    /// Migrates the events of aggregate type `A` into the stream of aggregate type `B` in the
+    /// target repository.
+    ///
+    /// An upcaster may itself move events to aggregate type `B`, e.g., a `MoveEventUpcaster`.
+    /// Events moved to any other aggregate type cannot be written by the migration, which fails
+    /// rather than writing them to the stream of `B`.
+    pub async fn migrate_to<A: Aggregate, B: Aggregate>(
+        &self,
+    ) -> Result<MigrationReport, PersistenceError> {
+        let mut stream = self.source.stream_all_events::<A>().await?;
+        let mut source_sequences: HashMap<String, usize> = HashMap::new();
+        let mut target_sequences: HashMap<String, usize> = HashMap::new();
+        let mut batch = Vec::with_capacity(self.batch_size);
+        let mut report = MigrationReport::default();
+        while let Some(event) = stream.next_serialized().await {
+            let event = event?;
+            let last_sequence = source_sequences
+                .entry(event.aggregate_id.clone())
+                .or_default();
+            if event.sequence != *last_sequence + 1 {
+                return Err(sequence_error(
+                    "source",
+                    &event.aggregate_id,
+                    *last_sequence + 1,
+                    event.sequence,
+                ));
+            }
+            *last_sequence = event.sequence;
+            report.events_read += 1;
+
+            for mut event in self.upcast(event)? {
+                if event.aggregate_type == A::aggregate_type() {
+                    event.aggregate_type = B::aggregate_type();
+                } else if event.aggregate_type != B::aggregate_type() {
+                    return Err(PersistenceError::UnknownError(
+                        format!(
+                            "{} of aggregate '{}' was moved to aggregate type {}, which a migration into {} cannot write",
+                            event.event_type,
+                            event.aggregate_id,
+                            event.aggregate_type,
+                            B::aggregate_type()
+                        )
+                        .into(),
+                    ));
+                }
+                let sequence = target_sequences
+                    .entry(event.aggregate_id.clone())
+                    .or_default();
+                *sequence += 1;
+                event.sequence = *sequence;
+                batch.push(event);
+            }
+            if batch.len() >= self.batch_size {
+                self.write::<B>(&mut batch, &mut report).await?;
+            }
+        }
+        self.write::<B>(&mut batch, &mut report).await?;
+        self.verify::<B>(&target_sequences).await?;
+        report.aggregates = target_sequences.len();
+        Ok(report)
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        if self.upcasters.is_empty() {
+            return Ok(vec![event]);
+        }
+        let policy = CompressionPolicy {
+            compression: event.compression,
+            threshold: 0,
+        };
+        upcast_event(event.decompress()?, &self.upcasters)?
+            .into_iter()
+            .map(|event| event.compress(&policy))
+            .collect()
+    }
+
+    async fn write<B: Aggregate>(
+        &self,
+        batch: &mut Vec<SerializedEvent>,
+        report: &mut MigrationReport,
+    ) -> Result<(), PersistenceError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.target.persist::<B>(batch, None).await?;
+        report.events_written += batch.len();
+        batch.clear();
+        Ok(())
+    }
+
+    async fn verify<B: Aggregate>(
+        &self,
+        expected: &HashMap<String, usize>,
+    ) -> Result<(), PersistenceError> {
+        for (aggregate_id, count) in expected {
+            let events = self.target.get_events::<B>(aggregate_id).await?;
+            if events.len() != *count {
+                return Err(PersistenceError::UnknownError(
+                    format!(
+                        "migrated {} events for aggregate '{}' but {} were found in the target",
+                        count,
+                        aggregate_id,
+                        events.len()
+                    )
+                    .into(),
+                ));
+            }
+            for (index, event) in events.iter().enumerate() {
+                if event.sequence != index + 1 {
+                    return Err(sequence_error(
+                        "target",
+                        aggregate_id,
+                        index + 1,
+                        event.sequence,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sequence_error(
+    repository: &str,
+    aggregate_id: &str,
+    expected: usize,
+    found: usize,
+) -> PersistenceError {
+    PersistenceError::UnknownError(
+        format!(
+            "expected sequence {} for aggregate '{}' in the {} but found {}",
+            expected, aggregate_id, repository, found
+        )
+        .into(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, TestAggregate, TestDirectory, TestEvents,
+    };
+    use crate::persist::{
+        DropEventUpcaster, EventMigration, FileEventRepository, MigrationReport, MoveEventUpcaster,
+        PersistedEventRepository, SerializedEvent,
+    };
+
+    fn event(aggregate_id: &str, sequence: usize, event: TestEvents) -> SerializedEvent {
+        let mut event = test_serialized_event(sequence, event);
+        event.aggregate_id = aggregate_id.to_string();
+        event
+    }
+
+    async fn source(directory: &TestDirectory, events: &[SerializedEvent]) -> FileEventRepository {
+        let repo = FileEventRepository::new(&directory.0);
+        repo.persist::<TestAggregate>(events, None).await.unwrap();
+        repo
+    }
+
+    #[tokio::test]
+    async fn migrate() {
+        let (source_directory, target_directory) = (TestDirectory::new(), TestDirectory::new());
+        let source = source(
+            &source_directory,
+            &[
+                event("agg-1", 1, TestEvents::Started),
+                event("agg-2", 1, TestEvents::Started),
+                event("agg-1", 2, TestEvents::SomethingWasDone),
+                event("agg-2", 2, TestEvents::SomethingWasDone),
+            ],
+        )
+        .await;
+        let target = FileEventRepository::new(&target_directory.0);
+        let migration = EventMigration::new(source, target)
+            .with_upcasters(vec![Box::new(DropEventUpcaster::new("Started"))])
+            .with_transform(Box::new(|event: SerializedEvent| {
+                Ok(vec![event.clone(), event])
+            }))
+            .with_batch_size(3);
+        let report = migration.migrate::<TestAggregate>().await.unwrap();
+        assert_eq!(
+            MigrationReport {
+                aggregates: 2,
+                events_read: 4,
+                events_written: 4,
+            },
+            report
+        );
+
+        let target = FileEventRepository::new(&target_directory.0);
+        for aggregate_id in ["agg-1", "agg-2"] {
+            let events = target
+                .get_events::<TestAggregate>(aggregate_id)
+                .await
+                .unwrap();
+            let sequences: Vec<usize> = events.iter().map(|event| event.sequence).collect();
+            assert_eq!(vec![1, 2], sequences);
+            assert!(events
+                .iter()
+                .all(|event| event.event_type == "SomethingWasDone"));
+        }
+    }
+
+    #[tokio::test]
+    async fn migrate_into_populated_target() {
+        let (source_directory, target_directory) = (TestDirectory::new(), TestDirectory::new());
+        let events = [event("agg-1", 1, TestEvents::Started)];
+        let source = source(&source_directory, &events).await;
+        let target = self::source(&target_directory, &events).await;
+        let result = EventMigration::new(source, target)
+            .migrate::<TestAggregate>()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn migrate_moved_events() {
+        let (source_directory, target_directory) = (TestDirectory::new(), TestDirectory::new());
+        let events = [
+            event("agg-1", 1, TestEvents::Started),
+            event("agg-1", 2, TestEvents::SomethingWasDone),
+        ];
+        let source = source(&source_directory, &events).await;
+        let target = FileEventRepository::new(&target_directory.0);
+        let result = EventMigration::new(source, target)
+            .with_upcasters(vec![Box::new(MoveEventUpcaster::new(
+                "SomethingWasDone",
+                "TestAggregate",
+                "OtherAggregate",
+            ))])
+            .migrate::<TestAggregate>()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "lz4")]
+    async fn migrate_compressed() {
+        use crate::persist::Compression;
+
+        let (source_directory, target_directory) = (TestDirectory::new(), TestDirectory::new());
+        let compressed = |sequence| {
+            let event = event("agg-1", sequence, TestEvents::SomethingWasDone);
+            SerializedEvent {
+                payload: Compression::Lz4.compress(&event.payload).unwrap(),
+                ..event.with_compression(Compression::Lz4)
+            }
+        };
+        let source = source(&source_directory, &[compressed(1), compressed(2)]).await;
+        let target = FileEventRepository::new(&target_directory.0);
+        EventMigration::new(source, target)
+            .with_upcasters(vec![Box::new(DropEventUpcaster::new("Started"))])
+            .migrate::<TestAggregate>()
+            .await
+            .unwrap();
+
+        let target = FileEventRepository::new(&target_directory.0);
+        let events = target.get_events::<TestAggregate>("agg-1").await.unwrap();
+        assert_eq!(2, events.len());
+        for event in events {
+            assert_eq!(Compression::Lz4, event.compression);
+            let event = event.decompress().unwrap();
+            assert_eq!(
+                TestEvents::SomethingWasDone,
+                serde_json::from_slice::<TestEvents>(&event.payload).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::persist::generic_query::{select_views, update_view_with_retry};
+use crate::persist::{
+    PersistenceError, QueryErrorHandler, UpdateRetryPolicy, ViewIdSelector, ViewRepository,
+};
+use crate::query::MultiSourceView;
+use crate::{Aggregate, EventEnvelope, Query};
+
+/// A query updating a `MultiSourceView` with the events of several aggregate types, returning the
+/// materialized views as a `GenericQuery` does for a single aggregate type.
+///
+/// The query may be dispatched the events of any aggregate type `A` whose envelopes convert into
+/// the view's event type `E`. Shared within an `Arc`, a single query can be registered on the
+/// `Cqrs` instance of each of these aggregate types.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use actuality::doc::setup::{Customer, CustomerService, MyAggregate, MyService};
+/// # use actuality::doc::persist::{
+/// #     MyDashboard, MyDashboardEvent, MyDashboardRepository, MyDatabaseConnection,
+/// # };
+/// use actuality::{Cqrs, MemoryStore};
+/// use actuality::persist::MultiSourceQuery;
+///
+/// # fn config(my_db_connection: MyDatabaseConnection) {
+/// let repo = Arc::new(MyDashboardRepository::new(my_db_connection));
+/// let query = Arc::new(MultiSourceQuery::<_, MyDashboard, MyDashboardEvent>::new(repo));
+///
+/// let customers = Cqrs::new(
+///     MemoryStore::<Customer>::default(),
+///     vec![Box::new(query.clone())],
+///     CustomerService,
+/// );
+/// let others = Cqrs::new(
+///     MemoryStore::<MyAggregate>::default(),
+///     vec![Box::new(query.clone())],
+///     MyService,
+/// );
+/// # }
+/// ```
+pub struct MultiSourceQuery<R, V, E>
+where
+    R: ViewRepository<V, E>,
+    V: MultiSourceView<E>,
+{
+    view_repository: Arc<R>,
+    retry_policy: UpdateRetryPolicy,
+    error_handler: Option<Box<QueryErrorHandler>>,
+    view_ids: Option<Box<ViewIdSelector<E>>>,
+    phantom: PhantomData<(V, E)>,
+}
+
+impl<R, V, E> MultiSourceQuery<R, V, E>
+where
+    R: ViewRepository<V, E>,
+    V: MultiSourceView<E>,
+{
+    /// Creates a new `MultiSourceQuery` using the provided `ViewRepository`.
+    pub fn new(view_repository: Arc<R>) -> Self {
+        Self {
+            view_repository,
+            retry_policy: UpdateRetryPolicy::default(),
+            error_handler: None,
+            view_ids: None,
+            phantom: Default::default(),
+        }
+    }
+
+    /// Allows the user to apply a custom error handler to the query, as with
+    /// [GenericQuery::use_error_handler](crate::persist::GenericQuery::use_error_handler).
+    pub fn use_error_handler(&mut self, error_handler: Box<QueryErrorHandler>) {
+        self.error_handler = Some(error_handler);
+    }
+
+    /// Configures how updates to a view are retried on an `OptimisticLockError`, by default they
+    /// are retried three times.
+    pub fn with_retry_policy(self, retry_policy: UpdateRetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Selects the ids of the views updated by each event, as with
+    /// [GenericQuery::with_view_ids](crate::persist::GenericQuery::with_view_ids). By default the
+    /// events update the view with the id of their aggregate instance.
+    ///
+    /// _Example: A dashboard of all customers alongside the dashboard of each._
+    /// ```
+    /// # use actuality::doc::persist::{MyDashboard, MyDashboardEvent, MyDashboardRepository};
+    /// # use actuality::persist::MultiSourceQuery;
+    /// # fn config(query: MultiSourceQuery<MyDashboardRepository, MyDashboard, MyDashboardEvent>) {
+    /// let query = query.with_view_ids(|event| match event {
+    ///     MyDashboardEvent::Customer(event) => {
+    ///         vec![event.aggregate_id.clone(), "all-customers".to_string()]
+    ///     }
+    ///     MyDashboardEvent::Other(event) => vec![event.aggregate_id.clone()],
+    /// });
+    /// # }
+    /// ```
+    pub fn with_view_ids<F>(self, view_ids: F) -> Self
+    where
+        F: Fn(&E) -> Vec<String> + Send + Sync + 'static,
+    {
+        Self {
+            view_ids: Some(Box::new(view_ids)),
+            ..self
+        }
+    }
+
+    /// Loads and deserializes a view based on the provided view id.
+    pub async fn load(&self, view_id: &str) -> Option<V> {
+        match self.view_repository.load_with_context(view_id).await {
+            Ok(option) => option.map(|(view, _)| view),
+            Err(e) => {
+                self.handle_error(e);
+                None
+            }
+        }
+    }
+
+    /// The view repository read from and updated.
+    pub fn view_repository(&self) -> Arc<R> {
+        self.view_repository.clone()
+    }
+
+    async fn apply_events(&self, view_id: &str, events: &[E]) -> Result<(), PersistenceError> {
+        update_view_with_retry(
+            self.view_repository.as_ref(),
+            &self.retry_policy,
+            view_id,
+            None,
+            events,
+            V::update,
+        )
+        .await?;
+        Ok(())
+    }
+
+    fn handle_error(&self, error: PersistenceError) {
+        if let Some(handler) = &self.error_handler {
+            (handler)(error);
+        }
+    }
+}
+
+#[async_trait]
+impl<R, V, E, A> Query<A> for MultiSourceQuery<R, V, E>
+where
+    R: ViewRepository<V, E>,
+    V: MultiSourceView<E>,
+    E: From<EventEnvelope<A>> + Send + Sync,
+    A: Aggregate,
+{
+    async fn dispatch(&self, view_id: &str, events: &[EventEnvelope<A>]) {
+        let converted: Vec<E> = events.iter().cloned().map(E::from).collect();
+        let selected = match &self.view_ids {
+            Some(view_ids) => select_views(view_ids.as_ref(), &converted)
+                .into_iter()
+                .map(|(view_id, selected)| {
+                    let events = selected
+                        .into_iter()
+                        .map(|i| E::from(events[i].clone()))
+                        .collect();
+                    (view_id, events)
+                })
+                .collect(),
+            None => vec![(view_id.to_string(), converted)],
+        };
+        for (view_id, events) in selected {
+            if let Err(err) = self.apply_events(&view_id, &events).await {
+                self.handle_error(err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::doc::setup::{
+        Customer, CustomerCommand, CustomerEvent, CustomerService, MyAggregate, MyCommands,
+        MyService,
+    };
+    use crate::persist::{MemoryViewRepository, MultiSourceQuery, ViewRepository};
+    use crate::query::MultiSourceView;
+    use crate::{Cqrs, EventEnvelope, MemoryStore};
+
+    enum DashboardEvent {
+        Customer(EventEnvelope<Customer>),
+        Other(EventEnvelope<MyAggregate>),
+    }
+
+    impl From<EventEnvelope<Customer>> for DashboardEvent {
+        fn from(event: EventEnvelope<Customer>) -> Self {
+            DashboardEvent::Customer(event)
+        }
+    }
+
+    impl From<EventEnvelope<MyAggregate>> for DashboardEvent {
+        fn from(event: EventEnvelope<MyAggregate>) -> Self {
+            DashboardEvent::Other(event)
+        }
+    }
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct Dashboard {
+        customer_name: Option<String>,
+        other_sequences: Vec<usize>,
+    }
+
+    impl MultiSourceView<DashboardEvent> for Dashboard {
+        fn update(&mut self, event: &DashboardEvent) {
+            match event {
+                DashboardEvent::Customer(event) => {
+                    if let CustomerEvent::NameAdded { name } = &event.payload {
+                        self.customer_name = Some(name.clone());
+                    }
+                }
+                DashboardEvent::Other(event) => self.other_sequences.push(event.sequence),
+            }
+        }
+    }
+
+    type DashboardRepository = MemoryViewRepository<Dashboard, DashboardEvent>;
+
+    #[tokio::test]
+    async fn dispatch_from_several_aggregates() {
+        let repo = Arc::new(DashboardRepository::default());
+        let query = Arc::new(MultiSourceQuery::<_, Dashboard, DashboardEvent>::new(repo));
+        let customers = Cqrs::new(
+            MemoryStore::<Customer>::default(),
+            vec![Box::new(query.clone())],
+            CustomerService,
+        );
+        let others = Cqrs::new(
+            MemoryStore::<MyAggregate>::default(),
+            vec![Box::new(query.clone())],
+            MyService,
+        );
+
+        customers
+            .execute(
+                "view-A",
+                CustomerCommand::AddCustomerName {
+                    name: "John Doe".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        others
+            .execute("view-A", MyCommands::DoSomething)
+            .await
+            .unwrap();
+        others
+            .execute("view-A", MyCommands::DoSomething)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(Dashboard {
+                customer_name: Some("John Doe".to_string()),
+                other_sequences: vec![1, 2],
+            }),
+            query.load("view-A").await
+        );
+        assert_eq!(
+            3,
+            query
+                .view_repository()
+                .load_with_context("view-A")
+                .await
+                .unwrap()
+                .unwrap()
+                .1
+                .version
+        );
+    }
+
+    #[tokio::test]
+    async fn dispatch_to_selected_views() {
+        let repo = Arc::new(DashboardRepository::default());
+        let query = Arc::new(
+            MultiSourceQuery::<_, Dashboard, DashboardEvent>::new(repo).with_view_ids(|event| {
+                match event {
+                    DashboardEvent::Customer(event) => {
+                        vec![event.aggregate_id.clone(), "all".to_string()]
+                    }
+                    DashboardEvent::Other(_) => vec!["all".to_string()],
+                }
+            }),
+        );
+        let customers = Cqrs::new(
+            MemoryStore::<Customer>::default(),
+            vec![Box::new(query.clone())],
+            CustomerService,
+        );
+        let others = Cqrs::new(
+            MemoryStore::<MyAggregate>::default(),
+            vec![Box::new(query.clone())],
+            MyService,
+        );
+
+        customers
+            .execute(
+                "customer-A",
+                CustomerCommand::AddCustomerName {
+                    name: "John Doe".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        others
+            .execute("other-A", MyCommands::DoSomething)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            Some(Dashboard {
+                customer_name: Some("John Doe".to_string()),
+                other_sequences: vec![],
+            }),
+            query.load("customer-A").await
+        );
+        assert_eq!(
+            Some(Dashboard {
+                customer_name: Some("John Doe".to_string()),
+                other_sequences: vec![1],
+            }),
+            query.load("all").await
+        );
+        assert_eq!(None, query.load("other-A").await);
+    }
+}
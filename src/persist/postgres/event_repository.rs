@@ -0,0 +1,684 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{AsyncMessage, NoTls, Row};
+
+use crate::persist::event_stream::ReplayStream;
+use crate::persist::postgres::{PostgresDatabase, NOTIFY_CHANNEL};
+use crate::persist::{
+    EventFilter, PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot,
+};
+use crate::Aggregate;
+
+const DEFAULT_STREAM_CHANNEL_SIZE: usize = 200;
+
+const SELECT_EVENTS: &str = "SELECT position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, format, compression
+  FROM events";
+
+/// A PostgreSQL backed event repository for use in backing a `PersistedEventStore`.
+pub struct PostgresEventRepository {
+    database: PostgresDatabase,
+    stream_channel_size: usize,
+}
+
+/// The payload of a notification sent by the `events` table trigger.
+#[derive(Deserialize)]
+struct EventNotification {
+    aggregate_type: String,
+    position: i64,
+}
+
+impl PostgresEventRepository {
+    /// Creates a new `PostgresEventRepository` using the provided database.
+    ///
+    /// ```
+    /// use actuality::persist::postgres::{PostgresDatabase, PostgresEventRepository};
+    ///
+    /// fn configure_repo(database: PostgresDatabase) -> PostgresEventRepository {
+    ///     PostgresEventRepository::new(database)
+    /// }
+    /// ```
+    pub fn new(database: PostgresDatabase) -> Self {
+        Self {
+            database,
+            stream_channel_size: DEFAULT_STREAM_CHANNEL_SIZE,
+        }
+    }
+
+    /// Configures the number of rows fetched from the server-side cursor and buffered in a
+    /// `ReplayStream` at a time, defaults to 200.
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    /// Subscribes to events of the aggregate type as they are committed by any process using
+    /// this database, via `LISTEN`/`NOTIFY`.
+    ///
+    /// Only events committed after the subscription is established are delivered, a consumer
+    /// wanting to catch up first should stream existing events with `stream_all_events`. The
+    /// subscription holds a dedicated connection, outside of the pool, until the stream is
+    /// dropped and a following event has been committed.
+    pub async fn subscribe<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        let (client, mut connection) = self.database.config.connect(NoTls).await?;
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        if notifier.send(notification).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(_) => return,
+                }
+            }
+        });
+        client
+            .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+            .await?;
+
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        let aggregate_type = A::aggregate_type();
+        tokio::spawn(async move {
+            let select = format!("{} WHERE position = $1", SELECT_EVENTS);
+            while let Some(notification) = notifications.recv().await {
+                let result = match serde_json::from_str::<EventNotification>(notification.payload())
+                {
+                    Ok(notice) if notice.aggregate_type != aggregate_type => continue,
+                    Ok(notice) => match client.query_one(&select, &[&notice.position]).await {
+                        Ok(row) => read_event(&row).map(|(_, event)| event),
+                        Err(err) => Err(err.into()),
+                    },
+                    Err(err) => Err(err.into()),
+                };
+                if feed.push(result).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(stream)
+    }
+
+    async fn select_events(
+        &self,
+        aggregate_type: &str,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let client = self.database.client().await?;
+        let statement = client
+            .prepare_cached(&format!(
+                "{} WHERE aggregate_type = $1 AND aggregate_id = $2 AND sequence > $3 ORDER BY sequence",
+                SELECT_EVENTS
+            ))
+            .await?;
+        let rows = client
+            .query(
+                &statement,
+                &[&aggregate_type, &aggregate_id, &(last_sequence as i64)],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| read_event(row).map(|(_, event)| event))
+            .collect()
+    }
+
+    /// Streams matching events through a server-side cursor, fetching one page at a time as
+    /// the consumer makes room in the `ReplayStream`.
+    fn stream(&self, aggregate_type: String, filter: EventFilter) -> ReplayStream {
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        let database = self.database.clone();
+        let page_size = self.stream_channel_size.max(1) as i32;
+        tokio::spawn(async move {
+            let result: Result<(), PersistenceError> = async {
+                let mut client = database.client().await?;
+                let tx = client.transaction().await?;
+                let mut params: Vec<Box<dyn ToSql + Sync + Send>> = vec![Box::new(aggregate_type)];
+                let conditions = filter_conditions(&filter, &mut params);
+                let sql = format!(
+                    "{} WHERE aggregate_type = $1{} ORDER BY position",
+                    SELECT_EVENTS, conditions
+                );
+                let params: Vec<&(dyn ToSql + Sync)> = params
+                    .iter()
+                    .map(|param| param.as_ref() as &(dyn ToSql + Sync))
+                    .collect();
+                let portal = tx.bind(sql.as_str(), &params).await?;
+                loop {
+                    let page = tx.query_portal(&portal, page_size).await?;
+                    let complete = (page.len() as i32) < page_size;
+                    for row in &page {
+                        let (_, event) = read_event(row)?;
+                        if feed.push(Ok(event)).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                    if complete {
+                        return Ok(());
+                    }
+                }
+            }
+            .await;
+            if let Err(err) = result {
+                let _ = feed.push(Err(err)).await;
+            }
+        });
+        stream
+    }
+}
+
+#[async_trait]
+impl PersistedEventRepository for PostgresEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events(&A::aggregate_type(), aggregate_id, 0)
+            .await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events(&A::aggregate_type(), aggregate_id, last_sequence)
+            .await
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let client = self.database.client().await?;
+        let row = client
+            .query_opt(
+                "SELECT last_sequence, snapshot_version, payload, format, compression
+                   FROM snapshots
+                  WHERE aggregate_type = $1 AND aggregate_id = $2",
+                &[&A::aggregate_type(), &aggregate_id],
+            )
+            .await?;
+        Ok(match row {
+            None => None,
+            Some(row) => Some(SerializedSnapshot {
+                aggregate_id: aggregate_id.to_string(),
+                format: row.try_get::<_, &str>(3)?.parse()?,
+                compression: row.try_get::<_, &str>(4)?.parse()?,
+                aggregate: row.try_get(2)?,
+                current_sequence: row.try_get::<_, i64>(0)? as usize,
+                current_snapshot: row.try_get::<_, i64>(1)? as usize,
+            }),
+        })
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<SerializedSnapshot>,
+    ) -> Result<(), PersistenceError> {
+        let aggregate_type = A::aggregate_type();
+        let mut client = self.database.client().await?;
+        let tx = client.transaction().await?;
+        let insert = tx
+            .prepare_cached(
+                "INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, format, compression)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .await?;
+        for event in events {
+            tx.execute(
+                &insert,
+                &[
+                    &aggregate_type,
+                    &event.aggregate_id,
+                    &(event.sequence as i64),
+                    &event.event_type,
+                    &event.event_version,
+                    &event.payload,
+                    &event.metadata,
+                    &event.format.as_str(),
+                    &event.compression.as_str(),
+                ],
+            )
+            .await?;
+        }
+        if let Some(snapshot) = snapshot_update {
+            let current_snapshot = snapshot.current_snapshot as i64;
+            let updated = if current_snapshot == 1 {
+                tx.execute(
+                    "INSERT INTO snapshots (aggregate_type, aggregate_id, last_sequence, snapshot_version, payload, format, compression)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                    &[
+                        &aggregate_type,
+                        &snapshot.aggregate_id,
+                        &(snapshot.current_sequence as i64),
+                        &current_snapshot,
+                        &snapshot.aggregate,
+                        &snapshot.format.as_str(),
+                        &snapshot.compression.as_str(),
+                    ],
+                )
+                .await?
+            } else {
+                tx.execute(
+                    "UPDATE snapshots
+                        SET last_sequence = $3, snapshot_version = $4, payload = $5, format = $6, compression = $7
+                      WHERE aggregate_type = $1 AND aggregate_id = $2 AND snapshot_version = $8",
+                    &[
+                        &aggregate_type,
+                        &snapshot.aggregate_id,
+                        &(snapshot.current_sequence as i64),
+                        &current_snapshot,
+                        &snapshot.aggregate,
+                        &snapshot.format.as_str(),
+                        &snapshot.compression.as_str(),
+                        &(current_snapshot - 1),
+                    ],
+                )
+                .await?
+            };
+            if updated != 1 {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let filter = EventFilter::default().with_aggregate_ids([aggregate_id]);
+        Ok(self.stream(A::aggregate_type(), filter))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream(A::aggregate_type(), EventFilter::default()))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &EventFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream(A::aggregate_type(), filter.clone()))
+    }
+}
+
+/// Renders the filter as conditions appended to a `WHERE` clause, adding the parameters they
+/// bind.
+fn filter_conditions(
+    filter: &EventFilter,
+    params: &mut Vec<Box<dyn ToSql + Sync + Send>>,
+) -> String {
+    let mut conditions = String::new();
+    let mut bind = |param: Box<dyn ToSql + Sync + Send>| {
+        params.push(param);
+        format!("${}", params.len())
+    };
+    for (column, values) in [
+        ("aggregate_id", &filter.aggregate_ids),
+        ("event_type", &filter.event_types),
+    ] {
+        if let Some(values) = values {
+            let values = bind(Box::new(values.clone()));
+            conditions.push_str(&format!(" AND {} = ANY({})", column, values));
+        }
+    }
+    for (condition, bound) in [
+        (
+            "sequence >=",
+            filter
+                .min_sequence
+                .map(|sequence| i64::try_from(sequence).unwrap_or(i64::MAX)),
+        ),
+        (
+            "sequence <=",
+            filter
+                .max_sequence
+                .map(|sequence| i64::try_from(sequence).unwrap_or(i64::MAX)),
+        ),
+        ("position >=", filter.min_position),
+        ("position <=", filter.max_position),
+    ] {
+        if let Some(bound) = bound {
+            conditions.push_str(&format!(" AND {} {}", condition, bind(Box::new(bound))));
+        }
+    }
+    for (condition, time) in [
+        ("committed_at >=", &filter.committed_from),
+        ("committed_at <", &filter.committed_until),
+    ] {
+        if let Some(time) = time {
+            let time = bind(Box::new(time.to_rfc3339()));
+            conditions.push_str(&format!(" AND {} {}::text::timestamptz", condition, time));
+        }
+    }
+    conditions
+}
+
+fn read_event(row: &Row) -> Result<(i64, SerializedEvent), PersistenceError> {
+    let event = SerializedEvent::new(
+        row.try_get(2)?,
+        row.try_get::<_, i64>(3)? as usize,
+        row.try_get(1)?,
+        row.try_get(4)?,
+        row.try_get(5)?,
+        row.try_get(6)?,
+        row.try_get(7)?,
+    )
+    .with_format(row.try_get::<_, &str>(8)?.parse()?)
+    .with_compression(row.try_get::<_, &str>(9)?.parse()?);
+    Ok((row.try_get(0)?, event))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use serde_json::json;
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, test_serialized_snapshot, TestAggregate, TestEvents,
+        TEST_AGGREGATE_ID,
+    };
+    use crate::persist::postgres::shared_test::TestCluster;
+    use crate::persist::postgres::PostgresEventRepository;
+    use crate::persist::{
+        Compression, EventFilter, PersistedEventRepository, PersistenceError, SerializationFormat,
+        SerializedEvent, SerializedSnapshot,
+    };
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        let mut event = test_serialized_event(sequence, TestEvents::SomethingWasDone);
+        event.aggregate_id = aggregate_id.to_string();
+        event
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn persist_and_load() {
+        let cluster = TestCluster::start();
+        let repo = PostgresEventRepository::new(cluster.database().await);
+        repo.persist::<TestAggregate>(
+            &[
+                event(TEST_AGGREGATE_ID, 1),
+                event(TEST_AGGREGATE_ID, 2),
+                event("other", 1),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![event(TEST_AGGREGATE_ID, 1), event(TEST_AGGREGATE_ID, 2)],
+            events
+        );
+        let events = repo
+            .get_last_events::<TestAggregate>(TEST_AGGREGATE_ID, 1)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 2)], events);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn binary_payloads() {
+        let cluster = TestCluster::start();
+        let repo = PostgresEventRepository::new(cluster.database().await);
+        let event = event(TEST_AGGREGATE_ID, 1)
+            .with_format(SerializationFormat::Postcard)
+            .with_compression(Compression::Zstd);
+        let event = SerializedEvent {
+            payload: vec![0, 159, 146, 150],
+            ..event
+        };
+        let snapshot = || SerializedSnapshot {
+            compression: Compression::Lz4,
+            aggregate: vec![0, 159, 146, 150],
+            ..test_serialized_snapshot(TEST_AGGREGATE_ID, 1, 1, 1)
+        };
+        repo.persist::<TestAggregate>(std::slice::from_ref(&event), Some(snapshot()))
+            .await
+            .unwrap();
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event], events);
+        let stored = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(Some(snapshot()), stored);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn optimistic_lock() {
+        let cluster = TestCluster::start();
+        let repo = PostgresEventRepository::new(cluster.database().await);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, 2), event(TEST_AGGREGATE_ID, 1)],
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        // the failed commit is rolled back in its entirety
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 1)], events);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn snapshots() {
+        let cluster = TestCluster::start();
+        let repo = PostgresEventRepository::new(cluster.database().await);
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 1), event(TEST_AGGREGATE_ID, 2)],
+            Some(test_serialized_snapshot(TEST_AGGREGATE_ID, 2, 2, 1)),
+        )
+        .await
+        .unwrap();
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_vec(&json!({"something_happened": 2})).unwrap(),
+            snapshot.aggregate
+        );
+        assert_eq!(2, snapshot.current_sequence);
+        assert_eq!(1, snapshot.current_snapshot);
+
+        // a stale snapshot version is rejected
+        let result = repo
+            .persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, 3)],
+                Some(test_serialized_snapshot(TEST_AGGREGATE_ID, 3, 3, 3)),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn stream_events() {
+        let cluster = TestCluster::start();
+        let repo =
+            PostgresEventRepository::new(cluster.database().await).with_streaming_channel_size(2);
+        for sequence in 1..=3 {
+            repo.persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, sequence), event("other", sequence)],
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut stream = repo.stream_all_events::<TestAggregate>().await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            let event = event.unwrap();
+            found.push((event.aggregate_id, event.sequence));
+        }
+        assert_eq!(6, found.len());
+        assert_eq!((TEST_AGGREGATE_ID.to_string(), 1), found[0]);
+        assert_eq!(("other".to_string(), 3), found[5]);
+
+        let mut stream = repo.stream_events::<TestAggregate>("other").await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            found.push(event.unwrap().sequence);
+        }
+        assert_eq!(vec![1, 2, 3], found);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn stream_filtered_events() {
+        let cluster = TestCluster::start();
+        let repo =
+            PostgresEventRepository::new(cluster.database().await).with_streaming_channel_size(2);
+        for sequence in 1..=3 {
+            repo.persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, sequence), event("other", sequence)],
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let found = |filter: EventFilter| {
+            let repo = &repo;
+            async move {
+                let mut stream = repo
+                    .stream_filtered_events::<TestAggregate>(&filter)
+                    .await
+                    .unwrap();
+                let mut found = Vec::new();
+                while let Some(event) = stream.next::<TestAggregate>().await {
+                    let event = event.unwrap();
+                    found.push((event.aggregate_id, event.sequence));
+                }
+                found
+            }
+        };
+        let filter = EventFilter::default()
+            .with_aggregate_ids(["other"])
+            .with_sequences(2..);
+        assert_eq!(
+            vec![("other".to_string(), 2), ("other".to_string(), 3)],
+            found(filter).await
+        );
+        let filter = EventFilter::default().with_positions(2..=3);
+        assert_eq!(
+            vec![("other".to_string(), 1), (TEST_AGGREGATE_ID.to_string(), 2)],
+            found(filter).await
+        );
+        let filter = EventFilter::default().with_event_types(["Started"]);
+        assert!(found(filter).await.is_empty());
+
+        let now = Utc::now();
+        let filter = EventFilter::default().with_committed_between(
+            now - chrono::Duration::hours(1),
+            now + chrono::Duration::hours(1),
+        );
+        assert_eq!(6, found(filter).await.len());
+        let filter = EventFilter::default().with_committed_between(
+            now - chrono::Duration::hours(2),
+            now - chrono::Duration::hours(1),
+        );
+        assert!(found(filter).await.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn legacy_events_outside_commit_window() {
+        let cluster = TestCluster::start();
+        let database = cluster.database().await;
+        // revert the commit time migration to commit an event as before it was applied
+        database
+            .client()
+            .await
+            .unwrap()
+            .batch_execute(
+                "ALTER TABLE events DROP COLUMN committed_at;
+                 DELETE FROM actuality_migrations WHERE version = 6;
+                 INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata)
+                 VALUES ('TestAggregate', 'legacy', 1, 'SomethingWasDone', '1.0', '\\x6e756c6c', '{}');",
+            )
+            .await
+            .unwrap();
+        database.migrate().await.unwrap();
+        let repo = PostgresEventRepository::new(database);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let filter = EventFilter::default().with_committed_between(
+            now - chrono::Duration::hours(1),
+            now + chrono::Duration::hours(1),
+        );
+        let mut stream = repo
+            .stream_filtered_events::<TestAggregate>(&filter)
+            .await
+            .unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            found.push(event.unwrap().aggregate_id);
+        }
+        assert_eq!(vec![TEST_AGGREGATE_ID.to_string()], found);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn subscribe() {
+        let cluster = TestCluster::start();
+        let repo = PostgresEventRepository::new(cluster.database().await);
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+
+        let mut subscription = repo.subscribe::<TestAggregate>().await.unwrap();
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 2), event(TEST_AGGREGATE_ID, 3)],
+            None,
+        )
+        .await
+        .unwrap();
+        for expected in [2, 3] {
+            let event =
+                tokio::time::timeout(Duration::from_secs(5), subscription.next::<TestAggregate>())
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(expected, event.sequence);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::persist::postgres::PostgresDatabase;
+use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+
+/// A PostgreSQL backed query repository for use in backing a `GenericQuery` or a
+/// `MultiSourceQuery`.
+///
+/// Each view is stored in its own table, created when the repository is constructed, holding
+/// the serialized view along with the version used for optimistic locking.
+pub struct PostgresViewRepository<V, A> {
+    database: PostgresDatabase,
+    insert_sql: String,
+    update_sql: String,
+    select_sql: String,
+    phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> PostgresViewRepository<V, A> {
+    /// Creates a new `PostgresViewRepository` that will store serialized views in a table named
+    /// identically to the `view_name` value provided. The table is created if it does not
+    /// already exist.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyView;
+    /// use actuality::persist::PersistenceError;
+    /// use actuality::persist::postgres::{PostgresDatabase, PostgresViewRepository};
+    ///
+    /// async fn configure_view_repo(
+    ///     database: PostgresDatabase,
+    /// ) -> Result<PostgresViewRepository<MyView, MyAggregate>, PersistenceError> {
+    ///     PostgresViewRepository::new("my_view_table", database).await
+    /// }
+    /// ```
+    pub async fn new(
+        view_name: &str,
+        database: PostgresDatabase,
+    ) -> Result<Self, PersistenceError> {
+        if view_name.is_empty()
+            || !view_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(PersistenceError::UnknownError(
+                format!("invalid view name '{}'", view_name).into(),
+            ));
+        }
+        let create_sql = format!(
+            "CREATE TABLE IF NOT EXISTS {}
+             (
+                 view_id TEXT   PRIMARY KEY,
+                 version BIGINT NOT NULL CHECK (version >= 0),
+                 payload JSONB  NOT NULL
+             )",
+            view_name
+        );
+        database.client().await?.batch_execute(&create_sql).await?;
+        Ok(Self {
+            insert_sql: format!(
+                "INSERT INTO {} (payload, version, view_id) VALUES ($1, $2, $3)",
+                view_name
+            ),
+            update_sql: format!(
+                "UPDATE {} SET payload = $1, version = $2 WHERE view_id = $3 AND version = $4",
+                view_name
+            ),
+            select_sql: format!(
+                "SELECT version, payload FROM {} WHERE view_id = $1",
+                view_name
+            ),
+            database,
+            phantom: Default::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl<V, A> ViewRepository<V, A> for PostgresViewRepository<V, A>
+where
+    V: Serialize + DeserializeOwned + Send + Sync,
+    A: Send + Sync,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        let client = self.database.client().await?;
+        let row = client.query_opt(&self.select_sql, &[&view_id]).await?;
+        match row {
+            None => Ok(None),
+            Some(row) => {
+                let version: i64 = row.try_get(0)?;
+                let view = serde_json::from_value(row.try_get(1)?)?;
+                Ok(Some((view, ViewContext::new(view_id.to_string(), version))))
+            }
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        let payload = serde_json::to_value(&view)?;
+        let version = context.version + 1;
+        let client = self.database.client().await?;
+        let updated = if context.version == 0 {
+            client
+                .execute(
+                    &self.insert_sql,
+                    &[&payload, &version, &context.view_instance_id],
+                )
+                .await?
+        } else {
+            client
+                .execute(
+                    &self.update_sql,
+                    &[
+                        &payload,
+                        &version,
+                        &context.view_instance_id,
+                        &context.version,
+                    ],
+                )
+                .await?
+        };
+        if updated != 1 {
+            return Err(PersistenceError::OptimisticLockError);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::event_store::shared_test::{TestAggregate, TestEvents};
+    use crate::persist::postgres::shared_test::TestCluster;
+    use crate::persist::postgres::PostgresViewRepository;
+    use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+    use crate::{EventEnvelope, View};
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct TestView {
+        events: usize,
+    }
+
+    impl View<TestAggregate> for TestView {
+        fn update(&mut self, event: &EventEnvelope<TestAggregate>) {
+            if event.payload == TestEvents::SomethingWasDone {
+                self.events += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn update_views() {
+        let cluster = TestCluster::start();
+        let repo = PostgresViewRepository::<TestView, TestAggregate>::new(
+            "test_view",
+            cluster.database().await,
+        )
+        .await
+        .unwrap();
+        assert!(repo.load("view-A").await.unwrap().is_none());
+
+        repo.update_view(
+            TestView { events: 1 },
+            ViewContext::new("view-A".to_string(), 0),
+        )
+        .await
+        .unwrap();
+        let (view, context) = repo.load_with_context("view-A").await.unwrap().unwrap();
+        assert_eq!(TestView { events: 1 }, view);
+        assert_eq!(1, context.version);
+
+        repo.update_view(TestView { events: 2 }, context)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(TestView { events: 2 }),
+            repo.load("view-A").await.unwrap()
+        );
+
+        let result = repo
+            .update_view(
+                TestView { events: 3 },
+                ViewContext::new("view-A".to_string(), 1),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL"]
+    async fn invalid_view_name() {
+        let cluster = TestCluster::start();
+        let result = PostgresViewRepository::<TestView, TestAggregate>::new(
+            "views; DROP TABLE events",
+            cluster.database().await,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
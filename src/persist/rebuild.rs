@@ -0,0 +1,531 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::persist::generic_query::RebuildEvents;
+use crate::persist::{
+    CancellationToken, EncryptedFields, EventSerializer, EventUpcaster, GenericQuery, KeyStore,
+    PersistedEventRepository, PersistenceError, QueryReplay, ReplayErrorPolicy,
+    ReplayProgressHandler, ViewRepository,
+};
+use crate::{Aggregate, AggregateError, EventEnvelope, Query, View};
+
+/// Rebuilds the views of a `GenericQuery` in a shadow view repository, then replaces the view
+/// repository the query loads from and updates, so that readers never see partially rebuilt
+/// views.
+///
+/// While the rebuild runs the query continues to update the live views, and the events
+/// dispatched to it are recorded. Once all events have been replayed into the shadow
+/// repository the recorded events are applied to it, skipping any already replayed, until it
+/// has caught up with the live views. The last recorded events are then applied and the
+/// repositories swapped, with events dispatched meanwhile held back until the swap is
+/// complete.
+///
+/// The shadow repository should hold no views when the rebuild starts. Should the rebuild
+/// fail, the query continues with its current view repository.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use actuality::doc::setup::{MyAggregate, MyRepository};
+/// # use actuality::doc::persist::{MyView, MyViewRepository};
+/// use actuality::persist::{GenericQuery, ViewRebuild};
+///
+/// async fn rebuild(
+///     events: MyRepository,
+///     query: Arc<GenericQuery<MyViewRepository, MyView, MyAggregate>>,
+///     shadow: MyViewRepository,
+/// ) {
+///     let rebuild = ViewRebuild::new(events, query, Arc::new(shadow)).with_workers(8);
+///     let _retired = rebuild.run().await.unwrap();
+/// }
+/// ```
+pub struct ViewRebuild<E, R, V, A>
+where
+    E: PersistedEventRepository,
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    replay: QueryReplay<E, ShadowQuery<R, V, A>, A>,
+    query: Arc<GenericQuery<R, V, A>>,
+    shadow: Arc<R>,
+}
+
+impl<E, R, V, A> ViewRebuild<E, R, V, A>
+where
+    E: PersistedEventRepository,
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Creates a rebuild replaying the events of the repository into the shadow view
+    /// repository, which then replaces the view repository of the query.
+    pub fn new(repository: E, query: Arc<GenericQuery<R, V, A>>, shadow: Arc<R>) -> Self {
+        let error: Arc<Mutex<Option<PersistenceError>>> = Default::default();
+        let mut shadow_query = GenericQuery::new(shadow.clone()).with_view_ids_of(&query);
+        shadow_query.use_error_handler(record_error(&error));
+        let replay = QueryReplay::new(
+            repository,
+            ShadowQuery {
+                query: shadow_query,
+                sequences: Default::default(),
+                error,
+            },
+        )
+        .with_error_policy(ReplayErrorPolicy::FailFast);
+        Self {
+            replay,
+            query,
+            shadow,
+        }
+    }
+
+    /// Configures the replay to upcast events, see `QueryReplay::with_upcasters`.
+    pub fn with_upcasters(self, upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        Self {
+            replay: self.replay.with_upcasters(upcasters),
+            ..self
+        }
+    }
+
+    /// Configures the serializer used to read the replayed events, see
+    /// `QueryReplay::with_serializer`.
+    pub fn with_serializer<S>(self, serializer: S) -> Self
+    where
+        S: EventSerializer<A::Event> + 'static,
+    {
+        Self {
+            replay: self.replay.with_serializer(serializer),
+            ..self
+        }
+    }
+
+    /// Configures the replay to decrypt the marked fields of events, see
+    /// `QueryReplay::with_encryption`.
+    pub fn with_encryption(
+        self,
+        key_store: Arc<dyn KeyStore>,
+        fields: Vec<EncryptedFields>,
+    ) -> Self {
+        Self {
+            replay: self.replay.with_encryption(key_store, fields),
+            ..self
+        }
+    }
+
+    /// Configures the number of replay workers, see `QueryReplay::with_workers`.
+    pub fn with_workers(self, workers: usize) -> Self {
+        Self {
+            replay: self.replay.with_workers(workers),
+            ..self
+        }
+    }
+
+    /// Configures the replay batch size, see `QueryReplay::with_batch_size`.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        Self {
+            replay: self.replay.with_batch_size(batch_size),
+            ..self
+        }
+    }
+
+    /// Configures a handler to receive the progress of the replay.
+    pub fn with_progress_handler(self, progress_handler: Box<ReplayProgressHandler>) -> Self {
+        Self {
+            replay: self.replay.with_progress_handler(progress_handler),
+            ..self
+        }
+    }
+
+    /// Configures a token with which the rebuild may be stopped before the view repositories
+    /// are swapped, the rebuild then returning an error.
+    pub fn with_cancellation(self, cancellation: CancellationToken) -> Self {
+        Self {
+            replay: self.replay.with_cancellation(cancellation),
+            ..self
+        }
+    }
+}
+
+impl<E, R, V, A> ViewRebuild<E, R, V, A>
+where
+    E: PersistedEventRepository,
+    R: ViewRepository<V, A> + 'static,
+    V: View<A> + 'static,
+    A: Aggregate + 'static,
+{
+    /// Rebuilds the views and swaps the view repositories, returning the view repository
+    /// replaced.
+    pub async fn run(&self) -> Result<Arc<R>, AggregateError<A::Error>> {
+        if !self.query.begin_rebuild().await {
+            return Err(PersistenceError::UnknownError(
+                "a rebuild of this query is already in progress".into(),
+            )
+            .into());
+        }
+        let result = self.rebuild().await;
+        if result.is_err() {
+            self.query.abort_rebuild().await;
+        }
+        result
+    }
+
+    async fn rebuild(&self) -> Result<Arc<R>, AggregateError<A::Error>> {
+        let report = self.replay.replay_all().await?;
+        if report.cancelled {
+            return Err(PersistenceError::UnknownError("the rebuild was cancelled".into()).into());
+        }
+        let shadow_query = self.replay.query();
+        shadow_query.take_error()?;
+        loop {
+            let events = self.query.take_rebuild_events().await;
+            if events.is_empty() {
+                break;
+            }
+            shadow_query.catch_up(events).await?;
+        }
+        let replaced = self
+            .query
+            .complete_rebuild(self.shadow.clone(), |events| shadow_query.catch_up(events))
+            .await?;
+        Ok(replaced)
+    }
+}
+
+fn record_error(
+    error: &Arc<Mutex<Option<PersistenceError>>>,
+) -> Box<dyn Fn(PersistenceError) + Send + Sync> {
+    let error = error.clone();
+    Box::new(move |e| {
+        error.lock().unwrap().get_or_insert(e);
+    })
+}
+
+/// Updates the shadow views, applying each event of an aggregate instance only once whether
+/// replayed or recorded from the live query.
+///
+/// Replayed events are applied as they are, an upcaster may split an event into several
+/// sharing its sequence. Recorded events are skipped where their sequence was replayed.
+struct ShadowQuery<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    query: GenericQuery<R, V, A>,
+    /// The last sequence replayed for each aggregate instance.
+    sequences: Mutex<HashMap<String, usize>>,
+    error: Arc<Mutex<Option<PersistenceError>>>,
+}
+
+impl<R, V, A> ShadowQuery<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Applies the events recorded from the live query that were not replayed.
+    async fn catch_up(&self, events: RebuildEvents<A>) -> Result<(), PersistenceError> {
+        for (view_id, events) in events {
+            let events: Vec<EventEnvelope<A>> = {
+                let sequences = self.sequences.lock().unwrap();
+                events
+                    .into_iter()
+                    .filter(|event| {
+                        sequences
+                            .get(&event.aggregate_id)
+                            .is_none_or(|sequence| event.sequence > *sequence)
+                    })
+                    .collect()
+            };
+            if !events.is_empty() {
+                self.query.dispatch(&view_id, &events).await;
+            }
+        }
+        self.take_error()
+    }
+
+    fn take_error(&self) -> Result<(), PersistenceError> {
+        match self.error.lock().unwrap().take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R, V, A> Query<A> for ShadowQuery<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    async fn dispatch(&self, view_id: &str, events: &[EventEnvelope<A>]) {
+        {
+            let mut sequences = self.sequences.lock().unwrap();
+            for event in events {
+                let sequence = sequences.entry(event.aggregate_id.clone()).or_default();
+                *sequence = event.sequence.max(*sequence);
+            }
+        }
+        self.query.dispatch(view_id, events).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use crate::doc::setup::{MyAggregate, MyEvents};
+    use crate::persist::encryption::FieldEncryption;
+    use crate::persist::event_store::shared_test::MockRepo;
+    use crate::persist::{
+        CancellationToken, EncryptedFields, EventUpcaster, GenericQuery, InMemoryKeyStore,
+        KeyStore, MemoryViewRepository, PersistedEventRepository, PersistenceError, ReplayStream,
+        SerializedEvent, SerializedSnapshot, ViewRebuild, ViewRepository,
+    };
+    use crate::{Aggregate, EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct CountView {
+        sequences: Vec<usize>,
+    }
+
+    impl View<MyAggregate> for CountView {
+        fn update(&mut self, event: &EventEnvelope<MyAggregate>) {
+            self.sequences.push(event.sequence);
+        }
+    }
+
+    type CountRepository = MemoryViewRepository<CountView, MyAggregate>;
+
+    type CountQuery = GenericQuery<CountRepository, CountView, MyAggregate>;
+
+    /// Dispatches the live events to the query as the replay begins, as though committed while
+    /// the rebuild runs.
+    struct LiveRepo {
+        repo: MockRepo,
+        query: Arc<CountQuery>,
+        live: Vec<EventEnvelope<MyAggregate>>,
+    }
+
+    #[async_trait]
+    impl PersistedEventRepository for LiveRepo {
+        async fn get_events<A: Aggregate>(
+            &self,
+            aggregate_id: &str,
+        ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+            self.repo.get_events::<A>(aggregate_id).await
+        }
+        async fn get_last_events<A: Aggregate>(
+            &self,
+            aggregate_id: &str,
+            last_sequence: usize,
+        ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+            self.repo
+                .get_last_events::<A>(aggregate_id, last_sequence)
+                .await
+        }
+        async fn get_snapshot<A: Aggregate>(
+            &self,
+            aggregate_id: &str,
+        ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+            self.repo.get_snapshot::<A>(aggregate_id).await
+        }
+        async fn persist<A: Aggregate>(
+            &self,
+            events: &[SerializedEvent],
+            snapshot_update: Option<SerializedSnapshot>,
+        ) -> Result<(), PersistenceError> {
+            self.repo.persist::<A>(events, snapshot_update).await
+        }
+        async fn stream_events<A: Aggregate>(
+            &self,
+            aggregate_id: &str,
+        ) -> Result<ReplayStream, PersistenceError> {
+            self.repo.stream_events::<A>(aggregate_id).await
+        }
+        async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+            for event in &self.live {
+                self.query
+                    .dispatch(&event.aggregate_id, std::slice::from_ref(event))
+                    .await;
+            }
+            self.repo.stream_all_events::<A>().await
+        }
+    }
+
+    fn envelope(aggregate_id: &str, sequence: usize) -> EventEnvelope<MyAggregate> {
+        EventEnvelope {
+            aggregate_id: aggregate_id.to_string(),
+            event_type: "".to_string(),
+            sequence,
+            system_id: "".to_string(),
+            payload: MyEvents::SomethingWasDone,
+            metadata: Default::default(),
+        }
+    }
+
+    fn serialized(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        SerializedEvent::try_from(&envelope(aggregate_id, sequence)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rebuild() {
+        let live_repository = Arc::new(CountRepository::default());
+        let query = Arc::new(CountQuery::new(live_repository.clone()));
+        // a live view missing its first event, to be corrected by the rebuild
+        query.dispatch("agg-A", &[envelope("agg-A", 2)]).await;
+
+        let events = MockRepo::with_events(Ok(vec![
+            serialized("agg-A", 1),
+            serialized("agg-A", 2),
+            serialized("agg-B", 1),
+        ]));
+        let repo = LiveRepo {
+            repo: events,
+            query: query.clone(),
+            live: vec![envelope("agg-A", 2), envelope("agg-A", 3)],
+        };
+        let shadow = Arc::new(CountRepository::default());
+        let rebuild = ViewRebuild::new(repo, query.clone(), shadow.clone()).with_workers(2);
+        let replaced = rebuild.run().await.unwrap();
+        assert!(Arc::ptr_eq(&live_repository, &replaced));
+        assert!(Arc::ptr_eq(&shadow, &query.view_repository()));
+
+        assert_eq!(vec![1, 2, 3], query.load("agg-A").await.unwrap().sequences);
+        assert_eq!(vec![1], query.load("agg-B").await.unwrap().sequences);
+
+        // events are no longer recorded, and update the rebuilt views
+        query.dispatch("agg-B", &[envelope("agg-B", 2)]).await;
+        assert!(query.take_rebuild_events().await.is_empty());
+        assert_eq!(vec![1, 2], query.load("agg-B").await.unwrap().sequences);
+        assert_eq!(
+            vec![2, 2, 3],
+            live_repository
+                .load("agg-A")
+                .await
+                .unwrap()
+                .unwrap()
+                .sequences
+        );
+    }
+
+    /// Splits each event into two sharing its sequence.
+    struct SplitUpcaster;
+
+    impl EventUpcaster for SplitUpcaster {
+        fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+            event_type == "SomethingWasDone"
+        }
+
+        fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+            Ok(vec![event.clone(), event])
+        }
+    }
+
+    #[tokio::test]
+    async fn rebuild_split_events() {
+        let query = Arc::new(CountQuery::new(Default::default()));
+        let repo = LiveRepo {
+            repo: MockRepo::with_events(Ok(vec![serialized("agg-A", 1), serialized("agg-A", 2)])),
+            query: query.clone(),
+            // live events as split when committed, the first already replayed
+            live: vec![
+                envelope("agg-A", 2),
+                envelope("agg-A", 2),
+                envelope("agg-A", 3),
+                envelope("agg-A", 3),
+            ],
+        };
+        let rebuild = ViewRebuild::new(repo, query.clone(), Default::default())
+            .with_upcasters(vec![Box::new(SplitUpcaster)])
+            .with_workers(2)
+            .with_batch_size(4);
+        rebuild.run().await.unwrap();
+        assert_eq!(
+            vec![1, 1, 2, 2, 3, 3],
+            query.load("agg-A").await.unwrap().sequences
+        );
+    }
+
+    #[tokio::test]
+    async fn rebuild_encrypted() {
+        // the whole payload is encrypted, shredded events replayed as the placeholder
+        let fields = || {
+            vec![EncryptedFields::new("SomethingWasDone", &[""])
+                .with_placeholder(json!("SomethingWasDone"))]
+        };
+        let key_store = Arc::new(InMemoryKeyStore::default());
+        let events = FieldEncryption::new(key_store.clone(), fields())
+            .encrypt_events(vec![
+                serialized("agg-A", 1),
+                serialized("agg-A", 2),
+                serialized("agg-B", 1),
+            ])
+            .await
+            .unwrap();
+        key_store.delete_key("agg-A").await.unwrap();
+
+        let query = Arc::new(CountQuery::new(Default::default()));
+        let repo = LiveRepo {
+            repo: MockRepo::with_events(Ok(events.clone())),
+            query: query.clone(),
+            live: vec![],
+        };
+        let rebuild = ViewRebuild::new(repo, query.clone(), Default::default())
+            .with_encryption(key_store.clone(), fields());
+        rebuild.run().await.unwrap();
+        assert_eq!(vec![1, 2], query.load("agg-A").await.unwrap().sequences);
+        assert_eq!(vec![1], query.load("agg-B").await.unwrap().sequences);
+
+        // encrypted events cannot be read without the key store
+        let repo = LiveRepo {
+            repo: MockRepo::with_events(Ok(events)),
+            query: query.clone(),
+            live: vec![],
+        };
+        let rebuild = ViewRebuild::new(repo, query.clone(), Default::default());
+        assert!(rebuild.run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rebuild_failed() {
+        let live_repository = Arc::new(CountRepository::default());
+        let query = Arc::new(CountQuery::new(live_repository.clone()));
+        let repo = LiveRepo {
+            repo: MockRepo::with_events(Err(PersistenceError::OptimisticLockError)),
+            query: query.clone(),
+            live: vec![],
+        };
+        let shadow = Arc::new(CountRepository::default());
+        let rebuild = ViewRebuild::new(repo, query.clone(), shadow);
+        assert!(rebuild.run().await.is_err());
+        assert!(Arc::ptr_eq(&live_repository, &query.view_repository()));
+
+        query.dispatch("agg-A", &[envelope("agg-A", 1)]).await;
+        assert!(query.take_rebuild_events().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rebuild_cancelled() {
+        let live_repository = Arc::new(CountRepository::default());
+        let query = Arc::new(CountQuery::new(live_repository.clone()));
+        let repo = LiveRepo {
+            repo: MockRepo::with_events(Ok(vec![serialized("agg-A", 1)])),
+            query: query.clone(),
+            live: vec![],
+        };
+        let cancellation = CancellationToken::default();
+        cancellation.cancel();
+        let rebuild = ViewRebuild::new(repo, query.clone(), Default::default())
+            .with_cancellation(cancellation);
+        assert!(rebuild.run().await.is_err());
+        assert!(Arc::ptr_eq(&live_repository, &query.view_repository()));
+    }
+}
@@ -1,8 +1,118 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::persist::{PersistedEventRepository, PersistenceError, QueryErrorHandler};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::sync::Notify;
+
+use crate::persist::encryption::FieldEncryption;
+use crate::persist::{
+    EncryptedFields, EventFilter, EventSerializer, EventUpcaster, KeyStore,
+    PersistedEventRepository, PersistenceError, QueryErrorHandler, ReplayStream,
+};
 use crate::{Aggregate, AggregateError, EventEnvelope, Query};
 
+/// The progress of a replay, reported to the `ReplayProgressHandler` as events are dispatched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// The number of events read from the repository so far.
+    pub events_read: usize,
+    /// The number of events dispatched to the query so far.
+    pub events_dispatched: usize,
+}
+
+/// A handler receiving the progress of a replay after each dispatch to the query.
+///
+/// ```
+/// use actuality::persist::{ReplayProgress, ReplayProgressHandler};
+///
+/// fn progress_handler(progress: ReplayProgress) {
+///     println!("{} events replayed", progress.events_dispatched);
+/// }
+///
+/// let handler: Box<ReplayProgressHandler> = Box::new(progress_handler);
+/// ```
+pub type ReplayProgressHandler = dyn Fn(ReplayProgress) + Send + Sync + 'static;
+
+/// The outcome of a replay.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of events read from the repository.
+    pub events_read: usize,
+    /// The number of events dispatched to the query.
+    pub events_dispatched: usize,
+    /// The number of errors skipped, by the type of error, e.g., `DeserializationError`.
+    pub errors: HashMap<String, usize>,
+    /// The time taken by the replay.
+    pub duration: Duration,
+    /// Whether the replay was stopped by its `CancellationToken`.
+    pub cancelled: bool,
+}
+
+impl ReplayReport {
+    /// The total number of errors skipped.
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+}
+
+/// How a replay handles an error reading or deserializing events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayErrorPolicy {
+    /// Skips the event, counting the error in the `ReplayReport`.
+    #[default]
+    SkipAndContinue,
+    /// Stops reading events and returns the error, rather than passing it to the error handler,
+    /// once the events already read are dispatched.
+    FailFast,
+}
+
+/// Stops a replay from another task, any events already read are still dispatched.
+///
+/// ```
+/// use actuality::persist::CancellationToken;
+///
+/// let token = CancellationToken::default();
+/// let replay_token = token.clone();
+/// // configure the replay with `replay_token`, then stop it when needed
+/// token.cancel();
+/// assert!(replay_token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancellationToken {
+    /// Cancels any replay using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the token is cancelled.
+    async fn cancelled(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
 /// A utility for replaying committed events to a `Query`.
 ///
 /// ```rust
@@ -21,8 +131,16 @@ where
     A: Aggregate,
 {
     repository: R,
-    query: Q,
+    query: Arc<Q>,
     error_handler: Option<Box<QueryErrorHandler>>,
+    upcasters: Arc<Vec<Box<dyn EventUpcaster>>>,
+    serializer: Option<Box<dyn EventSerializer<A::Event>>>,
+    encryption: Option<Arc<FieldEncryption>>,
+    workers: usize,
+    batch_size: usize,
+    progress_handler: Option<Arc<ReplayProgressHandler>>,
+    error_policy: ReplayErrorPolicy,
+    cancellation: CancellationToken,
     phantom_data: PhantomData<A>,
 }
 
@@ -37,8 +155,16 @@ where
     pub fn new(repository: R, query: Q) -> Self {
         Self {
             repository,
-            query,
+            query: Arc::new(query),
             error_handler: None,
+            upcasters: Default::default(),
+            serializer: None,
+            encryption: None,
+            workers: 1,
+            batch_size: 1,
+            progress_handler: None,
+            error_policy: Default::default(),
+            cancellation: Default::default(),
             phantom_data: Default::default(),
         }
     }
@@ -57,50 +183,437 @@ where
         self.error_handler = Some(error_handler);
     }
 
-    /// Replay the events of a single aggregate instance.
-    pub async fn replay(&self, aggregate_id: &str) -> Result<(), AggregateError<A::Error>> {
-        let mut stream = self.repository.stream_events::<A>(aggregate_id).await?;
-        while let Some(event) = stream.next::<A>().await {
-            self.apply(event).await;
+    /// Configures the replay to upcast events before they are applied to the query, these
+    /// should be the same upcasters configured on the `PersistedEventStore` so that legacy
+    /// events are read in their current form.
+    ///
+    /// _Example: Replaying events renamed since they were committed._
+    /// ```
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::{QueryReplay, RenameEventUpcaster};
+    /// # fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let replay = replay.with_upcasters(vec![Box::new(RenameEventUpcaster::new(
+    ///     "SomethingDone",
+    ///     "SomethingWasDone",
+    /// ))]);
+    /// # }
+    /// ```
+    pub fn with_upcasters(self, upcasters: Vec<Box<dyn EventUpcaster>>) -> Self {
+        Self {
+            upcasters: Arc::new(upcasters),
+            ..self
         }
-        Ok(())
     }
 
-    /// Replay the events of all aggregate instances within the database.
-    pub async fn replay_all(&self) -> Result<(), AggregateError<A::Error>> {
-        let mut stream = self.repository.stream_all_events::<A>().await?;
-        while let Some(event) = stream.next::<A>().await {
-            self.apply(event).await;
+    /// Configures the serializer used to read the replayed events, this should be the serializer
+    /// configured with `PersistedEventStore::with_serializer`. JSON and postcard payloads are
+    /// read without it, rkyv payloads require an `RkyvSerializer`.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::{PostcardSerializer, QueryReplay};
+    /// # fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let replay = replay.with_serializer(PostcardSerializer);
+    /// # }
+    /// ```
+    pub fn with_serializer<S>(self, serializer: S) -> Self
+    where
+        S: EventSerializer<A::Event> + 'static,
+    {
+        Self {
+            serializer: Some(Box::new(serializer)),
+            ..self
+        }
+    }
+
+    /// Configures the replay to decrypt the marked fields of events, these should be the key
+    /// store and fields configured with `PersistedEventStore::with_encryption`. Fields whose
+    /// subject's key has been deleted are replayed with the placeholder.
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::{EncryptedFields, InMemoryKeyStore, QueryReplay};
+    /// # fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let key_store = Arc::new(InMemoryKeyStore::default());
+    /// let replay = replay.with_encryption(
+    ///     key_store,
+    ///     vec![EncryptedFields::new("CustomerRegistered", &["/email", "/phone"])],
+    /// );
+    /// # }
+    /// ```
+    pub fn with_encryption(
+        self,
+        key_store: Arc<dyn KeyStore>,
+        fields: Vec<EncryptedFields>,
+    ) -> Self {
+        Self {
+            encryption: Some(Arc::new(FieldEncryption::new(key_store, fields))),
+            ..self
+        }
+    }
+
+    /// Configures the number of workers dispatching events to the query, one by default. Each
+    /// worker is spawned as its own task, so that the workers dispatch in parallel on a
+    /// multi-threaded runtime, while events are read on the task running the replay.
+    ///
+    /// Events are partitioned between the workers by aggregate id, so the events of each
+    /// aggregate instance are still dispatched in order, while events of different aggregate
+    /// instances may be dispatched in any order.
+    ///
+    /// _Example: Rebuilding a view with eight workers, dispatching up to 100 events at a time._
+    /// ```
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::QueryReplay;
+    /// # async fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let replay = replay
+    ///     .with_workers(8)
+    ///     .with_batch_size(100)
+    ///     .with_progress_handler(Box::new(|progress| {
+    ///         println!("{} events replayed", progress.events_dispatched)
+    ///     }));
+    /// replay.replay_all().await.unwrap();
+    /// # }
+    /// ```
+    pub fn with_workers(self, workers: usize) -> Self {
+        Self {
+            workers: workers.max(1),
+            ..self
+        }
+    }
+
+    /// Configures the number of events each worker may buffer before dispatching them, one by
+    /// default. The buffered events of each aggregate instance are passed to the query in a
+    /// single `dispatch`, a worker dispatching its buffer early whenever it waits on the
+    /// repository.
+    pub fn with_batch_size(self, batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Configures a handler to receive the progress of a replay after each dispatch.
+    pub fn with_progress_handler(self, progress_handler: Box<ReplayProgressHandler>) -> Self {
+        Self {
+            progress_handler: Some(progress_handler.into()),
+            ..self
         }
-        Ok(())
     }
 
-    async fn apply(&self, event: Result<EventEnvelope<A>, PersistenceError>) {
-        match event {
-            Ok(event) => {
-                let aggregate_id = event.aggregate_id.clone();
-                self.query.dispatch(&aggregate_id, &[event]).await;
+    /// Configures how errors reading or deserializing events are handled, by default they are
+    /// skipped, counted in the `ReplayReport` and passed to the error handler.
+    ///
+    /// _Example: Stopping a replay at the first error._
+    /// ```
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::{QueryReplay, ReplayErrorPolicy};
+    /// # async fn config(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let replay = replay.with_error_policy(ReplayErrorPolicy::FailFast);
+    /// match replay.replay_all().await {
+    ///     Ok(report) => println!("replayed {} events", report.events_dispatched),
+    ///     Err(error) => println!("replay stopped: {}", error),
+    /// }
+    /// # }
+    /// ```
+    pub fn with_error_policy(self, error_policy: ReplayErrorPolicy) -> Self {
+        Self {
+            error_policy,
+            ..self
+        }
+    }
+
+    /// Configures a token with which the replay may be stopped, the `ReplayReport` of a
+    /// cancelled replay noting that it was cancelled.
+    pub fn with_cancellation(self, cancellation: CancellationToken) -> Self {
+        Self {
+            cancellation,
+            ..self
+        }
+    }
+
+    /// The query events are replayed to.
+    pub(crate) fn query(&self) -> &Q {
+        &self.query
+    }
+}
+
+impl<R, Q, A> QueryReplay<R, Q, A>
+where
+    R: PersistedEventRepository,
+    Q: Query<A> + 'static,
+    A: Aggregate + 'static,
+{
+    /// Replay the events of a single aggregate instance.
+    pub async fn replay(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_events::<A>(aggregate_id).await?;
+        Ok(self.replay_stream(stream).await?)
+    }
+
+    /// Replay the events of all aggregate instances within the database.
+    pub async fn replay_all(&self) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_all_events::<A>().await?;
+        Ok(self.replay_stream(stream).await?)
+    }
+
+    /// Replay only the events selected by the filter, e.g., to repair a query for the events
+    /// committed during an incident.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::{MyAggregate, MyQuery, MyRepository};
+    /// # use actuality::persist::{EventFilter, QueryReplay};
+    /// # use chrono::{Duration, Utc};
+    /// # async fn repair(replay: QueryReplay<MyRepository,MyQuery,MyAggregate>) {
+    /// let until = Utc::now();
+    /// let filter = EventFilter::default()
+    ///     .with_event_types(["SomethingWasDone"])
+    ///     .with_committed_between(until - Duration::hours(1), until);
+    /// replay.replay_filtered(&filter).await.unwrap();
+    /// # }
+    /// ```
+    pub async fn replay_filtered(
+        &self,
+        filter: &EventFilter,
+    ) -> Result<ReplayReport, AggregateError<A::Error>> {
+        let stream = self.repository.stream_filtered_events::<A>(filter).await?;
+        Ok(self.replay_stream(stream).await?)
+    }
+
+    /// Reads the stream, passing each event to the worker for its aggregate instance.
+    async fn replay_stream(&self, stream: ReplayStream) -> Result<ReplayReport, PersistenceError> {
+        let started = Instant::now();
+        let mut stream = stream.with_shared_upcasters(self.upcasters.clone());
+        if let Some(encryption) = &self.encryption {
+            stream = stream.with_shared_encryption(encryption.clone());
+        }
+        let counters = Arc::new(ReplayCounters::default());
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..self.workers).map(|_| channel(self.batch_size)).unzip();
+        let workers: Vec<_> = receivers
+            .into_iter()
+            .map(|receiver| {
+                let worker = ReplayWorker {
+                    query: self.query.clone(),
+                    progress_handler: self.progress_handler.clone(),
+                    counters: counters.clone(),
+                    batch_size: self.batch_size,
+                    phantom_data: PhantomData,
+                };
+                tokio::spawn(worker.dispatch_events(receiver))
+            })
+            .collect();
+        let read_counters = counters.clone();
+        let read = async move {
+            let mut outcome = ReadOutcome::default();
+            loop {
+                let event = tokio::select! {
+                    biased;
+                    _ = self.cancellation.cancelled() => {
+                        outcome.cancelled = true;
+                        return outcome;
+                    }
+                    event = stream.next_deserialized::<A>(self.serializer.as_deref()) => event,
+                };
+                match event {
+                    Some(Ok(event)) => {
+                        read_counters.read.fetch_add(1, Ordering::Relaxed);
+                        let worker = partition(&event.aggregate_id, senders.len());
+                        if senders[worker].send(event).await.is_err() {
+                            return outcome;
+                        }
+                    }
+                    Some(Err(error)) => {
+                        if self.error_policy == ReplayErrorPolicy::FailFast {
+                            outcome.failure = Some(error);
+                            return outcome;
+                        }
+                        let error_type = error_type(&error).to_string();
+                        *outcome.errors.entry(error_type).or_default() += 1;
+                        if let Some(handler) = &self.error_handler {
+                            (handler)(error);
+                        }
+                    }
+                    None => return outcome,
+                }
             }
-            Err(error) => {
-                if let Some(handler) = &self.error_handler {
-                    (handler)(error);
+        };
+        let outcome = read.await;
+        for worker in workers {
+            if let Err(error) = worker.await {
+                if error.is_panic() {
+                    std::panic::resume_unwind(error.into_panic());
                 }
             }
         }
+        if let Some(error) = outcome.failure {
+            return Err(error);
+        }
+        Ok(ReplayReport {
+            events_read: counters.read.load(Ordering::Relaxed),
+            events_dispatched: counters.dispatched.load(Ordering::Relaxed),
+            errors: outcome.errors,
+            duration: started.elapsed(),
+            cancelled: outcome.cancelled,
+        })
+    }
+}
+
+/// A worker dispatching the events of its partition of aggregate instances to the query.
+struct ReplayWorker<Q, A>
+where
+    Q: Query<A>,
+    A: Aggregate,
+{
+    query: Arc<Q>,
+    progress_handler: Option<Arc<ReplayProgressHandler>>,
+    counters: Arc<ReplayCounters>,
+    batch_size: usize,
+    phantom_data: PhantomData<A>,
+}
+
+impl<Q, A> ReplayWorker<Q, A>
+where
+    Q: Query<A>,
+    A: Aggregate,
+{
+    /// Dispatches the events received by the worker, buffering up to the batch size while more
+    /// events are immediately available.
+    async fn dispatch_events(self, mut receiver: Receiver<EventEnvelope<A>>) {
+        let mut batch = ReplayBatch::default();
+        loop {
+            let event = match receiver.try_recv() {
+                Ok(event) => Some(event),
+                Err(TryRecvError::Empty) => {
+                    self.dispatch_batch(&mut batch).await;
+                    receiver.recv().await
+                }
+                Err(TryRecvError::Disconnected) => None,
+            };
+            match event {
+                Some(event) => {
+                    batch.push(event);
+                    if batch.len >= self.batch_size {
+                        self.dispatch_batch(&mut batch).await;
+                    }
+                }
+                None => {
+                    self.dispatch_batch(&mut batch).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn dispatch_batch(&self, batch: &mut ReplayBatch<A>) {
+        for (aggregate_id, events) in batch.take() {
+            self.query.dispatch(&aggregate_id, &events).await;
+            let dispatched = self
+                .counters
+                .dispatched
+                .fetch_add(events.len(), Ordering::Relaxed);
+            if let Some(handler) = &self.progress_handler {
+                (handler)(ReplayProgress {
+                    events_read: self.counters.read.load(Ordering::Relaxed),
+                    events_dispatched: dispatched + events.len(),
+                });
+            }
+        }
+    }
+}
+
+/// Selects the worker for an aggregate instance.
+fn partition(aggregate_id: &str, workers: usize) -> usize {
+    if workers == 1 {
+        return 0;
+    }
+    let mut hasher = DefaultHasher::new();
+    aggregate_id.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+/// The name of the variant of the error, used to count errors in a `ReplayReport`.
+fn error_type(error: &PersistenceError) -> &'static str {
+    match error {
+        PersistenceError::OptimisticLockError => "OptimisticLockError",
+        PersistenceError::ConnectionError(_) => "ConnectionError",
+        PersistenceError::DeserializationError(_) => "DeserializationError",
+        PersistenceError::UnknownError(_) => "UnknownError",
+    }
+}
+
+/// The result of reading the stream.
+#[derive(Default)]
+struct ReadOutcome {
+    errors: HashMap<String, usize>,
+    cancelled: bool,
+    failure: Option<PersistenceError>,
+}
+
+#[derive(Default)]
+struct ReplayCounters {
+    read: AtomicUsize,
+    dispatched: AtomicUsize,
+}
+
+/// The events buffered by a worker, grouped by aggregate instance in the order received.
+struct ReplayBatch<A: Aggregate> {
+    events: Vec<(String, Vec<EventEnvelope<A>>)>,
+    index: HashMap<String, usize>,
+    len: usize,
+}
+
+impl<A: Aggregate> Default for ReplayBatch<A> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            index: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<A: Aggregate> ReplayBatch<A> {
+    fn push(&mut self, event: EventEnvelope<A>) {
+        self.len += 1;
+        match self.index.get(&event.aggregate_id) {
+            Some(position) => self.events[*position].1.push(event),
+            None => {
+                self.index
+                    .insert(event.aggregate_id.clone(), self.events.len());
+                self.events.push((event.aggregate_id.clone(), vec![event]));
+            }
+        }
+    }
+
+    fn take(&mut self) -> Vec<(String, Vec<EventEnvelope<A>>)> {
+        self.index.clear();
+        self.len = 0;
+        std::mem::take(&mut self.events)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::sync::{Arc, Mutex};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::time::Duration;
 
     use async_trait::async_trait;
 
     use crate::doc::setup::{MyAggregate, MyEvents};
-    use crate::persist::event_store::shared_test::MockRepo;
-    use crate::persist::replay::QueryReplay;
-    use crate::persist::SerializedEvent;
-    use crate::{EventEnvelope, query::Query};
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, MockRepo, TestAggregate, TestEvents,
+    };
+    use crate::persist::replay::{
+        partition, CancellationToken, QueryReplay, ReplayErrorPolicy, ReplayProgress,
+    };
+    use crate::persist::{
+        EventFilter, EventSerializer, RkyvSerializer, SemanticVersionEventUpcaster,
+        SerializationFormat, SerializedEvent,
+    };
+    use crate::{AggregateError, EventEnvelope, query::Query};
 
     #[derive(Debug)]
     struct MockQuery {
@@ -163,6 +676,293 @@ mod test {
         assert_events_eq(events, expected_events);
     }
 
+    #[tokio::test]
+    async fn query_replay_upcasted() {
+        let legacy_event = SerializedEvent::new(
+            AGGREGATE_ID.to_string(),
+            1,
+            "MyAggregate".to_string(),
+            "SomethingWasDone".to_string(),
+            "0.0.1".to_string(),
+            serde_json::to_vec(&serde_json::json!("SomethingDone")).unwrap(),
+            serde_json::json!({}),
+        );
+        let upcaster = SemanticVersionEventUpcaster::new(
+            "SomethingWasDone",
+            "0.1.0",
+            Box::new(|_| serde_json::json!("SomethingWasDone")),
+        );
+        let event_repo = MockRepo::with_events(Ok(vec![legacy_event]));
+        let (query, event_list) = MockQuery::new();
+        let query_replay =
+            QueryReplay::new(event_repo, query).with_upcasters(vec![Box::new(upcaster)]);
+        query_replay.replay_all().await.unwrap();
+
+        let events = event_list.lock().unwrap().to_owned();
+        assert_eq!(1, events.len());
+        assert_eq!(MyEvents::SomethingWasDone, events[0].payload);
+    }
+
+    #[derive(Default)]
+    struct TestQuery {
+        events: Mutex<Vec<TestEvents>>,
+    }
+
+    #[async_trait]
+    impl Query<TestAggregate> for TestQuery {
+        async fn dispatch(&self, _aggregate_id: &str, events: &[EventEnvelope<TestAggregate>]) {
+            let mut event_list = self.events.lock().unwrap();
+            event_list.extend(events.iter().map(|event| event.payload.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn query_replay_rkyv() {
+        let event = test_serialized_event(1, TestEvents::SomethingWasDone);
+        let event = SerializedEvent {
+            payload: RkyvSerializer
+                .serialize(&TestEvents::SomethingWasDone)
+                .unwrap(),
+            ..event.with_format(SerializationFormat::Rkyv)
+        };
+        let event_repo = MockRepo::with_events(Ok(vec![event]));
+        let query_replay =
+            QueryReplay::new(event_repo, TestQuery::default()).with_serializer(RkyvSerializer);
+        let report = query_replay.replay_all().await.unwrap();
+
+        assert_eq!(0, report.error_count());
+        assert_eq!(
+            vec![TestEvents::SomethingWasDone],
+            *query_replay.query.events.lock().unwrap()
+        );
+    }
+
+    #[derive(Default)]
+    struct BatchQuery {
+        dispatches: Mutex<Vec<(String, Vec<usize>)>>,
+    }
+
+    #[async_trait]
+    impl Query<MyAggregate> for BatchQuery {
+        async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<MyAggregate>]) {
+            assert!(events
+                .iter()
+                .all(|event| event.aggregate_id == aggregate_id));
+            let sequences = events.iter().map(|event| event.sequence).collect();
+            self.dispatches
+                .lock()
+                .unwrap()
+                .push((aggregate_id.to_string(), sequences));
+        }
+    }
+
+    #[tokio::test]
+    async fn query_replay_parallel() {
+        let mut ser_events = Vec::new();
+        for sequence in 1..=10 {
+            for aggregate in 0..7 {
+                let event = EventEnvelope::<MyAggregate> {
+                    aggregate_id: format!("aggregate-{}", aggregate),
+                    event_type: "all".to_string(),
+                    sequence,
+                    system_id: "".to_string(),
+                    payload: MyEvents::SomethingWasDone,
+                    metadata: Default::default(),
+                };
+                ser_events.push(SerializedEvent::try_from(&event).unwrap());
+            }
+        }
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let progress: Arc<Mutex<Vec<ReplayProgress>>> = Default::default();
+        let reported = progress.clone();
+        let query_replay = QueryReplay::new(event_repo, BatchQuery::default())
+            .with_workers(3)
+            .with_batch_size(4)
+            .with_progress_handler(Box::new(move |p| reported.lock().unwrap().push(p)));
+        query_replay.replay_all().await.unwrap();
+
+        let dispatches = query_replay.query.dispatches.lock().unwrap();
+        assert!(dispatches.iter().all(|(_, sequences)| sequences.len() <= 4));
+        for aggregate in 0..7 {
+            let aggregate_id = format!("aggregate-{}", aggregate);
+            let sequences: Vec<usize> = dispatches
+                .iter()
+                .filter(|(id, _)| *id == aggregate_id)
+                .flat_map(|(_, sequences)| sequences.clone())
+                .collect();
+            assert_eq!((1..=10).collect::<Vec<usize>>(), sequences);
+        }
+
+        let progress = progress.lock().unwrap();
+        assert_eq!(dispatches.len(), progress.len());
+        assert_eq!(70, progress.last().unwrap().events_dispatched);
+        assert_eq!(70, progress.last().unwrap().events_read);
+    }
+
+    fn serialized_events(count: usize) -> Vec<SerializedEvent> {
+        (1..=count)
+            .map(|sequence| {
+                let event = EventEnvelope::<MyAggregate> {
+                    aggregate_id: AGGREGATE_ID.to_string(),
+                    event_type: "all".to_string(),
+                    sequence,
+                    system_id: "".to_string(),
+                    payload: MyEvents::SomethingWasDone,
+                    metadata: Default::default(),
+                };
+                SerializedEvent::try_from(&event).unwrap()
+            })
+            .collect()
+    }
+
+    fn unknown_event(sequence: usize) -> SerializedEvent {
+        SerializedEvent {
+            payload: serde_json::to_vec(&serde_json::json!("SomethingUnknown")).unwrap(),
+            ..serialized_events(sequence).pop().unwrap()
+        }
+    }
+
+    /// Blocks each dispatch, as CPU-bound work would, until a dispatch of another worker is in
+    /// progress at the same time.
+    #[derive(Default)]
+    struct BlockingQuery {
+        dispatching: Mutex<usize>,
+        dispatched: Condvar,
+    }
+
+    #[async_trait]
+    impl Query<MyAggregate> for BlockingQuery {
+        async fn dispatch(&self, _aggregate_id: &str, _events: &[EventEnvelope<MyAggregate>]) {
+            let mut dispatching = self.dispatching.lock().unwrap();
+            *dispatching += 1;
+            self.dispatched.notify_all();
+            let (_dispatching, waited) = self
+                .dispatched
+                .wait_timeout_while(dispatching, Duration::from_secs(5), |dispatching| {
+                    *dispatching < 2
+                })
+                .unwrap();
+            assert!(!waited.timed_out(), "workers did not dispatch in parallel");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn query_replay_workers_in_parallel() {
+        // an aggregate instance for each of the two workers
+        let ser_events = (0..2)
+            .map(|worker| {
+                let aggregate_id = (0..)
+                    .map(|aggregate| format!("aggregate-{}", aggregate))
+                    .find(|aggregate_id| partition(aggregate_id, 2) == worker)
+                    .unwrap();
+                let event = EventEnvelope::<MyAggregate> {
+                    aggregate_id,
+                    event_type: "all".to_string(),
+                    sequence: 1,
+                    system_id: "".to_string(),
+                    payload: MyEvents::SomethingWasDone,
+                    metadata: Default::default(),
+                };
+                SerializedEvent::try_from(&event).unwrap()
+            })
+            .collect();
+        let event_repo = MockRepo::with_events(Ok(ser_events));
+        let query_replay = QueryReplay::new(event_repo, BlockingQuery::default()).with_workers(2);
+        let report = query_replay.replay_all().await.unwrap();
+
+        assert_eq!(2, report.events_dispatched);
+    }
+
+    #[tokio::test]
+    async fn query_replay_report() {
+        let mut events = serialized_events(3);
+        events.insert(1, unknown_event(2));
+        let event_repo = MockRepo::with_events(Ok(events));
+        let (query, event_list) = MockQuery::new();
+        let report = QueryReplay::new(event_repo, query)
+            .replay_all()
+            .await
+            .unwrap();
+
+        assert_eq!(3, report.events_read);
+        assert_eq!(3, report.events_dispatched);
+        assert_eq!(Some(&1), report.errors.get("DeserializationError"));
+        assert_eq!(1, report.error_count());
+        assert!(!report.cancelled);
+        assert_eq!(3, event_list.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn query_replay_fail_fast() {
+        let mut events = serialized_events(3);
+        events.insert(1, unknown_event(2));
+        let event_repo = MockRepo::with_events(Ok(events));
+        let (query, event_list) = MockQuery::new();
+        let result = QueryReplay::new(event_repo, query)
+            .with_error_policy(ReplayErrorPolicy::FailFast)
+            .replay_all()
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(AggregateError::DeserializationError(_))
+        ));
+        assert_eq!(1, event_list.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn query_replay_cancelled() {
+        let event_repo = MockRepo::with_events(Ok(serialized_events(10)));
+        let (query, event_list) = MockQuery::new();
+        let cancellation = CancellationToken::default();
+        let progress_cancellation = cancellation.clone();
+        let report = QueryReplay::new(event_repo, query)
+            .with_cancellation(cancellation)
+            .with_progress_handler(Box::new(move |_| progress_cancellation.cancel()))
+            .replay_all()
+            .await
+            .unwrap();
+
+        assert!(report.cancelled);
+        assert!(report.events_dispatched < 10);
+        assert_eq!(report.events_read, report.events_dispatched);
+        assert_eq!(report.events_dispatched, event_list.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn query_replay_filtered() {
+        let mut events = serialized_events(4);
+        events[1].aggregate_id = "other".to_string();
+        events[3].event_type = "SomethingElse".to_string();
+        let event_repo = MockRepo::with_events(Ok(events));
+        let (query, event_list) = MockQuery::new();
+        let filter = EventFilter::default()
+            .with_aggregate_ids([AGGREGATE_ID])
+            .with_event_types(["SomethingWasDone"])
+            .with_sequences(2..);
+        let report = QueryReplay::new(event_repo, query)
+            .replay_filtered(&filter)
+            .await
+            .unwrap();
+
+        assert_eq!(1, report.events_dispatched);
+        let sequences: Vec<usize> = event_list
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.sequence)
+            .collect();
+        assert_eq!(vec![3], sequences);
+
+        // positions are not known to the repository
+        let event_repo = MockRepo::with_events(Ok(serialized_events(1)));
+        let (query, _) = MockQuery::new();
+        let result = QueryReplay::new(event_repo, query)
+            .replay_filtered(&EventFilter::default().with_positions(..10))
+            .await;
+        assert!(matches!(result, Err(AggregateError::UnexpectedError(_))));
+    }
+
     fn assert_events_eq(
         expected: Vec<EventEnvelope<MyAggregate>>,
         found: Vec<EventEnvelope<MyAggregate>>,
@@ -0,0 +1,625 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::persist::{EventUpcaster, PersistenceError, SemanticVersion};
+use crate::DomainEvent;
+
+/// Guards against unbounded recursion when comparing recursive schemas.
+const MAX_DEPTH: usize = 32;
+
+/// The JSON Schema of a single event type and version.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventSchema {
+    /// The type of the event, as given by `DomainEvent::event_type`.
+    pub event_type: String,
+    /// The version of the event, as given by `DomainEvent::event_version`.
+    pub event_version: String,
+    /// The JSON Schema of the serialized event payload.
+    pub schema: Value,
+}
+
+/// A change to an event schema that prevents previously committed events from being read, with
+/// no `EventUpcaster` to convert them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BreakingChange {
+    /// The type of the event.
+    pub event_type: String,
+    /// The recorded version of the event.
+    pub recorded_version: String,
+    /// The current version of the event, `None` if the event type no longer exists.
+    pub current_version: Option<String>,
+    /// Describes each incompatibility found.
+    pub reasons: Vec<String>,
+}
+
+impl Display for BreakingChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.current_version {
+            Some(current_version) => write!(
+                f,
+                "{} v{} cannot be read as v{}: {}",
+                self.event_type,
+                self.recorded_version,
+                current_version,
+                self.reasons.join(", ")
+            ),
+            None => write!(
+                f,
+                "{} v{}: {}",
+                self.event_type,
+                self.recorded_version,
+                self.reasons.join(", ")
+            ),
+        }
+    }
+}
+
+/// Records the JSON Schema of each event type and version, generated from the `DomainEvent`
+/// with [schemars](https://docs.rs/schemars), so that changes to events may be checked for
+/// backward compatibility before they are released.
+///
+/// The schemas recorded for released events are kept alongside the code, typically with the
+/// `actuality::test::assert_schemas_compatible` test helper. A change is breaking where events
+/// valid under the recorded schema would not be valid under the current one, e.g., a field
+/// becoming required or changing type, unless an upcaster converts events of the recorded
+/// version.
+///
+/// Requires the `schema` feature.
+///
+/// ```
+/// use actuality::persist::SchemaRegistry;
+/// # use actuality::DomainEvent;
+/// # use schemars::JsonSchema;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+/// # enum CustomerEvent {
+/// #     NameAdded { name: String },
+/// #     EmailUpdated { new_email: String },
+/// # }
+/// # impl DomainEvent for CustomerEvent {
+/// #     fn event_type(&self) -> String {
+/// #         match self {
+/// #             CustomerEvent::NameAdded { .. } => "NameAdded".to_string(),
+/// #             CustomerEvent::EmailUpdated { .. } => "EmailUpdated".to_string(),
+/// #         }
+/// #     }
+/// #     fn event_version(&self) -> String {
+/// #         "1.0".to_string()
+/// #     }
+/// # }
+///
+/// # fn config() -> Result<(), actuality::persist::PersistenceError> {
+/// let mut current = SchemaRegistry::new();
+/// current.register(&[
+///     CustomerEvent::NameAdded { name: String::new() },
+///     CustomerEvent::EmailUpdated { new_email: String::new() },
+/// ])?;
+/// let recorded = SchemaRegistry::load("schemas/customer.json")?;
+/// for change in recorded.breaking_changes(&current, &[]) {
+///     println!("{}", change);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaRegistry {
+    schemas: Vec<EventSchema>,
+}
+
+impl SchemaRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a registry previously saved to the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        let contents = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    /// Saves the registry to the file as formatted JSON.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistenceError> {
+        let mut contents = serde_json::to_vec_pretty(self)?;
+        contents.push(b'\n');
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Registers the schemas of the events, one example being given for each event type as
+    /// the type and version of an event are only known for an instance.
+    ///
+    /// For an enum the schema of each event is that of its variant, for other types it is the
+    /// schema of the whole type.
+    pub fn register<E: DomainEvent + JsonSchema>(
+        &mut self,
+        examples: &[E],
+    ) -> Result<(), PersistenceError> {
+        let root = serde_json::to_value(schemars::schema_for!(E))?;
+        for example in examples {
+            let event_type = example.event_type();
+            let payload = serde_json::to_value(example)?;
+            let schema = event_schema(&root, &payload).ok_or_else(|| {
+                PersistenceError::UnknownError(
+                    format!("no schema found for the '{}' event", event_type).into(),
+                )
+            })?;
+            self.insert(EventSchema {
+                event_type,
+                event_version: example.event_version(),
+                schema,
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds the schema, replacing any schema of the same event type and version.
+    pub fn insert(&mut self, schema: EventSchema) {
+        self.schemas.retain(|recorded| {
+            recorded.event_type != schema.event_type
+                || recorded.event_version != schema.event_version
+        });
+        self.schemas.push(schema);
+        self.schemas.sort_by(|a, b| {
+            a.event_type
+                .cmp(&b.event_type)
+                .then_with(|| compare_versions(&a.event_version, &b.event_version))
+        });
+    }
+
+    /// Adds the schemas of another registry, replacing those of the same event type and version.
+    pub fn merge(&mut self, other: &SchemaRegistry) {
+        for schema in &other.schemas {
+            self.insert(schema.clone());
+        }
+    }
+
+    /// Returns the schema of the event type and version.
+    pub fn schema(&self, event_type: &str, event_version: &str) -> Option<&EventSchema> {
+        self.schemas
+            .iter()
+            .find(|schema| schema.event_type == event_type && schema.event_version == event_version)
+    }
+
+    /// Returns the schema of the latest version of the event type.
+    pub fn latest(&self, event_type: &str) -> Option<&EventSchema> {
+        self.schemas
+            .iter()
+            .rev()
+            .find(|schema| schema.event_type == event_type)
+    }
+
+    /// All schemas, ordered by event type and version.
+    pub fn schemas(&self) -> &[EventSchema] {
+        &self.schemas
+    }
+
+    /// Compares each recorded schema with the latest current schema of the same event type,
+    /// returning the breaking changes for which none of the upcasters converts the recorded
+    /// version.
+    pub fn breaking_changes(
+        &self,
+        current: &SchemaRegistry,
+        upcasters: &[Box<dyn EventUpcaster>],
+    ) -> Vec<BreakingChange> {
+        let mut changes = Vec::new();
+        for recorded in &self.schemas {
+            let upcast = upcasters
+                .iter()
+                .any(|upcaster| upcaster.can_upcast(&recorded.event_type, &recorded.event_version));
+            if upcast {
+                continue;
+            }
+            let (current_version, reasons) = match current.latest(&recorded.event_type) {
+                Some(latest) => {
+                    let mut reasons = Vec::new();
+                    Comparison {
+                        old_root: &recorded.schema,
+                        new_root: &latest.schema,
+                        reasons: &mut reasons,
+                    }
+                    .compare(&recorded.schema, &latest.schema, "", 0);
+                    (Some(latest.event_version.clone()), reasons)
+                }
+                None => (None, vec!["the event type no longer exists".to_string()]),
+            };
+            if !reasons.is_empty() {
+                changes.push(BreakingChange {
+                    event_type: recorded.event_type.clone(),
+                    recorded_version: recorded.event_version.clone(),
+                    current_version,
+                    reasons,
+                });
+            }
+        }
+        changes
+    }
+}
+
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (SemanticVersion::from_str(a), SemanticVersion::from_str(b)) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// Extracts the schema of a single event from the schema of its type, keeping the definitions
+/// it may refer to.
+fn event_schema(root: &Value, payload: &Value) -> Option<Value> {
+    let mut schema = match payload {
+        // a unit variant
+        Value::String(variant) if is_enum_value(root, payload) => unit_variant(variant),
+        Value::String(variant) => alternatives(root)
+            .find(|alternative| is_enum_value(alternative, payload))
+            .map(|_| unit_variant(variant))?,
+        // an externally tagged variant
+        Value::Object(fields) if fields.len() == 1 && alternatives(root).next().is_some() => {
+            // uninteresting unwrap: the object has a single field
+            let variant = fields.keys().next().unwrap();
+            alternatives(root)
+                .find(|alternative| {
+                    alternative
+                        .pointer("/properties")
+                        .and_then(Value::as_object)
+                        .is_some_and(|properties| properties.contains_key(variant))
+                })?
+                .clone()
+        }
+        _ => {
+            let mut schema = root.clone();
+            if let Some(fields) = schema.as_object_mut() {
+                fields.remove("$schema");
+                fields.remove("definitions");
+            }
+            schema
+        }
+    };
+    if let (Some(definitions), Some(fields)) = (root.get("definitions"), schema.as_object_mut()) {
+        fields.insert("definitions".to_string(), definitions.clone());
+    }
+    Some(schema)
+}
+
+fn alternatives(schema: &Value) -> impl Iterator<Item = &Value> {
+    ["oneOf", "anyOf"]
+        .into_iter()
+        .filter_map(|key| schema.get(key).and_then(Value::as_array))
+        .flatten()
+}
+
+fn is_enum_value(schema: &Value, value: &Value) -> bool {
+    schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .is_some_and(|values| values.contains(value))
+}
+
+fn unit_variant(variant: &str) -> Value {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), Value::from("string"));
+    schema.insert("enum".to_string(), Value::from(vec![variant]));
+    Value::Object(schema)
+}
+
+/// Compares a recorded schema with a current one, recording where values valid under the
+/// recorded schema are no longer valid.
+struct Comparison<'a> {
+    old_root: &'a Value,
+    new_root: &'a Value,
+    reasons: &'a mut Vec<String>,
+}
+
+impl Comparison<'_> {
+    fn compare(&mut self, old: &Value, new: &Value, path: &str, depth: usize) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let old = resolve(self.old_root, old);
+        let new = resolve(self.new_root, new);
+        let location = if path.is_empty() { "the event" } else { path };
+
+        if let (Some(old_types), Some(new_types)) = (types(old), types(new)) {
+            let accepted = old_types.iter().all(|old_type| {
+                new_types.contains(old_type)
+                    || (old_type == "integer" && new_types.contains("number"))
+            });
+            if !accepted {
+                self.reasons.push(format!(
+                    "{} changed type from {} to {}",
+                    location,
+                    describe(&old_types),
+                    describe(&new_types)
+                ));
+                return;
+            }
+        }
+
+        if let (Some(old_values), Some(new_values)) = (
+            old.get("enum").and_then(Value::as_array),
+            new.get("enum").and_then(Value::as_array),
+        ) {
+            for value in old_values
+                .iter()
+                .filter(|value| !new_values.contains(value))
+            {
+                self.reasons
+                    .push(format!("{} no longer accepts {}", location, value));
+            }
+        }
+
+        let old_required = strings(old.get("required"));
+        for field in strings(new.get("required")) {
+            if !old_required.contains(&field) {
+                self.reasons
+                    .push(format!("{} is now required", join(path, &field)));
+            }
+        }
+
+        if let Some(old_properties) = old.get("properties").and_then(Value::as_object) {
+            let new_properties = new.get("properties").and_then(Value::as_object);
+            let closed = new.get("additionalProperties") == Some(&Value::Bool(false));
+            for (field, old_property) in old_properties {
+                match new_properties.and_then(|properties| properties.get(field)) {
+                    Some(new_property) => {
+                        self.compare(old_property, new_property, &join(path, field), depth + 1)
+                    }
+                    None if closed => self
+                        .reasons
+                        .push(format!("{} was removed", join(path, field))),
+                    None => {}
+                }
+            }
+        }
+
+        if let (Some(old_items), Some(new_items)) = (old.get("items"), new.get("items")) {
+            self.compare(old_items, new_items, &format!("{}[]", path), depth + 1);
+        }
+
+        let new_alternatives: Vec<&Value> = alternatives(new).collect();
+        if !new_alternatives.is_empty() {
+            for (index, old_alternative) in alternatives(old).enumerate() {
+                let accepted = new_alternatives.iter().any(|new_alternative| {
+                    let mut reasons = Vec::new();
+                    Comparison {
+                        old_root: self.old_root,
+                        new_root: self.new_root,
+                        reasons: &mut reasons,
+                    }
+                    .compare(old_alternative, new_alternative, path, depth + 1);
+                    reasons.is_empty()
+                });
+                if !accepted {
+                    self.reasons.push(format!(
+                        "{} no longer accepts alternative {}",
+                        location,
+                        index + 1
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Follows a `$ref` to the definitions of the root schema.
+fn resolve<'a>(root: &'a Value, schema: &'a Value) -> &'a Value {
+    match schema.get("$ref").and_then(Value::as_str) {
+        Some(reference) => reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .unwrap_or(schema),
+        None => schema,
+    }
+}
+
+fn types(schema: &Value) -> Option<HashSet<String>> {
+    match schema.get("type")? {
+        Value::String(schema_type) => Some(HashSet::from([schema_type.clone()])),
+        Value::Array(_) => Some(strings(schema.get("type"))),
+        _ => None,
+    }
+}
+
+fn strings(value: Option<&Value>) -> HashSet<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn describe(types: &HashSet<String>) -> String {
+    let mut types: Vec<&str> = types.iter().map(String::as_str).collect();
+    types.sort_unstable();
+    types.join(" or ")
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_string()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::{DropEventUpcaster, EventUpcaster, SchemaRegistry};
+    use crate::DomainEvent;
+
+    mod v1 {
+        use super::*;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+        pub(crate) enum AccountEvent {
+            Opened { owner: String, limit: u32 },
+            Closed,
+        }
+
+        impl DomainEvent for AccountEvent {
+            fn event_type(&self) -> String {
+                match self {
+                    AccountEvent::Opened { .. } => "Opened".to_string(),
+                    AccountEvent::Closed => "Closed".to_string(),
+                }
+            }
+
+            fn event_version(&self) -> String {
+                "1.0".to_string()
+            }
+        }
+
+        pub(crate) fn registry() -> SchemaRegistry {
+            let mut registry = SchemaRegistry::new();
+            registry
+                .register(&[
+                    AccountEvent::Opened {
+                        owner: String::new(),
+                        limit: 0,
+                    },
+                    AccountEvent::Closed,
+                ])
+                .unwrap();
+            registry
+        }
+    }
+
+    mod v2 {
+        use super::*;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+        pub(crate) enum AccountEvent {
+            Opened {
+                owner: String,
+                limit: f64,
+                #[serde(default)]
+                nickname: Option<String>,
+                currency: String,
+            },
+            Frozen,
+        }
+
+        impl DomainEvent for AccountEvent {
+            fn event_type(&self) -> String {
+                match self {
+                    AccountEvent::Opened { .. } => "Opened".to_string(),
+                    AccountEvent::Frozen => "Frozen".to_string(),
+                }
+            }
+
+            fn event_version(&self) -> String {
+                "2.0".to_string()
+            }
+        }
+
+        pub(crate) fn registry() -> SchemaRegistry {
+            let mut registry = SchemaRegistry::new();
+            registry
+                .register(&[
+                    AccountEvent::Opened {
+                        owner: String::new(),
+                        limit: 0.0,
+                        nickname: None,
+                        currency: String::new(),
+                    },
+                    AccountEvent::Frozen,
+                ])
+                .unwrap();
+            registry
+        }
+    }
+
+    #[test]
+    fn register() {
+        let registry = v1::registry();
+        assert_eq!(2, registry.schemas().len());
+        let opened = registry.schema("Opened", "1.0").unwrap();
+        assert!(opened.schema["properties"]["Opened"].is_object());
+        let closed = registry.latest("Closed").unwrap();
+        assert_eq!(serde_json::json!(["Closed"]), closed.schema["enum"]);
+        assert!(v1::registry().breaking_changes(&registry, &[]).is_empty());
+    }
+
+    #[test]
+    fn breaking_changes() {
+        let changes = v1::registry().breaking_changes(&v2::registry(), &[]);
+        assert_eq!(2, changes.len());
+        assert_eq!("Closed", changes[0].event_type);
+        assert_eq!(None, changes[0].current_version);
+
+        let opened = &changes[1];
+        assert_eq!("Opened", opened.event_type);
+        assert_eq!(Some("2.0".to_string()), opened.current_version);
+        assert_eq!(
+            vec!["Opened.currency is now required".to_string()],
+            opened.reasons
+        );
+
+        let upcasters: Vec<Box<dyn EventUpcaster>> = vec![
+            Box::new(DropEventUpcaster::new("Closed")),
+            Box::new(DropEventUpcaster::new("Opened")),
+        ];
+        assert!(v1::registry()
+            .breaking_changes(&v2::registry(), &upcasters)
+            .is_empty());
+    }
+
+    #[test]
+    fn type_changes() {
+        let changes = v2::registry().breaking_changes(&v1::registry(), &[]);
+        let opened = changes
+            .iter()
+            .find(|change| change.event_type == "Opened")
+            .unwrap();
+        assert_eq!(
+            vec!["Opened.limit changed type from number to integer".to_string()],
+            opened.reasons
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("actuality-{}.json", rand::random::<u64>()));
+        let mut registry = v1::registry();
+        registry.merge(&v2::registry());
+        registry.save(&path).unwrap();
+        let loaded = SchemaRegistry::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry, loaded);
+        let versions: Vec<&str> = loaded
+            .schemas()
+            .iter()
+            .map(|schema| schema.event_version.as_str())
+            .collect();
+        assert_eq!(vec!["1.0", "2.0", "1.0", "2.0"], versions);
+    }
+
+    #[test]
+    fn assert_schemas_compatible() {
+        let path = std::env::temp_dir().join(format!("actuality-{}.json", rand::random::<u64>()));
+        crate::test::assert_schemas_compatible(&path, &v1::registry(), &[]);
+        assert_eq!(v1::registry(), SchemaRegistry::load(&path).unwrap());
+
+        let upcasters: Vec<Box<dyn EventUpcaster>> = vec![
+            Box::new(DropEventUpcaster::new("Closed")),
+            Box::new(DropEventUpcaster::new("Opened")),
+        ];
+        crate::test::assert_schemas_compatible(&path, &v2::registry(), &upcasters);
+        let recorded = SchemaRegistry::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(4, recorded.schemas().len());
+    }
+}
@@ -3,7 +3,13 @@ use std::convert::TryFrom;
 use crate::{Aggregate, DomainEvent, EventEnvelope};
 use serde_json::Value;
 
-use crate::persist::{EventStoreAggregateContext, EventUpcaster, PersistenceError};
+use crate::persist::compression::CompressionPolicy;
+use crate::persist::serializer::deserialize_payload;
+use crate::persist::upcaster::upcast_event;
+use crate::persist::{
+    Compression, EventSerializer, EventStoreAggregateContext, EventUpcaster, JsonSerializer,
+    PersistenceError, SerializationFormat,
+};
 
 /// A serialized version of an event with metadata.
 /// Used by repositories to store and load events from a database.
@@ -19,21 +25,26 @@ pub struct SerializedEvent {
     pub event_type: String,
     /// The version of event that is serialized.
     pub event_version: String,
-    /// The serialized domain event.
-    pub payload: Value,
-    /// Additional metadata, serialized from a HashMap<String,String>.
+    /// The format the payload was serialized with.
+    pub format: SerializationFormat,
+    /// The codec the payload was compressed with, if any.
+    pub compression: Compression,
+    /// The serialized, and possibly compressed, domain event.
+    pub payload: Vec<u8>,
+    /// Additional metadata, serialized from the aggregate's `Metadata`.
     pub metadata: Value,
 }
 
 impl SerializedEvent {
-    /// Create a new SerializedEvent with the given values.
+    /// Create a new SerializedEvent with the given values and an uncompressed JSON payload, use
+    /// `with_format` and `with_compression` for a payload in another form.
     pub fn new(
         aggregate_id: String,
         sequence: usize,
         aggregate_type: String,
         event_type: String,
         event_version: String,
-        payload: Value,
+        payload: Vec<u8>,
         metadata: Value,
     ) -> Self {
         Self {
@@ -42,43 +53,124 @@ impl SerializedEvent {
             aggregate_type,
             event_type,
             event_version,
+            format: SerializationFormat::Json,
+            compression: Compression::None,
             payload,
             metadata,
         }
     }
+
+    /// Sets the format that the payload was serialized with.
+    pub fn with_format(self, format: SerializationFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Sets the codec that the payload was compressed with.
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    pub(crate) fn compress(self, policy: &CompressionPolicy) -> Result<Self, PersistenceError> {
+        let compression = policy.codec_for(&self.payload);
+        if compression == Compression::None {
+            return Ok(self);
+        }
+        Ok(Self {
+            payload: compression.compress(&self.payload)?,
+            compression,
+            ..self
+        })
+    }
+
+    pub(crate) fn decompress(self) -> Result<Self, PersistenceError> {
+        if self.compression == Compression::None {
+            return Ok(self);
+        }
+        Ok(Self {
+            payload: self.compression.decompress(&self.payload)?,
+            compression: Compression::None,
+            ..self
+        })
+    }
 }
 
 pub(crate) fn serialize_events<A: Aggregate>(
     events: &[EventEnvelope<A>],
+    serializer: &dyn EventSerializer<A::Event>,
 ) -> Result<Vec<SerializedEvent>, PersistenceError> {
     let mut result: Vec<SerializedEvent> = Default::default();
     for event in events {
-        result.push(SerializedEvent::try_from(event)?);
+        result.push(serialize_event(event, serializer)?);
     }
     Ok(result)
 }
 
+pub(crate) fn serialize_event<A: Aggregate>(
+    event: &EventEnvelope<A>,
+    serializer: &dyn EventSerializer<A::Event>,
+) -> Result<SerializedEvent, PersistenceError> {
+    Ok(SerializedEvent {
+        aggregate_id: event.aggregate_id.clone(),
+        sequence: event.sequence,
+        aggregate_type: A::aggregate_type(),
+        event_type: event.payload.event_type(),
+        event_version: event.payload.event_version(),
+        format: serializer.format(),
+        compression: Compression::None,
+        payload: serializer.serialize(&event.payload)?,
+        metadata: serde_json::to_value(&event.metadata)?,
+    })
+}
+
+pub(crate) fn deserialize_event<A: Aggregate>(
+    event: SerializedEvent,
+    serializer: Option<&dyn EventSerializer<A::Event>>,
+) -> Result<EventEnvelope<A>, PersistenceError> {
+    let event = event.decompress()?;
+    let payload = deserialize_payload(serializer, event.format, &event.payload)?;
+    let metadata = serde_json::from_value(event.metadata)?;
+    Ok(EventEnvelope {
+        aggregate_id: event.aggregate_id,
+        event_type: "".to_string(),
+        sequence: event.sequence,
+        system_id: "".to_string(),
+        payload,
+        metadata,
+    })
+}
+
+pub(crate) fn deserialize_snapshot<A: Aggregate>(
+    snapshot: SerializedSnapshot,
+    serializer: Option<&dyn EventSerializer<A>>,
+) -> Result<EventStoreAggregateContext<A>, PersistenceError> {
+    let snapshot = snapshot.decompress()?;
+    let aggregate = deserialize_payload(serializer, snapshot.format, &snapshot.aggregate)?;
+    Ok(EventStoreAggregateContext {
+        aggregate_id: snapshot.aggregate_id,
+        aggregate,
+        current_sequence: snapshot.current_sequence,
+        current_snapshot: Some(snapshot.current_snapshot),
+    })
+}
+
 pub(crate) fn deserialize_events<A: Aggregate>(
     events: Vec<SerializedEvent>,
     upcasters: &Option<Vec<Box<dyn EventUpcaster>>>,
+    serializer: &dyn EventSerializer<A::Event>,
 ) -> Result<Vec<EventEnvelope<A>>, PersistenceError> {
     let mut result: Vec<EventEnvelope<A>> = Default::default();
     for event in events {
-        let upcasted_event = match upcasters {
-            None => event,
-            Some(upcasters) => {
-                let mut upcasted_event = event;
-                for upcaster in upcasters {
-                    if upcaster
-                        .can_upcast(&upcasted_event.event_type, &upcasted_event.event_version)
-                    {
-                        upcasted_event = upcaster.upcast(upcasted_event)
-                    }
-                }
-                upcasted_event
-            }
+        let event = event.decompress()?;
+        let upcasted_events = match upcasters {
+            None => vec![event],
+            Some(upcasters) => upcast_event(event, upcasters)?,
         };
-        result.push(EventEnvelope::<A>::try_from(upcasted_event)?);
+        for upcasted_event in upcasted_events {
+            result.push(deserialize_event(upcasted_event, Some(serializer))?);
+        }
     }
     Ok(result)
 }
@@ -86,21 +178,9 @@ pub(crate) fn deserialize_events<A: Aggregate>(
 impl<A: Aggregate> TryFrom<&EventEnvelope<A>> for SerializedEvent {
     type Error = PersistenceError;
 
+    /// Serializes the event payload to JSON.
     fn try_from(event: &EventEnvelope<A>) -> Result<Self, Self::Error> {
-        let aggregate_type = A::aggregate_type();
-        let event_type = event.payload.event_type();
-        let event_version = event.payload.event_version();
-        let payload = serde_json::to_value(&event.payload)?;
-        let metadata = serde_json::to_value(&event.metadata)?;
-        Ok(Self {
-            aggregate_id: event.aggregate_id.clone(),
-            sequence: event.sequence,
-            aggregate_type,
-            event_type,
-            event_version,
-            payload,
-            metadata,
-        })
+        serialize_event(event, &JsonSerializer)
     }
 }
 
@@ -110,43 +190,57 @@ impl<A: Aggregate> TryFrom<&EventEnvelope<A>> for SerializedEvent {
 pub struct SerializedSnapshot {
     /// The aggregate ID of the aggregate instance that has been loaded.
     pub aggregate_id: String,
-    /// The current state of the aggregate instance.
-    pub aggregate: Value,
+    /// The format the aggregate was serialized with.
+    pub format: SerializationFormat,
+    /// The codec the aggregate was compressed with, if any.
+    pub compression: Compression,
+    /// The serialized, and possibly compressed, current state of the aggregate instance.
+    pub aggregate: Vec<u8>,
     /// The last committed event sequence number for this aggregate instance.
     pub current_sequence: usize,
     /// The last committed snapshot version for this aggregate instance.
     pub current_snapshot: usize,
 }
 
+impl SerializedSnapshot {
+    pub(crate) fn compress(self, policy: &CompressionPolicy) -> Result<Self, PersistenceError> {
+        let compression = policy.codec_for(&self.aggregate);
+        if compression == Compression::None {
+            return Ok(self);
+        }
+        Ok(Self {
+            aggregate: compression.compress(&self.aggregate)?,
+            compression,
+            ..self
+        })
+    }
+
+    pub(crate) fn decompress(self) -> Result<Self, PersistenceError> {
+        if self.compression == Compression::None {
+            return Ok(self);
+        }
+        Ok(Self {
+            aggregate: self.compression.decompress(&self.aggregate)?,
+            compression: Compression::None,
+            ..self
+        })
+    }
+}
+
 impl<A: Aggregate> TryFrom<SerializedSnapshot> for EventStoreAggregateContext<A> {
     type Error = PersistenceError;
 
+    /// Decompresses and deserializes the aggregate from JSON or postcard.
     fn try_from(snapshot: SerializedSnapshot) -> Result<Self, Self::Error> {
-        let aggregate = serde_json::from_value(snapshot.aggregate.clone())?;
-        Ok(Self {
-            aggregate_id: snapshot.aggregate_id,
-            aggregate,
-            current_sequence: snapshot.current_sequence,
-            current_snapshot: Some(snapshot.current_snapshot),
-        })
+        deserialize_snapshot(snapshot, None)
     }
 }
 
 impl<A: Aggregate> TryFrom<SerializedEvent> for EventEnvelope<A> {
     type Error = PersistenceError;
 
+    /// Decompresses and deserializes the event payload from JSON or postcard.
     fn try_from(event: SerializedEvent) -> Result<Self, Self::Error> {
-        let event_type = "".to_string();
-        let system_id = "".to_string();
-        let payload = serde_json::from_value(event.payload)?;
-        let metadata = serde_json::from_value(event.metadata)?;
-        Ok(Self {
-            aggregate_id: event.aggregate_id,
-            event_type,
-            sequence: event.sequence,
-            system_id,
-            payload,
-            metadata,
-        })
+        deserialize_event(event, None)
     }
 }
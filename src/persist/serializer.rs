@@ -0,0 +1,277 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use rkyv::de::deserializers::SharedDeserializeMap;
+use rkyv::ser::serializers::AllocSerializer;
+use rkyv::validation::validators::DefaultValidator;
+use rkyv::{Archive, CheckBytes};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::persist::PersistenceError;
+
+/// Scratch space used by rkyv while serializing, values needing more are allocated.
+const RKYV_SCRATCH_SPACE: usize = 256;
+
+/// Identifies how a serialized payload was written, stored alongside each event and snapshot
+/// so that payloads remain readable after the configured serializer changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SerializationFormat {
+    /// [JSON](https://www.json.org/) via `serde_json`, required for upcasting.
+    #[default]
+    Json,
+    /// The compact binary [postcard](https://docs.rs/postcard) format via serde.
+    Postcard,
+    /// The zero-copy [rkyv](https://docs.rs/rkyv) format.
+    Rkyv,
+}
+
+impl SerializationFormat {
+    /// The tag stored with each payload, e.g., `json`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerializationFormat::Json => "json",
+            SerializationFormat::Postcard => "postcard",
+            SerializationFormat::Rkyv => "rkyv",
+        }
+    }
+}
+
+impl Display for SerializationFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for SerializationFormat {
+    type Err = PersistenceError;
+
+    fn from_str(tag: &str) -> Result<Self, Self::Err> {
+        match tag {
+            "json" => Ok(SerializationFormat::Json),
+            "postcard" => Ok(SerializationFormat::Postcard),
+            "rkyv" => Ok(SerializationFormat::Rkyv),
+            _ => Err(PersistenceError::DeserializationError(
+                format!("unknown serialization format '{}'", tag).into(),
+            )),
+        }
+    }
+}
+
+/// Converts events and aggregate snapshots to and from the bytes held by a `SerializedEvent`
+/// or `SerializedSnapshot`.
+///
+/// Payloads are read with the serializer matching their stored format. Where this differs
+/// from the configured serializer, JSON and postcard payloads are still read, rkyv payloads
+/// require an `RkyvSerializer` to be configured.
+pub trait EventSerializer<T>: Send + Sync {
+    /// The format written by this serializer.
+    fn format(&self) -> SerializationFormat;
+
+    /// Serializes the value.
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, PersistenceError>;
+
+    /// Deserializes a value previously written by this serializer.
+    fn deserialize(&self, payload: &[u8]) -> Result<T, PersistenceError>;
+}
+
+/// Serializes to JSON, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonSerializer;
+
+impl<T: Serialize + DeserializeOwned> EventSerializer<T> for JsonSerializer {
+    fn format(&self) -> SerializationFormat {
+        SerializationFormat::Json
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, PersistenceError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn deserialize(&self, payload: &[u8]) -> Result<T, PersistenceError> {
+        Ok(serde_json::from_slice(payload)?)
+    }
+}
+
+/// Serializes to postcard, a compact binary format.
+///
+/// Postcard is not self-describing so types relying on `deserialize_any`, e.g., those using
+/// `#[serde(untagged)]` or holding a `serde_json::Value`, cannot be read back.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PostcardSerializer;
+
+impl<T: Serialize + DeserializeOwned> EventSerializer<T> for PostcardSerializer {
+    fn format(&self) -> SerializationFormat {
+        SerializationFormat::Postcard
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, PersistenceError> {
+        postcard::to_allocvec(value).map_err(|err| PersistenceError::UnknownError(Box::new(err)))
+    }
+
+    fn deserialize(&self, payload: &[u8]) -> Result<T, PersistenceError> {
+        postcard::from_bytes(payload)
+            .map_err(|err| PersistenceError::DeserializationError(Box::new(err)))
+    }
+}
+
+/// Serializes to rkyv, requiring the events and aggregate to derive `rkyv::Archive`,
+/// `rkyv::Serialize` and `rkyv::Deserialize` with `#[archive(check_bytes)]`.
+///
+/// Payloads are validated before they are deserialized, `RkyvSerializer::access` reads a
+/// payload in place without deserializing it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RkyvSerializer;
+
+impl RkyvSerializer {
+    /// Validates a payload and returns its archived form without copying or deserializing it.
+    ///
+    /// The payload must be aligned for the archived type, as it is when written into an
+    /// `rkyv::AlignedVec`; an unaligned payload is reported as an error.
+    pub fn access<T>(payload: &[u8]) -> Result<&T::Archived, PersistenceError>
+    where
+        T: Archive,
+        T::Archived: for<'a> CheckBytes<DefaultValidator<'a>>,
+    {
+        rkyv::check_archived_root::<T>(payload)
+            .map_err(|err| PersistenceError::DeserializationError(err.to_string().into()))
+    }
+}
+
+impl<T> EventSerializer<T> for RkyvSerializer
+where
+    T: Archive + rkyv::Serialize<AllocSerializer<RKYV_SCRATCH_SPACE>>,
+    T::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + rkyv::Deserialize<T, SharedDeserializeMap>,
+{
+    fn format(&self) -> SerializationFormat {
+        SerializationFormat::Rkyv
+    }
+
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, PersistenceError> {
+        let bytes = rkyv::to_bytes::<T, RKYV_SCRATCH_SPACE>(value)
+            .map_err(|err| PersistenceError::UnknownError(err.to_string().into()))?;
+        Ok(bytes.into_vec())
+    }
+
+    fn deserialize(&self, payload: &[u8]) -> Result<T, PersistenceError> {
+        // stored payloads carry no alignment guarantee
+        let mut aligned = rkyv::AlignedVec::with_capacity(payload.len());
+        aligned.extend_from_slice(payload);
+        rkyv::from_bytes::<T>(&aligned)
+            .map_err(|err| PersistenceError::DeserializationError(err.to_string().into()))
+    }
+}
+
+/// Deserializes a payload stored in `format`, using the configured serializer if it wrote that
+/// format and otherwise falling back to the serde based serializers.
+pub(crate) fn deserialize_payload<T: Serialize + DeserializeOwned>(
+    serializer: Option<&dyn EventSerializer<T>>,
+    format: SerializationFormat,
+    payload: &[u8],
+) -> Result<T, PersistenceError> {
+    match serializer {
+        Some(serializer) if serializer.format() == format => serializer.deserialize(payload),
+        _ => match format {
+            SerializationFormat::Json => JsonSerializer.deserialize(payload),
+            SerializationFormat::Postcard => PostcardSerializer.deserialize(payload),
+            SerializationFormat::Rkyv => Err(PersistenceError::DeserializationError(
+                "an RkyvSerializer is required to read rkyv payloads".into(),
+            )),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::serializer::deserialize_payload;
+    use crate::persist::{
+        EventSerializer, JsonSerializer, PersistenceError, PostcardSerializer, RkyvSerializer,
+        SerializationFormat,
+    };
+
+    #[derive(
+        Debug, PartialEq, Serialize, Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+    )]
+    #[archive(check_bytes)]
+    struct Shipped {
+        order_id: String,
+        items: Vec<u32>,
+    }
+
+    fn shipped() -> Shipped {
+        Shipped {
+            order_id: "order-A".to_string(),
+            items: vec![1, 2, 3],
+        }
+    }
+
+    fn round_trip<S: EventSerializer<Shipped>>(serializer: S) -> Vec<u8> {
+        let payload = serializer.serialize(&shipped()).unwrap();
+        assert_eq!(shipped(), serializer.deserialize(&payload).unwrap());
+        payload
+    }
+
+    #[test]
+    fn serializers_round_trip() {
+        let json = round_trip(JsonSerializer);
+        let postcard = round_trip(PostcardSerializer);
+        round_trip(RkyvSerializer);
+        assert!(postcard.len() < json.len());
+    }
+
+    #[test]
+    fn rkyv_access() {
+        let payload = rkyv::to_bytes::<_, 256>(&shipped()).unwrap();
+        let archived = RkyvSerializer::access::<Shipped>(&payload).unwrap();
+        assert_eq!("order-A", archived.order_id.as_str());
+        assert_eq!(3, archived.items.len());
+
+        let result = RkyvSerializer::access::<Shipped>(&payload[1..]);
+        assert!(matches!(
+            result,
+            Err(PersistenceError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_by_stored_format() {
+        let postcard =
+            EventSerializer::<Shipped>::serialize(&PostcardSerializer, &shipped()).unwrap();
+        let configured: &dyn EventSerializer<Shipped> = &JsonSerializer;
+        assert_eq!(
+            shipped(),
+            deserialize_payload(Some(configured), SerializationFormat::Postcard, &postcard)
+                .unwrap()
+        );
+
+        let rkyv = RkyvSerializer.serialize(&shipped()).unwrap();
+        let result =
+            deserialize_payload::<Shipped>(Some(configured), SerializationFormat::Rkyv, &rkyv);
+        assert!(result.is_err());
+        let configured: &dyn EventSerializer<Shipped> = &RkyvSerializer;
+        assert_eq!(
+            shipped(),
+            deserialize_payload(Some(configured), SerializationFormat::Rkyv, &rkyv).unwrap()
+        );
+    }
+
+    #[test]
+    fn format_tags() {
+        for format in [
+            SerializationFormat::Json,
+            SerializationFormat::Postcard,
+            SerializationFormat::Rkyv,
+        ] {
+            assert_eq!(
+                format,
+                SerializationFormat::from_str(format.as_str()).unwrap()
+            );
+        }
+        assert!(SerializationFormat::from_str("xml").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! A [SQLite](https://www.sqlite.org/) backed event and view repository for small services
+//! that want a SQL database without running a server.
+//!
+//! Enabled with the `sqlite` feature.
+//!
+//! ```
+//! # use actuality::doc::setup::{MyAggregate, MyService};
+//! # use actuality::doc::persist::MyView;
+//! use std::sync::Arc;
+//! use actuality::Cqrs;
+//! use actuality::persist::{GenericQuery, PersistedEventStore};
+//! use actuality::persist::sqlite::{SqliteDatabase, SqliteEventRepository, SqliteViewRepository};
+//!
+//! # fn config() -> Result<(), actuality::persist::PersistenceError> {
+//! let database = SqliteDatabase::open("my-service.db")?;
+//! let view_repo = SqliteViewRepository::<MyView, MyAggregate>::new("my_view", database.clone())?;
+//! let query = GenericQuery::new(Arc::new(view_repo));
+//! let store = PersistedEventStore::<_, MyAggregate>::new_event_store(SqliteEventRepository::new(database));
+//! let cqrs = Cqrs::new(store, vec![Box::new(query)], MyService);
+//! # Ok(())
+//! # }
+//! ```
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{ffi, Connection, ErrorCode};
+
+use crate::persist::PersistenceError;
+
+pub use event_repository::SqliteEventRepository;
+pub use view_repository::SqliteViewRepository;
+
+mod event_repository;
+mod view_repository;
+
+/// Schema migrations for the event and snapshot tables, applied in order when a database is
+/// opened.
+const MIGRATIONS: &[(i64, &str, &str)] = &[
+    (
+        1,
+        "create_events",
+        include_str!("../../migrations/sqlite/0001_create_events.sql"),
+    ),
+    (
+        2,
+        "create_snapshots",
+        include_str!("../../migrations/sqlite/0002_create_snapshots.sql"),
+    ),
+    (
+        3,
+        "serialization_format",
+        include_str!("../../migrations/sqlite/0003_serialization_format.sql"),
+    ),
+    (
+        4,
+        "compression",
+        include_str!("../../migrations/sqlite/0004_compression.sql"),
+    ),
+    (
+        5,
+        "commit_time",
+        include_str!("../../migrations/sqlite/0005_commit_time.sql"),
+    ),
+];
+
+/// A shared connection to a SQLite database, used by both the `SqliteEventRepository` and any
+/// `SqliteViewRepository`s.
+///
+/// Database calls are made on the blocking thread pool so that they do not stall the async
+/// runtime.
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteDatabase {
+    /// Opens, or creates, the database file at the provided path and applies any outstanding
+    /// schema migrations.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+        let connection = Connection::open(path)
+            .map_err(|err| PersistenceError::ConnectionError(Box::new(err)))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        Self::with_connection(connection)
+    }
+
+    /// Opens a new in-memory database, useful for testing.
+    pub fn open_in_memory() -> Result<Self, PersistenceError> {
+        let connection = Connection::open_in_memory()
+            .map_err(|err| PersistenceError::ConnectionError(Box::new(err)))?;
+        Self::with_connection(connection)
+    }
+
+    /// Uses an existing connection, applying any outstanding schema migrations.
+    pub fn with_connection(mut connection: Connection) -> Result<Self, PersistenceError> {
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub(crate) async fn call<T, F>(&self, f: F) -> Result<T, PersistenceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, PersistenceError> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            // uninteresting unwrap: a poisoned lock means a panic occurred within a transaction
+            let mut connection = connection.lock().unwrap();
+            f(&mut connection)
+        })
+        .await
+        .map_err(|err| PersistenceError::UnknownError(Box::new(err)))?
+    }
+}
+
+/// Applies each migration that has not yet been recorded in the `actuality_migrations` table.
+fn migrate(connection: &mut Connection) -> Result<(), PersistenceError> {
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS actuality_migrations
+         (
+             version    INTEGER PRIMARY KEY,
+             name       TEXT NOT NULL,
+             applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+         );",
+    )?;
+    let applied: i64 = connection.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM actuality_migrations",
+        [],
+        |row| row.get(0),
+    )?;
+    for (version, name, sql) in MIGRATIONS {
+        if *version <= applied {
+            continue;
+        }
+        let tx = connection.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.execute(
+            "INSERT INTO actuality_migrations (version, name) VALUES (?1, ?2)",
+            rusqlite::params![version, name],
+        )?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+impl From<rusqlite::Error> for PersistenceError {
+    fn from(err: rusqlite::Error) -> Self {
+        match &err {
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+                // a unique key of events, snapshots or views was already taken by another writer
+                ErrorCode::ConstraintViolation
+                    if failure.extended_code == ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+                        || failure.extended_code == ffi::SQLITE_CONSTRAINT_UNIQUE =>
+                {
+                    PersistenceError::OptimisticLockError
+                }
+                ErrorCode::CannotOpen
+                | ErrorCode::DatabaseBusy
+                | ErrorCode::DatabaseLocked
+                | ErrorCode::NotADatabase => PersistenceError::ConnectionError(Box::new(err)),
+                _ => PersistenceError::UnknownError(Box::new(err)),
+            },
+            rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..)
+            | rusqlite::Error::InvalidColumnType(..) => {
+                PersistenceError::DeserializationError(Box::new(err))
+            }
+            _ => PersistenceError::UnknownError(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::persist::sqlite::{migrate, SqliteDatabase, MIGRATIONS};
+    use crate::persist::PersistenceError;
+
+    #[tokio::test]
+    async fn migrations_applied_once() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let applied: i64 = database
+            .call(|connection| {
+                migrate(connection)?;
+                Ok(connection.query_row(
+                    "SELECT COUNT(*) FROM actuality_migrations",
+                    [],
+                    |row| row.get(0),
+                )?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(MIGRATIONS.len() as i64, applied);
+    }
+
+    #[tokio::test]
+    async fn constraint_violations() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let insert = |sql: &'static str| {
+            database.call(move |connection| {
+                connection.execute(sql, [])?;
+                Ok(())
+            })
+        };
+        insert("CREATE TABLE keyed (key TEXT PRIMARY KEY, value TEXT NOT NULL)")
+            .await
+            .unwrap();
+        insert("INSERT INTO keyed VALUES ('a', 'b')").await.unwrap();
+
+        let result = insert("INSERT INTO keyed VALUES ('a', 'c')").await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let result = insert("INSERT INTO keyed VALUES ('b', NULL)").await;
+        assert!(matches!(result, Err(PersistenceError::UnknownError(_))));
+    }
+}
@@ -0,0 +1,615 @@
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, OptionalExtension, Row};
+
+use crate::persist::event_stream::ReplayStream;
+use crate::persist::sqlite::SqliteDatabase;
+use crate::persist::{
+    EventFilter, PersistedEventRepository, PersistenceError, SerializedEvent, SerializedSnapshot,
+};
+use crate::Aggregate;
+
+const DEFAULT_STREAM_CHANNEL_SIZE: usize = 200;
+
+/// Commit times are stored as UTC timestamps with millisecond precision, in a format that
+/// orders as text.
+const COMMIT_TIME: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+const SELECT_EVENTS: &str = "SELECT position, aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, format, compression
+  FROM events";
+
+/// A SQLite backed event repository for use in backing a `PersistedEventStore`.
+pub struct SqliteEventRepository {
+    database: SqliteDatabase,
+    stream_channel_size: usize,
+}
+
+impl SqliteEventRepository {
+    /// Creates a new `SqliteEventRepository` using the provided database.
+    ///
+    /// ```
+    /// use actuality::persist::sqlite::{SqliteDatabase, SqliteEventRepository};
+    ///
+    /// let database = SqliteDatabase::open_in_memory().unwrap();
+    /// let repo = SqliteEventRepository::new(database);
+    /// ```
+    pub fn new(database: SqliteDatabase) -> Self {
+        Self {
+            database,
+            stream_channel_size: DEFAULT_STREAM_CHANNEL_SIZE,
+        }
+    }
+
+    /// Configures the number of events read from the database and buffered in a
+    /// `ReplayStream` at a time, defaults to 200.
+    pub fn with_streaming_channel_size(self, stream_channel_size: usize) -> Self {
+        Self {
+            stream_channel_size,
+            ..self
+        }
+    }
+
+    async fn select_events(
+        &self,
+        aggregate_type: String,
+        aggregate_id: String,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.database
+            .call(move |connection| {
+                let mut statement = connection.prepare_cached(&format!(
+                    "{} WHERE aggregate_type = ?1 AND aggregate_id = ?2 AND sequence > ?3 ORDER BY sequence",
+                    SELECT_EVENTS
+                ))?;
+                let mut rows =
+                    statement.query(params![aggregate_type, aggregate_id, last_sequence as i64])?;
+                let mut events = Vec::new();
+                while let Some(row) = rows.next()? {
+                    events.push(read_event(row)?.1);
+                }
+                Ok(events)
+            })
+            .await
+    }
+
+    /// Streams matching events one page at a time, the connection is released between pages
+    /// so that consumers of the stream may write to the same database.
+    fn stream(&self, aggregate_type: String, filter: EventFilter) -> ReplayStream {
+        let (mut feed, stream) = ReplayStream::new(self.stream_channel_size);
+        let database = self.database.clone();
+        let page_size = self.stream_channel_size.max(1) as i64;
+        let (conditions, filter_params) = filter_conditions(&filter);
+        let sql = format!(
+            "{} WHERE aggregate_type = ?1 AND position > ?2{} ORDER BY position LIMIT ?3",
+            SELECT_EVENTS, conditions
+        );
+        tokio::spawn(async move {
+            let mut last_position = 0;
+            loop {
+                let sql = sql.clone();
+                let mut params: Vec<Value> = vec![
+                    Value::Text(aggregate_type.clone()),
+                    Value::Integer(last_position),
+                    Value::Integer(page_size),
+                ];
+                params.extend(filter_params.iter().cloned());
+                let page = database
+                    .call(move |connection| {
+                        let mut statement = connection.prepare_cached(&sql)?;
+                        let mut rows = statement.query(params_from_iter(params))?;
+                        let mut page = Vec::new();
+                        while let Some(row) = rows.next()? {
+                            page.push(read_event(row)?);
+                        }
+                        Ok(page)
+                    })
+                    .await;
+                let page = match page {
+                    Ok(page) => page,
+                    Err(err) => {
+                        let _ = feed.push(Err(err)).await;
+                        return;
+                    }
+                };
+                let complete = (page.len() as i64) < page_size;
+                for (position, event) in page {
+                    last_position = position;
+                    if feed.push(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                if complete {
+                    return;
+                }
+            }
+        });
+        stream
+    }
+}
+
+#[async_trait]
+impl PersistedEventRepository for SqliteEventRepository {
+    async fn get_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events(A::aggregate_type(), aggregate_id.to_string(), 0)
+            .await
+    }
+
+    async fn get_last_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+        last_sequence: usize,
+    ) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        self.select_events(A::aggregate_type(), aggregate_id.to_string(), last_sequence)
+            .await
+    }
+
+    async fn get_snapshot<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Option<SerializedSnapshot>, PersistenceError> {
+        let aggregate_type = A::aggregate_type();
+        let aggregate_id = aggregate_id.to_string();
+        self.database
+            .call(move |connection| {
+                let row = connection
+                    .query_row(
+                        "SELECT last_sequence, snapshot_version, payload, format, compression
+                           FROM snapshots
+                          WHERE aggregate_type = ?1 AND aggregate_id = ?2",
+                        params![aggregate_type, aggregate_id],
+                        |row| {
+                            Ok((
+                                row.get::<_, i64>(0)?,
+                                row.get::<_, i64>(1)?,
+                                row.get::<_, Vec<u8>>(2)?,
+                                row.get::<_, String>(3)?,
+                                row.get::<_, String>(4)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+                match row {
+                    None => Ok(None),
+                    Some((last_sequence, snapshot_version, payload, format, compression)) => {
+                        Ok(Some(SerializedSnapshot {
+                            aggregate_id,
+                            format: format.parse()?,
+                            compression: compression.parse()?,
+                            aggregate: payload,
+                            current_sequence: last_sequence as usize,
+                            current_snapshot: snapshot_version as usize,
+                        }))
+                    }
+                }
+            })
+            .await
+    }
+
+    async fn persist<A: Aggregate>(
+        &self,
+        events: &[SerializedEvent],
+        snapshot_update: Option<SerializedSnapshot>,
+    ) -> Result<(), PersistenceError> {
+        let aggregate_type = A::aggregate_type();
+        let events = events.to_vec();
+        self.database
+            .call(move |connection| {
+                let tx = connection.transaction()?;
+                {
+                    let mut insert = tx.prepare_cached(&format!(
+                        "INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version, payload, metadata, format, compression, committed_at)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, {})",
+                        COMMIT_TIME
+                    ))?;
+                    for event in &events {
+                        insert.execute(params![
+                            aggregate_type,
+                            event.aggregate_id,
+                            event.sequence as i64,
+                            event.event_type,
+                            event.event_version,
+                            event.payload,
+                            serde_json::to_string(&event.metadata)?,
+                            event.format.as_str(),
+                            event.compression.as_str(),
+                        ])?;
+                    }
+                }
+                if let Some(snapshot) = snapshot_update {
+                    let current_snapshot = snapshot.current_snapshot as i64;
+                    let updated = if current_snapshot == 1 {
+                        tx.execute(
+                            "INSERT INTO snapshots (aggregate_type, aggregate_id, last_sequence, snapshot_version, payload, format, compression)
+                             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                            params![
+                                aggregate_type,
+                                snapshot.aggregate_id,
+                                snapshot.current_sequence as i64,
+                                current_snapshot,
+                                snapshot.aggregate,
+                                snapshot.format.as_str(),
+                                snapshot.compression.as_str()
+                            ],
+                        )?
+                    } else {
+                        tx.execute(
+                            "UPDATE snapshots
+                                SET last_sequence = ?3, snapshot_version = ?4, payload = ?5, format = ?6, compression = ?7
+                              WHERE aggregate_type = ?1 AND aggregate_id = ?2 AND snapshot_version = ?8",
+                            params![
+                                aggregate_type,
+                                snapshot.aggregate_id,
+                                snapshot.current_sequence as i64,
+                                current_snapshot,
+                                snapshot.aggregate,
+                                snapshot.format.as_str(),
+                                snapshot.compression.as_str(),
+                                current_snapshot - 1
+                            ],
+                        )?
+                    };
+                    if updated != 1 {
+                        return Err(PersistenceError::OptimisticLockError);
+                    }
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn stream_events<A: Aggregate>(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<ReplayStream, PersistenceError> {
+        let filter = EventFilter::default().with_aggregate_ids([aggregate_id]);
+        Ok(self.stream(A::aggregate_type(), filter))
+    }
+
+    async fn stream_all_events<A: Aggregate>(&self) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream(A::aggregate_type(), EventFilter::default()))
+    }
+
+    async fn stream_filtered_events<A: Aggregate>(
+        &self,
+        filter: &EventFilter,
+    ) -> Result<ReplayStream, PersistenceError> {
+        Ok(self.stream(A::aggregate_type(), filter.clone()))
+    }
+}
+
+/// Renders the filter as conditions appended to a `WHERE` clause, with the parameters they
+/// bind numbered from `?4`.
+fn filter_conditions(filter: &EventFilter) -> (String, Vec<Value>) {
+    let mut conditions = String::new();
+    let mut params = Vec::new();
+    let mut bind = |value: Value| {
+        params.push(value);
+        format!("?{}", params.len() + 3)
+    };
+    for (column, values) in [
+        ("aggregate_id", &filter.aggregate_ids),
+        ("event_type", &filter.event_types),
+    ] {
+        // bound as a single JSON array, the number of values is not limited by the maximum
+        // number of bound variables
+        if let Some(values) = values {
+            let values = Value::Text(serde_json::Value::from(values.clone()).to_string());
+            conditions.push_str(&format!(
+                " AND {} IN (SELECT value FROM json_each({}))",
+                column,
+                bind(values)
+            ));
+        }
+    }
+    for (condition, bound) in [
+        (
+            "sequence >=",
+            filter
+                .min_sequence
+                .map(|sequence| i64::try_from(sequence).unwrap_or(i64::MAX)),
+        ),
+        (
+            "sequence <=",
+            filter
+                .max_sequence
+                .map(|sequence| i64::try_from(sequence).unwrap_or(i64::MAX)),
+        ),
+        ("position >=", filter.min_position),
+        ("position <=", filter.max_position),
+    ] {
+        if let Some(bound) = bound {
+            conditions.push_str(&format!(
+                " AND {} {}",
+                condition,
+                bind(Value::Integer(bound))
+            ));
+        }
+    }
+    for (condition, time) in [
+        ("committed_at >=", &filter.committed_from),
+        ("committed_at <", &filter.committed_until),
+    ] {
+        if let Some(time) = time {
+            let time = Value::Text(time.to_rfc3339_opts(SecondsFormat::Millis, true));
+            conditions.push_str(&format!(" AND {} {}", condition, bind(time)));
+        }
+    }
+    (conditions, params)
+}
+
+fn read_event(row: &Row) -> Result<(i64, SerializedEvent), PersistenceError> {
+    let metadata: String = row.get(7)?;
+    let format: String = row.get(8)?;
+    let compression: String = row.get(9)?;
+    let event = SerializedEvent::new(
+        row.get(2)?,
+        row.get::<_, i64>(3)? as usize,
+        row.get(1)?,
+        row.get(4)?,
+        row.get(5)?,
+        row.get(6)?,
+        serde_json::from_str(&metadata)?,
+    )
+    .with_format(format.parse()?)
+    .with_compression(compression.parse()?);
+    Ok((row.get(0)?, event))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use crate::persist::event_store::shared_test::{
+        test_serialized_event, test_serialized_snapshot, TestAggregate, TestEvents,
+        TEST_AGGREGATE_ID,
+    };
+    use crate::persist::sqlite::{SqliteDatabase, SqliteEventRepository};
+    use crate::persist::{
+        Compression, EventFilter, PersistedEventRepository, PersistenceError, SerializationFormat,
+        SerializedEvent, SerializedSnapshot,
+    };
+
+    fn event(aggregate_id: &str, sequence: usize) -> SerializedEvent {
+        let mut event = test_serialized_event(sequence, TestEvents::SomethingWasDone);
+        event.aggregate_id = aggregate_id.to_string();
+        event
+    }
+
+    fn repo() -> SqliteEventRepository {
+        SqliteEventRepository::new(SqliteDatabase::open_in_memory().unwrap())
+    }
+
+    #[tokio::test]
+    async fn persist_and_load() {
+        let repo = repo();
+        repo.persist::<TestAggregate>(
+            &[
+                event(TEST_AGGREGATE_ID, 1),
+                event(TEST_AGGREGATE_ID, 2),
+                event("other", 1),
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![event(TEST_AGGREGATE_ID, 1), event(TEST_AGGREGATE_ID, 2)],
+            events
+        );
+        let events = repo
+            .get_last_events::<TestAggregate>(TEST_AGGREGATE_ID, 1)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 2)], events);
+    }
+
+    #[tokio::test]
+    async fn binary_payloads() {
+        let repo = repo();
+        let event = event(TEST_AGGREGATE_ID, 1)
+            .with_format(SerializationFormat::Postcard)
+            .with_compression(Compression::Zstd);
+        let event = SerializedEvent {
+            payload: vec![0, 159, 146, 150],
+            ..event
+        };
+        let snapshot = || SerializedSnapshot {
+            compression: Compression::Lz4,
+            aggregate: vec![0, 159, 146, 150],
+            ..test_serialized_snapshot(TEST_AGGREGATE_ID, 1, 1, 1)
+        };
+        repo.persist::<TestAggregate>(std::slice::from_ref(&event), Some(snapshot()))
+            .await
+            .unwrap();
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event], events);
+        let stored = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(Some(snapshot()), stored);
+    }
+
+    #[tokio::test]
+    async fn optimistic_lock() {
+        let repo = repo();
+        repo.persist::<TestAggregate>(&[event(TEST_AGGREGATE_ID, 1)], None)
+            .await
+            .unwrap();
+        let result = repo
+            .persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, 2), event(TEST_AGGREGATE_ID, 1)],
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        // the failed commit is rolled back in its entirety
+        let events = repo
+            .get_events::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap();
+        assert_eq!(vec![event(TEST_AGGREGATE_ID, 1)], events);
+    }
+
+    #[tokio::test]
+    async fn snapshots() {
+        let repo = repo();
+        assert_eq!(
+            None,
+            repo.get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+                .await
+                .unwrap()
+        );
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 1), event(TEST_AGGREGATE_ID, 2)],
+            Some(test_serialized_snapshot(TEST_AGGREGATE_ID, 2, 2, 1)),
+        )
+        .await
+        .unwrap();
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_vec(&json!({"something_happened": 2})).unwrap(),
+            snapshot.aggregate
+        );
+        assert_eq!(2, snapshot.current_sequence);
+        assert_eq!(1, snapshot.current_snapshot);
+
+        // a stale snapshot version is rejected
+        let result = repo
+            .persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, 3)],
+                Some(test_serialized_snapshot(TEST_AGGREGATE_ID, 3, 3, 3)),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+
+        repo.persist::<TestAggregate>(
+            &[event(TEST_AGGREGATE_ID, 3)],
+            Some(test_serialized_snapshot(TEST_AGGREGATE_ID, 3, 3, 2)),
+        )
+        .await
+        .unwrap();
+        let snapshot = repo
+            .get_snapshot::<TestAggregate>(TEST_AGGREGATE_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(3, snapshot.current_sequence);
+        assert_eq!(2, snapshot.current_snapshot);
+    }
+
+    #[tokio::test]
+    async fn stream_events() {
+        let repo = repo().with_streaming_channel_size(2);
+        for sequence in 1..=3 {
+            repo.persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, sequence), event("other", sequence)],
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut stream = repo.stream_all_events::<TestAggregate>().await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            let event = event.unwrap();
+            found.push((event.aggregate_id, event.sequence));
+        }
+        assert_eq!(6, found.len());
+        assert_eq!((TEST_AGGREGATE_ID.to_string(), 1), found[0]);
+        assert_eq!(("other".to_string(), 3), found[5]);
+
+        let mut stream = repo.stream_events::<TestAggregate>("other").await.unwrap();
+        let mut found = Vec::new();
+        while let Some(event) = stream.next::<TestAggregate>().await {
+            found.push(event.unwrap().sequence);
+        }
+        assert_eq!(vec![1, 2, 3], found);
+    }
+
+    #[tokio::test]
+    async fn stream_filtered_events() {
+        let repo = repo().with_streaming_channel_size(2);
+        for sequence in 1..=3 {
+            repo.persist::<TestAggregate>(
+                &[event(TEST_AGGREGATE_ID, sequence), event("other", sequence)],
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let found = |filter: EventFilter| {
+            let repo = &repo;
+            async move {
+                let mut stream = repo
+                    .stream_filtered_events::<TestAggregate>(&filter)
+                    .await
+                    .unwrap();
+                let mut found = Vec::new();
+                while let Some(event) = stream.next::<TestAggregate>().await {
+                    let event = event.unwrap();
+                    found.push((event.aggregate_id, event.sequence));
+                }
+                found
+            }
+        };
+        let filter = EventFilter::default()
+            .with_aggregate_ids(["other"])
+            .with_sequences(2..);
+        assert_eq!(
+            vec![("other".to_string(), 2), ("other".to_string(), 3)],
+            found(filter).await
+        );
+        // more aggregate ids than SQLite allows bound variables
+        let aggregate_ids = (0..40_000)
+            .map(|i| format!("missing-{}", i))
+            .chain(["other".to_string()]);
+        let filter = EventFilter::default()
+            .with_aggregate_ids(aggregate_ids)
+            .with_sequences(..=1);
+        assert_eq!(vec![("other".to_string(), 1)], found(filter).await);
+        let filter = EventFilter::default().with_positions(2..=3);
+        assert_eq!(
+            vec![("other".to_string(), 1), (TEST_AGGREGATE_ID.to_string(), 2)],
+            found(filter).await
+        );
+        let filter = EventFilter::default().with_event_types(["Started"]);
+        assert!(found(filter).await.is_empty());
+        let filter = EventFilter::default().with_sequences((
+            std::ops::Bound::Excluded(usize::MAX),
+            std::ops::Bound::Unbounded,
+        ));
+        assert!(found(filter).await.is_empty());
+        let filter = EventFilter::default().with_positions(..i64::MIN);
+        assert!(found(filter).await.is_empty());
+
+        let now = Utc::now();
+        let filter = EventFilter::default()
+            .with_committed_between(now - Duration::hours(1), now + Duration::hours(1));
+        assert_eq!(6, found(filter).await.len());
+        let filter = EventFilter::default()
+            .with_committed_between(now - Duration::hours(2), now - Duration::hours(1));
+        assert!(found(filter).await.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+use rusqlite::{params, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::persist::sqlite::SqliteDatabase;
+use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+
+/// A SQLite backed query repository for use in backing a `GenericQuery` or a
+/// `MultiSourceQuery`.
+///
+/// Each view is stored in its own table, created when the repository is constructed, holding
+/// the serialized view along with the version used for optimistic locking.
+pub struct SqliteViewRepository<V, A> {
+    database: SqliteDatabase,
+    insert_sql: String,
+    update_sql: String,
+    select_sql: String,
+    phantom: PhantomData<(V, A)>,
+}
+
+impl<V, A> SqliteViewRepository<V, A> {
+    /// Creates a new `SqliteViewRepository` that will store serialized views in a SQLite table
+    /// named identically to the `view_name` value provided. The table is created if it does not
+    /// already exist.
+    ///
+    /// ```
+    /// # use actuality::doc::setup::MyAggregate;
+    /// # use actuality::doc::persist::MyView;
+    /// use actuality::persist::sqlite::{SqliteDatabase, SqliteViewRepository};
+    ///
+    /// let database = SqliteDatabase::open_in_memory().unwrap();
+    /// let repo = SqliteViewRepository::<MyView, MyAggregate>::new("my_view_table", database).unwrap();
+    /// ```
+    pub fn new(view_name: &str, database: SqliteDatabase) -> Result<Self, PersistenceError> {
+        if view_name.is_empty()
+            || !view_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(PersistenceError::UnknownError(
+                format!("invalid view name '{}'", view_name).into(),
+            ));
+        }
+        let repo = Self {
+            insert_sql: format!(
+                "INSERT INTO {} (payload, version, view_id) VALUES (?1, ?2, ?3)",
+                view_name
+            ),
+            update_sql: format!(
+                "UPDATE {} SET payload = ?1, version = ?2 WHERE view_id = ?3 AND version = ?4",
+                view_name
+            ),
+            select_sql: format!(
+                "SELECT version, payload FROM {} WHERE view_id = ?1",
+                view_name
+            ),
+            database,
+            phantom: Default::default(),
+        };
+        let create_sql = format!(
+            "CREATE TABLE IF NOT EXISTS {}
+             (
+                 view_id TEXT    PRIMARY KEY,
+                 version INTEGER NOT NULL CHECK (version >= 0),
+                 payload TEXT    NOT NULL
+             )",
+            view_name
+        );
+        // uninteresting unwrap: a poisoned lock means a panic occurred within a transaction
+        repo.database
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch(&create_sql)?;
+        Ok(repo)
+    }
+}
+
+#[async_trait]
+impl<V, A> ViewRepository<V, A> for SqliteViewRepository<V, A>
+where
+    V: Serialize + DeserializeOwned + Send + Sync,
+    A: Send + Sync,
+{
+    async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError> {
+        Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+    }
+
+    async fn load_with_context(
+        &self,
+        view_id: &str,
+    ) -> Result<Option<(V, ViewContext)>, PersistenceError> {
+        let select_sql = self.select_sql.clone();
+        let view_id = view_id.to_string();
+        let row = self
+            .database
+            .call(move |connection| {
+                let row = connection
+                    .query_row(&select_sql, params![view_id], |row| {
+                        Ok((
+                            view_id.clone(),
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, String>(1)?,
+                        ))
+                    })
+                    .optional()?;
+                Ok(row)
+            })
+            .await?;
+        match row {
+            None => Ok(None),
+            Some((view_id, version, payload)) => {
+                let view = serde_json::from_str(&payload)?;
+                Ok(Some((view, ViewContext::new(view_id, version))))
+            }
+        }
+    }
+
+    async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError> {
+        let payload = serde_json::to_string(&view)?;
+        let insert_sql = self.insert_sql.clone();
+        let update_sql = self.update_sql.clone();
+        self.database
+            .call(move |connection| {
+                let version = context.version + 1;
+                let updated = if context.version == 0 {
+                    connection.execute(
+                        &insert_sql,
+                        params![payload, version, context.view_instance_id],
+                    )?
+                } else {
+                    connection.execute(
+                        &update_sql,
+                        params![payload, version, context.view_instance_id, context.version],
+                    )?
+                };
+                if updated != 1 {
+                    return Err(PersistenceError::OptimisticLockError);
+                }
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+
+    use crate::persist::event_store::shared_test::{TestAggregate, TestEvents};
+    use crate::persist::sqlite::{SqliteDatabase, SqliteViewRepository};
+    use crate::persist::{PersistenceError, ViewContext, ViewRepository};
+    use crate::{EventEnvelope, View};
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct TestView {
+        events: usize,
+    }
+
+    impl View<TestAggregate> for TestView {
+        fn update(&mut self, event: &EventEnvelope<TestAggregate>) {
+            if event.payload == TestEvents::SomethingWasDone {
+                self.events += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn update_views() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let repo =
+            SqliteViewRepository::<TestView, TestAggregate>::new("test_view", database).unwrap();
+        assert!(repo.load("view-A").await.unwrap().is_none());
+
+        repo.update_view(
+            TestView { events: 1 },
+            ViewContext::new("view-A".to_string(), 0),
+        )
+        .await
+        .unwrap();
+        let (view, context) = repo.load_with_context("view-A").await.unwrap().unwrap();
+        assert_eq!(TestView { events: 1 }, view);
+        assert_eq!(1, context.version);
+
+        repo.update_view(TestView { events: 2 }, context)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(TestView { events: 2 }),
+            repo.load("view-A").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn optimistic_lock() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let repo =
+            SqliteViewRepository::<TestView, TestAggregate>::new("test_view", database).unwrap();
+        repo.update_view(
+            TestView { events: 1 },
+            ViewContext::new("view-A".to_string(), 0),
+        )
+        .await
+        .unwrap();
+
+        let result = repo
+            .update_view(
+                TestView { events: 1 },
+                ViewContext::new("view-A".to_string(), 0),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+        let result = repo
+            .update_view(
+                TestView { events: 1 },
+                ViewContext::new("view-A".to_string(), 2),
+            )
+            .await;
+        assert!(matches!(result, Err(PersistenceError::OptimisticLockError)));
+    }
+
+    #[test]
+    fn invalid_view_name() {
+        let database = SqliteDatabase::open_in_memory().unwrap();
+        let result = SqliteViewRepository::<TestView, TestAggregate>::new(
+            "views; DROP TABLE events",
+            database,
+        );
+        assert!(result.is_err());
+    }
+}
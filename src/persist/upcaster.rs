@@ -3,23 +3,80 @@ use std::fmt::{Display, Formatter};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
-use crate::persist::SerializedEvent;
+use crate::persist::{PersistenceError, SerializationFormat, SerializedEvent};
+
+pub use registry::{AppliedUpcaster, UpcasterRegistry, UpcasterRegistryError, VersionedUpcaster};
+
+mod registry;
 
 /// Used to upcast and event from an older type or version to the current form. This is needed
 /// to modify the structure of events older versions are already persisted.
+///
+/// An event may be replaced by any number of events, allowing a coarse legacy event to be split
+/// into finer events or an obsolete event to be dropped entirely.
+///
+/// ```
+/// use actuality::persist::{EventUpcaster, PersistenceError, SerializedEvent};
+///
+/// /// Splits the legacy `AddressChanged` event into `StreetChanged` and `CityChanged`.
+/// struct SplitAddressChanged;
+///
+/// impl EventUpcaster for SplitAddressChanged {
+///     fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+///         event_type == "AddressChanged"
+///     }
+///
+///     fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+///         let payload: serde_json::Value = serde_json::from_slice(&event.payload)?;
+///         let street = SerializedEvent {
+///             event_type: "StreetChanged".to_string(),
+///             payload: serde_json::to_vec(&payload["street"])?,
+///             ..event.clone()
+///         };
+///         let city = SerializedEvent {
+///             event_type: "CityChanged".to_string(),
+///             payload: serde_json::to_vec(&payload["city"])?,
+///             ..event
+///         };
+///         Ok(vec![street, city])
+///     }
+/// }
+/// ```
 pub trait EventUpcaster: Send + Sync {
     /// Examines and event type and version to understand if the event should be upcasted.
     fn can_upcast(&self, event_type: &str, event_version: &str) -> bool;
 
-    /// Modifies the serialized event to conform the the new structure.
-    fn upcast(&self, event: SerializedEvent) -> SerializedEvent;
+    /// Modifies the serialized event to conform the the new structure, returning the events
+    /// that replace it.
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError>;
+}
+
+/// Applies each upcaster in turn to the event and to every event produced by the upcasters
+/// before it.
+pub(crate) fn upcast_event(
+    event: SerializedEvent,
+    upcasters: &[Box<dyn EventUpcaster>],
+) -> Result<Vec<SerializedEvent>, PersistenceError> {
+    let mut events = vec![event];
+    for upcaster in upcasters {
+        let mut upcasted_events = Vec::with_capacity(events.len());
+        for event in events {
+            if upcaster.can_upcast(&event.event_type, &event.event_version) {
+                upcasted_events.extend(upcaster.upcast(event)?);
+            } else {
+                upcasted_events.push(event);
+            }
+        }
+        events = upcasted_events;
+    }
+    Ok(events)
 }
 
 /// A helper type for creating the upcaster function for a `SemanticVersionEventUpcaster`.
 pub type SemanticVersionEventUpcasterFunc = dyn Fn(Value) -> Value + Send + Sync;
 
 /// A representation of a semantic version used in a `SemanticVersionEventUpcaster`.
-#[derive(Debug, PartialOrd, PartialEq)]
+#[derive(Clone, Debug, PartialOrd, PartialEq)]
 pub struct SemanticVersion {
     major_version: u32,
     minor_version: u32,
@@ -123,10 +180,10 @@ impl From<ParseIntError> for SemanticVersionError {
 ///             "".to_string(),
 ///             "".to_string(),
 ///             "".to_string(),
-///             payload,
+///             serde_json::to_vec(&payload).unwrap(),
 ///             Default::default(),
 ///         );
-/// let upcasted_event = upcaster.upcast(event);
+/// let upcasted_events = upcaster.upcast(event).unwrap();
 ///
 /// let expected_payload: Value = serde_json::from_str(
 ///             r#"{
@@ -141,11 +198,11 @@ impl From<ParseIntError> for SemanticVersionError {
 ///             "".to_string(),
 ///             "".to_string(),
 ///             "2.3.4".to_string(),
-///             expected_payload,
+///             serde_json::to_vec(&expected_payload).unwrap(),
 ///             Default::default(),
 ///         );
 ///
-/// assert_eq!(upcasted_event, expected_event);
+/// assert_eq!(upcasted_events, vec![expected_event]);
 /// ```
 pub struct SemanticVersionEventUpcaster {
     event_type: String,
@@ -184,20 +241,136 @@ impl EventUpcaster for SemanticVersionEventUpcaster {
         self.event_version.supersedes(&event_version)
     }
 
-    fn upcast(&self, event: SerializedEvent) -> SerializedEvent {
-        let upcasted_payload = (self.f)(event.payload);
-        SerializedEvent {
-            aggregate_id: event.aggregate_id,
-            sequence: event.sequence,
-            aggregate_type: event.aggregate_type,
-            event_type: event.event_type,
+    /// Only JSON payloads can be upcast, events in other formats are reported as an error.
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        if event.format != SerializationFormat::Json {
+            return Err(PersistenceError::DeserializationError(
+                format!(
+                    "{} v{} requires upcasting, which is only supported for json payloads",
+                    event.event_type, event.event_version
+                )
+                .into(),
+            ));
+        }
+        let payload: Value = serde_json::from_slice(&event.payload)?;
+        let upcasted_payload = (self.f)(payload);
+        Ok(vec![SerializedEvent {
             event_version: self.event_version.to_string(),
-            payload: upcasted_payload,
-            metadata: event.metadata,
+            payload: serde_json::to_vec(&upcasted_payload)?,
+            ..event
+        }])
+    }
+}
+
+/// Renames every version of an event type, leaving the payload unchanged.
+///
+/// ```
+/// use actuality::persist::RenameEventUpcaster;
+///
+/// let upcaster = RenameEventUpcaster::new("CustomerCreated", "CustomerRegistered");
+/// ```
+pub struct RenameEventUpcaster {
+    from_event_type: String,
+    to_event_type: String,
+}
+
+impl RenameEventUpcaster {
+    /// Creates a `RenameEventUpcaster`
+    pub fn new(from_event_type: &str, to_event_type: &str) -> Self {
+        Self {
+            from_event_type: from_event_type.to_string(),
+            to_event_type: to_event_type.to_string(),
+        }
+    }
+}
+
+impl EventUpcaster for RenameEventUpcaster {
+    fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+        event_type == self.from_event_type
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        Ok(vec![SerializedEvent {
+            event_type: self.to_event_type.clone(),
+            ..event
+        }])
+    }
+}
+
+/// Moves every version of an event type from one aggregate type to another.
+///
+/// Repositories select events by aggregate type so a moved event is not loaded with the
+/// aggregate it was moved to, this is intended for use when rewriting events into a new store.
+///
+/// ```
+/// use actuality::persist::MoveEventUpcaster;
+///
+/// let upcaster = MoveEventUpcaster::new("PaymentReceived", "Order", "Invoice");
+/// ```
+pub struct MoveEventUpcaster {
+    event_type: String,
+    from_aggregate_type: String,
+    to_aggregate_type: String,
+}
+
+impl MoveEventUpcaster {
+    /// Creates a `MoveEventUpcaster`
+    pub fn new(event_type: &str, from_aggregate_type: &str, to_aggregate_type: &str) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            from_aggregate_type: from_aggregate_type.to_string(),
+            to_aggregate_type: to_aggregate_type.to_string(),
         }
     }
 }
 
+impl EventUpcaster for MoveEventUpcaster {
+    fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+        event_type == self.event_type
+    }
+
+    /// Events of other aggregate types are returned unchanged.
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        if event.aggregate_type != self.from_aggregate_type {
+            return Ok(vec![event]);
+        }
+        Ok(vec![SerializedEvent {
+            aggregate_type: self.to_aggregate_type.clone(),
+            ..event
+        }])
+    }
+}
+
+/// Drops every version of an obsolete event type.
+///
+/// ```
+/// use actuality::persist::DropEventUpcaster;
+///
+/// let upcaster = DropEventUpcaster::new("NewsletterSent");
+/// ```
+pub struct DropEventUpcaster {
+    event_type: String,
+}
+
+impl DropEventUpcaster {
+    /// Creates a `DropEventUpcaster`
+    pub fn new(event_type: &str) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+        }
+    }
+}
+
+impl EventUpcaster for DropEventUpcaster {
+    fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+        event_type == self.event_type
+    }
+
+    fn upcast(&self, _event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        Ok(vec![])
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
@@ -206,9 +379,11 @@ mod test {
     use serde_json::json;
     use serde_json::Value;
 
+    use crate::persist::upcaster::upcast_event;
     use crate::persist::SemanticVersionEventUpcasterFunc;
     use crate::persist::{
-        EventUpcaster, SemanticVersion, SemanticVersionError, SemanticVersionEventUpcaster,
+        DropEventUpcaster, EventUpcaster, MoveEventUpcaster, PersistenceError, RenameEventUpcaster,
+        SemanticVersion, SemanticVersionError, SemanticVersionEventUpcaster, SerializationFormat,
     };
 
     fn semantic_version(major_version: u32, minor_version: u32, patch: u32) -> SemanticVersion {
@@ -282,12 +457,12 @@ mod test {
             "".to_string(),
             "".to_string(),
             "".to_string(),
-            payload,
+            serde_json::to_vec(&payload).unwrap(),
             Default::default(),
         );
-        println!("{}", event.payload.to_string());
-        let upcasted_event = upcaster.upcast(event);
-        println!("{}", upcasted_event.payload.to_string());
+        println!("{}", String::from_utf8_lossy(&event.payload));
+        let upcasted_events = upcaster.upcast(event).unwrap();
+        println!("{}", String::from_utf8_lossy(&upcasted_events[0].payload));
     }
     #[test]
     fn semantic_version_upcaster_upcast_for_documentation() {
@@ -315,10 +490,10 @@ mod test {
             "".to_string(),
             "".to_string(),
             "".to_string(),
-            payload,
+            serde_json::to_vec(&payload).unwrap(),
             Default::default(),
         );
-        let upcasted_event = upcaster.upcast(event);
+        let upcasted_events = upcaster.upcast(event).unwrap();
 
         let expected_payload: Value = serde_json::from_str(
             r#"{
@@ -334,11 +509,110 @@ mod test {
             "".to_string(),
             "".to_string(),
             "2.3.4".to_string(),
-            expected_payload,
+            serde_json::to_vec(&expected_payload).unwrap(),
+            Default::default(),
+        );
+
+        assert_eq!(upcasted_events, vec![expected_event]);
+    }
+
+    fn event(aggregate_type: &str, event_type: &str, payload: Value) -> SerializedEvent {
+        SerializedEvent::new(
+            "agg-1".to_string(),
+            1,
+            aggregate_type.to_string(),
+            event_type.to_string(),
+            "1.0".to_string(),
+            serde_json::to_vec(&payload).unwrap(),
             Default::default(),
+        )
+    }
+
+    struct SplitAddressChanged;
+
+    impl EventUpcaster for SplitAddressChanged {
+        fn can_upcast(&self, event_type: &str, _event_version: &str) -> bool {
+            event_type == "AddressChanged"
+        }
+
+        fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+            let payload: Value = serde_json::from_slice(&event.payload)?;
+            Ok(vec![
+                SerializedEvent {
+                    event_type: "StreetChanged".to_string(),
+                    payload: serde_json::to_vec(&payload["street"])?,
+                    ..event.clone()
+                },
+                SerializedEvent {
+                    event_type: "CityChanged".to_string(),
+                    payload: serde_json::to_vec(&payload["city"])?,
+                    ..event
+                },
+            ])
+        }
+    }
+
+    #[test]
+    fn upcaster_helpers() {
+        let renamed = RenameEventUpcaster::new("Created", "Registered")
+            .upcast(event("Customer", "Created", json!({})))
+            .unwrap();
+        assert_eq!(vec![event("Customer", "Registered", json!({}))], renamed);
+
+        let upcaster = MoveEventUpcaster::new("PaymentReceived", "Order", "Invoice");
+        assert!(upcaster.can_upcast("PaymentReceived", "0.1"));
+        let moved = upcaster
+            .upcast(event("Order", "PaymentReceived", json!({})))
+            .unwrap();
+        assert_eq!(vec![event("Invoice", "PaymentReceived", json!({}))], moved);
+        let unchanged = upcaster
+            .upcast(event("Refund", "PaymentReceived", json!({})))
+            .unwrap();
+        assert_eq!(
+            vec![event("Refund", "PaymentReceived", json!({}))],
+            unchanged
+        );
+
+        let upcaster = DropEventUpcaster::new("NewsletterSent");
+        assert!(!upcaster.can_upcast("Created", "1.0"));
+        assert!(upcaster
+            .upcast(event("Customer", "NewsletterSent", json!({})))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn upcaster_chain() {
+        let upcasters: Vec<Box<dyn EventUpcaster>> = vec![
+            Box::new(SplitAddressChanged),
+            Box::new(DropEventUpcaster::new("CityChanged")),
+            Box::new(RenameEventUpcaster::new("StreetChanged", "StreetRenamed")),
+        ];
+        let address_changed = event(
+            "Customer",
+            "AddressChanged",
+            json!({"street": "1 Main St", "city": "Springfield"}),
         );
+        let events = upcast_event(address_changed, &upcasters).unwrap();
+        assert_eq!(
+            vec![event("Customer", "StreetRenamed", json!("1 Main St"))],
+            events
+        );
+
+        let unrelated = event("Customer", "Created", json!({}));
+        let events = upcast_event(unrelated.clone(), &upcasters).unwrap();
+        assert_eq!(vec![unrelated], events);
+    }
 
-        assert_eq!(upcasted_event, expected_event);
+    #[test]
+    fn semantic_version_upcaster_requires_json() {
+        let upcaster = SemanticVersionEventUpcaster::new("EventX", "2.3.4", test_upcast());
+        let event = event("Customer", "EventX", json!({"id": 1}))
+            .with_format(SerializationFormat::Postcard);
+        assert!(matches!(
+            upcaster.upcast(event),
+            Err(PersistenceError::DeserializationError(_))
+        ));
     }
 
     fn test_upcast() -> Box<SemanticVersionEventUpcasterFunc> {
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::persist::{
+    EventUpcaster, PersistenceError, SemanticVersion, SemanticVersionEventUpcasterFunc,
+    SerializationFormat, SerializedEvent,
+};
+
+/// A single step in the upcasting of an event type, transforming the JSON payload of events
+/// from `from_version` (inclusive) up to `to_version` (exclusive) into the `to_version` form.
+pub struct VersionedUpcaster {
+    event_type: String,
+    from_version: String,
+    to_version: String,
+    f: Box<SemanticVersionEventUpcasterFunc>,
+}
+
+impl VersionedUpcaster {
+    /// Creates a `VersionedUpcaster`, the versions are validated when it is registered.
+    pub fn new(
+        event_type: &str,
+        from_version: &str,
+        to_version: &str,
+        f: Box<SemanticVersionEventUpcasterFunc>,
+    ) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            from_version: from_version.to_string(),
+            to_version: to_version.to_string(),
+            f,
+        }
+    }
+}
+
+/// Identifies an upcasting step applied to an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedUpcaster {
+    /// The type of event upcast.
+    pub event_type: String,
+    /// The version of the event before the step.
+    pub from_version: String,
+    /// The version of the event after the step.
+    pub to_version: String,
+}
+
+impl Display for AppliedUpcaster {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.event_type, self.from_version, self.to_version
+        )
+    }
+}
+
+/// The upcasters registered with an `UpcasterRegistry` do not form a valid chain.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UpcasterRegistryError {
+    /// A version is not a valid `SemanticVersion`.
+    InvalidVersion {
+        /// The event type of the upcaster.
+        event_type: String,
+        /// The invalid version.
+        version: String,
+    },
+    /// An upcaster does not move to a later version.
+    EmptyRange {
+        /// The event type of the upcaster.
+        event_type: String,
+        /// The version upcast from.
+        from_version: String,
+        /// The version upcast to.
+        to_version: String,
+    },
+    /// Two upcasters apply to the same versions.
+    Overlap {
+        /// The event type of the upcasters.
+        event_type: String,
+        /// The version at which the upcasters overlap.
+        version: String,
+    },
+    /// No upcaster applies between two versions.
+    Gap {
+        /// The event type of the upcasters.
+        event_type: String,
+        /// The version at which the preceding upcaster ends.
+        from_version: String,
+        /// The version at which the following upcaster starts.
+        to_version: String,
+    },
+}
+
+impl Display for UpcasterRegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpcasterRegistryError::InvalidVersion {
+                event_type,
+                version,
+            } => write!(f, "{}: invalid version '{}'", event_type, version),
+            UpcasterRegistryError::EmptyRange {
+                event_type,
+                from_version,
+                to_version,
+            } => write!(
+                f,
+                "{}: upcaster from {} to {} does not move to a later version",
+                event_type, from_version, to_version
+            ),
+            UpcasterRegistryError::Overlap {
+                event_type,
+                version,
+            } => write!(
+                f,
+                "{}: more than one upcaster applies to {}",
+                event_type, version
+            ),
+            UpcasterRegistryError::Gap {
+                event_type,
+                from_version,
+                to_version,
+            } => write!(
+                f,
+                "{}: no upcaster from {} to {}",
+                event_type, from_version, to_version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UpcasterRegistryError {}
+
+struct Step {
+    from_version: SemanticVersion,
+    to_version: SemanticVersion,
+    f: Box<SemanticVersionEventUpcasterFunc>,
+}
+
+/// Upcasts events through a validated chain of `VersionedUpcaster`s for each event type.
+///
+/// Upcasters are ordered by version regardless of the order they are registered in, and each
+/// event type's chain must be contiguous: registration fails if two upcasters apply to the same
+/// version or if a version between the first and last upcaster is not covered. The event
+/// version is set to the target version after each step.
+///
+/// As an `EventUpcaster` the registry may be used wherever upcasters are configured.
+///
+/// ```
+/// use actuality::persist::{UpcasterRegistry, VersionedUpcaster};
+/// use serde_json::{json, Value};
+///
+/// let registry = UpcasterRegistry::new(vec![
+///     VersionedUpcaster::new("AddressUpdated", "2.0", "3.0", Box::new(|mut payload: Value| {
+///         payload["country"] = json!("USA");
+///         payload
+///     })),
+///     VersionedUpcaster::new("AddressUpdated", "1.0", "2.0", Box::new(|mut payload: Value| {
+///         payload["zip"] = payload["zip"].to_string().into();
+///         payload
+///     })),
+/// ])
+/// .unwrap();
+/// ```
+pub struct UpcasterRegistry {
+    chains: HashMap<String, Vec<Step>>,
+}
+
+impl UpcasterRegistry {
+    /// Creates a registry from the upcasters, validating the chain formed for each event type.
+    pub fn new(upcasters: Vec<VersionedUpcaster>) -> Result<Self, UpcasterRegistryError> {
+        let mut chains: HashMap<String, Vec<Step>> = HashMap::new();
+        for upcaster in upcasters {
+            let from_version = parse_version(&upcaster.event_type, &upcaster.from_version)?;
+            let to_version = parse_version(&upcaster.event_type, &upcaster.to_version)?;
+            if !to_version.supersedes(&from_version) {
+                return Err(UpcasterRegistryError::EmptyRange {
+                    event_type: upcaster.event_type,
+                    from_version: from_version.to_string(),
+                    to_version: to_version.to_string(),
+                });
+            }
+            chains.entry(upcaster.event_type).or_default().push(Step {
+                from_version,
+                to_version,
+                f: upcaster.f,
+            });
+        }
+        for (event_type, chain) in chains.iter_mut() {
+            // uninteresting unwrap: semantic versions are totally ordered
+            chain.sort_by(|a, b| a.from_version.partial_cmp(&b.from_version).unwrap());
+            for pair in chain.windows(2) {
+                let (previous, next) = (&pair[0], &pair[1]);
+                if previous.to_version.supersedes(&next.from_version) {
+                    return Err(UpcasterRegistryError::Overlap {
+                        event_type: event_type.clone(),
+                        version: next.from_version.to_string(),
+                    });
+                }
+                if next.from_version.supersedes(&previous.to_version) {
+                    return Err(UpcasterRegistryError::Gap {
+                        event_type: event_type.clone(),
+                        from_version: previous.to_version.to_string(),
+                        to_version: next.from_version.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(Self { chains })
+    }
+
+    /// Upcasts the event to the latest registered version of its type, returning the steps
+    /// applied.
+    ///
+    /// Events older than the earliest registered version of their type cannot be upcast and are
+    /// reported as an error, as are events with a payload other than JSON.
+    pub fn upcast_with_report(
+        &self,
+        event: SerializedEvent,
+    ) -> Result<(SerializedEvent, Vec<AppliedUpcaster>), PersistenceError> {
+        let chain = match self.chains.get(&event.event_type) {
+            Some(chain) => chain,
+            None => return Ok((event, Vec::new())),
+        };
+        let mut version = match SemanticVersion::from_str(&event.event_version) {
+            Ok(version) => version,
+            Err(_) => return Ok((event, Vec::new())),
+        };
+        let first = &chain[0];
+        if first.from_version.supersedes(&version) {
+            return Err(PersistenceError::DeserializationError(
+                format!(
+                    "{} v{} predates the earliest upcaster, from v{}",
+                    event.event_type, event.event_version, first.from_version
+                )
+                .into(),
+            ));
+        }
+        let mut applied = Vec::new();
+        let mut payload: Option<Value> = None;
+        for step in chain {
+            if !step.to_version.supersedes(&version) {
+                continue;
+            }
+            let current = match payload.take() {
+                Some(current) => current,
+                None => parse_payload(&event)?,
+            };
+            payload = Some((step.f)(current));
+            applied.push(AppliedUpcaster {
+                event_type: event.event_type.clone(),
+                from_version: version.to_string(),
+                to_version: step.to_version.to_string(),
+            });
+            version = step.to_version.clone();
+        }
+        let payload = match payload {
+            Some(payload) => payload,
+            None => return Ok((event, applied)),
+        };
+        let event = SerializedEvent {
+            event_version: version.to_string(),
+            payload: serde_json::to_vec(&payload)?,
+            ..event
+        };
+        Ok((event, applied))
+    }
+}
+
+impl EventUpcaster for UpcasterRegistry {
+    fn can_upcast(&self, event_type: &str, event_version: &str) -> bool {
+        let chain = match self.chains.get(event_type) {
+            Some(chain) => chain,
+            None => return false,
+        };
+        match SemanticVersion::from_str(event_version) {
+            // uninteresting unwrap: chains are never empty
+            Ok(version) => chain.last().unwrap().to_version.supersedes(&version),
+            Err(_) => false,
+        }
+    }
+
+    fn upcast(&self, event: SerializedEvent) -> Result<Vec<SerializedEvent>, PersistenceError> {
+        let (event, _) = self.upcast_with_report(event)?;
+        Ok(vec![event])
+    }
+}
+
+fn parse_version(
+    event_type: &str,
+    version: &str,
+) -> Result<SemanticVersion, UpcasterRegistryError> {
+    SemanticVersion::from_str(version).map_err(|_| UpcasterRegistryError::InvalidVersion {
+        event_type: event_type.to_string(),
+        version: version.to_string(),
+    })
+}
+
+fn parse_payload(event: &SerializedEvent) -> Result<Value, PersistenceError> {
+    if event.format != SerializationFormat::Json {
+        return Err(PersistenceError::DeserializationError(
+            format!(
+                "{} v{} requires upcasting, which is only supported for json payloads",
+                event.event_type, event.event_version
+            )
+            .into(),
+        ));
+    }
+    Ok(serde_json::from_slice(&event.payload)?)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::persist::{
+        AppliedUpcaster, EventUpcaster, PersistenceError, SerializedEvent, UpcasterRegistry,
+        UpcasterRegistryError, VersionedUpcaster,
+    };
+
+    fn step(from_version: &str, to_version: &str) -> VersionedUpcaster {
+        let field = format!("v{}", to_version);
+        VersionedUpcaster::new(
+            "AddressUpdated",
+            from_version,
+            to_version,
+            Box::new(move |mut payload: Value| {
+                payload[&field] = json!(true);
+                payload
+            }),
+        )
+    }
+
+    fn event(event_version: &str) -> SerializedEvent {
+        SerializedEvent::new(
+            "agg-1".to_string(),
+            1,
+            "Customer".to_string(),
+            "AddressUpdated".to_string(),
+            event_version.to_string(),
+            serde_json::to_vec(&json!({})).unwrap(),
+            Default::default(),
+        )
+    }
+
+    fn applied(from_version: &str, to_version: &str) -> AppliedUpcaster {
+        AppliedUpcaster {
+            event_type: "AddressUpdated".to_string(),
+            from_version: from_version.to_string(),
+            to_version: to_version.to_string(),
+        }
+    }
+
+    #[test]
+    fn upcast_in_version_order() {
+        let registry = UpcasterRegistry::new(vec![step("2.0", "3.0"), step("1.0", "2.0")]).unwrap();
+        assert!(registry.can_upcast("AddressUpdated", "1.5"));
+        assert!(!registry.can_upcast("AddressUpdated", "3.0"));
+        assert!(!registry.can_upcast("CustomerCreated", "1.0"));
+
+        let (upcasted, report) = registry.upcast_with_report(event("1.5")).unwrap();
+        assert_eq!("3.0.0", upcasted.event_version);
+        let payload: Value = serde_json::from_slice(&upcasted.payload).unwrap();
+        assert_eq!(json!({"v2.0": true, "v3.0": true}), payload);
+        assert_eq!(
+            vec![applied("1.5.0", "2.0.0"), applied("2.0.0", "3.0.0")],
+            report
+        );
+
+        let (upcasted, report) = registry.upcast_with_report(event("2.0")).unwrap();
+        assert_eq!("3.0.0", upcasted.event_version);
+        assert_eq!(vec![applied("2.0.0", "3.0.0")], report);
+
+        let current = event("3.0");
+        let (upcasted, report) = registry.upcast_with_report(current.clone()).unwrap();
+        assert_eq!(current, upcasted);
+        assert!(report.is_empty());
+
+        let result = registry.upcast_with_report(event("0.9"));
+        assert!(matches!(
+            result,
+            Err(PersistenceError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_chains() {
+        let result = UpcasterRegistry::new(vec![step("1.0", "2.0"), step("1.5", "3.0")]);
+        assert_eq!(
+            Some(UpcasterRegistryError::Overlap {
+                event_type: "AddressUpdated".to_string(),
+                version: "1.5.0".to_string(),
+            }),
+            result.err()
+        );
+
+        let result = UpcasterRegistry::new(vec![step("1.0", "2.0"), step("2.1", "3.0")]);
+        assert_eq!(
+            Some(UpcasterRegistryError::Gap {
+                event_type: "AddressUpdated".to_string(),
+                from_version: "2.0.0".to_string(),
+                to_version: "2.1.0".to_string(),
+            }),
+            result.err()
+        );
+
+        let result = UpcasterRegistry::new(vec![step("2.0", "2.0")]);
+        assert!(matches!(
+            result,
+            Err(UpcasterRegistryError::EmptyRange { .. })
+        ));
+
+        let result = UpcasterRegistry::new(vec![step("1.0", "two")]);
+        assert!(matches!(
+            result,
+            Err(UpcasterRegistryError::InvalidVersion { .. })
+        ));
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::persist::generic_query::update_view_with_retry;
+use crate::persist::{PersistenceError, UpdateRetryPolicy, ViewRepository};
+use crate::{Aggregate, EventEnvelope, View};
+
+const DEFAULT_CAPACITY: usize = 1000;
+const DEFAULT_MAX_PENDING: usize = 100;
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configures a write-behind cache of the views updated by a `GenericQuery`.
+///
+/// Views are held in the cache as events are dispatched to them, the updates of any number of
+/// dispatches being written to the view repository with a single `update_view`. Updated views
+/// are written once `max_pending` of them await writing, once the flush interval has passed
+/// since they were last written, as they are evicted from the cache or on
+/// `GenericQuery::flush`. Updates not yet written are lost should the process stop, they may be
+/// recovered with a `QueryReplay` of the events committed meanwhile.
+///
+/// ```
+/// use std::time::Duration;
+/// use actuality::persist::ViewCacheConfig;
+///
+/// let config = ViewCacheConfig::default()
+///     .with_capacity(10_000)
+///     .with_max_pending(500)
+///     .with_flush_interval(Duration::from_millis(250));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ViewCacheConfig {
+    capacity: usize,
+    max_pending: usize,
+    flush_interval: Option<Duration>,
+}
+
+impl Default for ViewCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            max_pending: DEFAULT_MAX_PENDING,
+            flush_interval: Some(DEFAULT_FLUSH_INTERVAL),
+        }
+    }
+}
+
+impl ViewCacheConfig {
+    /// Configures the number of views held in the cache, defaults to 1000. The least recently
+    /// updated view is evicted once the cache is full, being written first if updated.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ..self
+        }
+    }
+
+    /// Configures the number of updated views awaiting writing at which all are written,
+    /// defaults to 100.
+    pub fn with_max_pending(self, max_pending: usize) -> Self {
+        Self {
+            max_pending: max_pending.max(1),
+            ..self
+        }
+    }
+
+    /// Configures the longest time updated views await writing, defaults to one second.
+    ///
+    /// The interval is checked as events are dispatched, a task started with
+    /// `GenericQuery::spawn_cache_flush` writing views updated before the query falls idle.
+    pub fn with_flush_interval(self, flush_interval: Duration) -> Self {
+        Self {
+            flush_interval: Some(flush_interval),
+            ..self
+        }
+    }
+
+    /// Writes updated views only when full or on `GenericQuery::flush`.
+    pub fn without_flush_interval(self) -> Self {
+        Self {
+            flush_interval: None,
+            ..self
+        }
+    }
+}
+
+struct CachedView<V, A: Aggregate> {
+    view: V,
+    version: i64,
+    /// The events applied since the view was last written, reapplied should writing the view
+    /// be retried.
+    events: Vec<EventEnvelope<A>>,
+}
+
+/// A cached view, locked while it is loaded, updated or written so that the updates of each
+/// view are made in turn without holding the lock on the cache. Empty until the view is
+/// loaded, or once it must be reloaded.
+type ViewSlot<V, A> = Arc<tokio::sync::Mutex<Option<CachedView<V, A>>>>;
+
+struct CacheEntry<V, A: Aggregate> {
+    slot: ViewSlot<V, A>,
+    used: u64,
+}
+
+/// Views to be written to a view repository, taken from the cache so that they are written
+/// after its lock is released.
+struct ViewWrites<R, V, A: Aggregate> {
+    repository: Option<Arc<R>>,
+    views: Vec<(String, ViewSlot<V, A>)>,
+}
+
+/// The views held by a `GenericQuery` configured with a `ViewCacheConfig`.
+pub(crate) struct ViewCache<R, V, A: Aggregate> {
+    config: ViewCacheConfig,
+    state: Mutex<CacheState<R, V, A>>,
+}
+
+/// The cached views, along with the view repository they were loaded from.
+struct CacheState<R, V, A: Aggregate> {
+    repository: Option<Arc<R>>,
+    views: HashMap<String, CacheEntry<V, A>>,
+    /// The ids of the cached views by when they were last used, least recent first.
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    /// The ids of the cached views updated since they were last written.
+    pending: HashSet<String>,
+    last_flush: Instant,
+}
+
+impl<R, V, A> ViewCache<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    pub(crate) fn new(config: ViewCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(CacheState {
+                repository: None,
+                views: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+                pending: HashSet::new(),
+                last_flush: Instant::now(),
+            }),
+        }
+    }
+
+    pub(crate) fn flush_interval(&self) -> Option<Duration> {
+        self.config.flush_interval
+    }
+
+    /// Returns a copy of the cached view, if loaded from this repository.
+    pub(crate) async fn load(
+        &self,
+        repository: &Arc<R>,
+        view_id: &str,
+    ) -> Option<Result<V, PersistenceError>> {
+        let slot = {
+            let state = self.state.lock().unwrap();
+            if !state.holds(repository) {
+                return None;
+            }
+            state.views.get(view_id)?.slot.clone()
+        };
+        let cached = slot.lock().await;
+        cached.as_ref().map(|cached| copy_view(&cached.view))
+    }
+
+    /// Applies the events to the cached view, loading it if not cached, then writes any views
+    /// due to be written. Returns the errors loading or writing views.
+    pub(crate) async fn apply(
+        &self,
+        repository: &Arc<R>,
+        retry_policy: &UpdateRetryPolicy,
+        view_id: &str,
+        events: &[EventEnvelope<A>],
+    ) -> Vec<PersistenceError> {
+        let mut errors = Vec::new();
+        let (slot, replaced) = {
+            let mut state = self.state.lock().unwrap();
+            // the view repository was replaced by a rebuild, or events recorded by the rebuild
+            // are still being dispatched to the replaced one
+            let replaced = (!state.holds(repository)).then(|| state.replace(repository));
+            (state.use_slot(view_id), replaced)
+        };
+        if let Some(replaced) = replaced {
+            errors.extend(replaced.write(retry_policy).await);
+        }
+
+        let mut cached = slot.lock().await;
+        if cached.is_none() {
+            match repository.load_with_context(view_id).await {
+                Ok(loaded) => {
+                    let (view, version) = match loaded {
+                        Some((view, context)) => (view, context.version),
+                        None => (V::default(), 0),
+                    };
+                    *cached = Some(CachedView {
+                        view,
+                        version,
+                        events: Vec::new(),
+                    });
+                }
+                Err(error) => {
+                    errors.push(error);
+                    return errors;
+                }
+            }
+        }
+        let updated = cached.as_mut().expect("the view is loaded");
+        for event in events {
+            updated.view.update(event);
+        }
+        updated.events.extend_from_slice(events);
+
+        let (orphaned, evicted, flushed) = {
+            let mut state = self.state.lock().unwrap();
+            let orphaned = !state.caches(view_id, &slot);
+            if !orphaned {
+                state.pending.insert(view_id.to_string());
+            }
+            let evicted = state.evict(self.config.capacity);
+            let interval_passed = self
+                .config
+                .flush_interval
+                .is_some_and(|interval| state.last_flush.elapsed() >= interval);
+            let flushed = (state.pending.len() >= self.config.max_pending || interval_passed)
+                .then(|| state.take_pending());
+            (orphaned, evicted, flushed)
+        };
+        if orphaned {
+            // evicted, or the view repository replaced, while the view was updated
+            let result = write_view(repository.as_ref(), retry_policy, view_id, &mut cached).await;
+            if let Err(error) = result {
+                errors.push(error);
+            }
+        }
+        drop(cached);
+        errors.extend(evicted.write(retry_policy).await);
+        if let Some(flushed) = flushed {
+            errors.extend(flushed.write(retry_policy).await);
+        }
+        errors
+    }
+
+    /// Writes every updated view, returning the errors writing them.
+    pub(crate) async fn flush(&self, retry_policy: &UpdateRetryPolicy) -> Vec<PersistenceError> {
+        let flushed = self.state.lock().unwrap().take_pending();
+        flushed.write(retry_policy).await
+    }
+}
+
+impl<R, V, A> CacheState<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    fn holds(&self, repository: &Arc<R>) -> bool {
+        self.repository
+            .as_ref()
+            .is_some_and(|cached| Arc::ptr_eq(cached, repository))
+    }
+
+    /// Whether the slot is that of the cached view.
+    fn caches(&self, view_id: &str, slot: &ViewSlot<V, A>) -> bool {
+        self.views
+            .get(view_id)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.slot, slot))
+    }
+
+    /// Empties the cache to hold the views of another repository, returning the updated views
+    /// to be written to the repository replaced.
+    fn replace(&mut self, repository: &Arc<R>) -> ViewWrites<R, V, A> {
+        let replaced = self.take_pending();
+        self.views.clear();
+        self.recency.clear();
+        self.repository = Some(repository.clone());
+        replaced
+    }
+
+    /// Returns the slot of the view, adding an empty slot if not cached, and marks it as the
+    /// most recently used.
+    fn use_slot(&mut self, view_id: &str) -> ViewSlot<V, A> {
+        self.clock += 1;
+        let used = self.clock;
+        let entry = self
+            .views
+            .entry(view_id.to_string())
+            .or_insert_with(|| CacheEntry {
+                slot: Default::default(),
+                used,
+            });
+        let previous = std::mem::replace(&mut entry.used, used);
+        let slot = entry.slot.clone();
+        self.recency.remove(&previous);
+        self.recency.insert(used, view_id.to_string());
+        slot
+    }
+
+    /// Evicts the least recently used views beyond the capacity, returning those updated to be
+    /// written.
+    fn evict(&mut self, capacity: usize) -> ViewWrites<R, V, A> {
+        let mut evicted = Vec::new();
+        while self.views.len() > capacity {
+            let (_, view_id) = self.recency.pop_first().expect("cached views are ordered");
+            let entry = self
+                .views
+                .remove(&view_id)
+                .expect("ordered views are cached");
+            if self.pending.remove(&view_id) {
+                evicted.push((view_id, entry.slot));
+            }
+        }
+        ViewWrites {
+            repository: self.repository.clone(),
+            views: evicted,
+        }
+    }
+
+    /// Takes the updated views to be written.
+    fn take_pending(&mut self) -> ViewWrites<R, V, A> {
+        self.last_flush = Instant::now();
+        let views = self
+            .pending
+            .drain()
+            .filter_map(|view_id| {
+                let slot = self.views.get(&view_id)?.slot.clone();
+                Some((view_id, slot))
+            })
+            .collect();
+        ViewWrites {
+            repository: self.repository.clone(),
+            views,
+        }
+    }
+}
+
+impl<R, V, A> ViewWrites<R, V, A>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Writes the views, returning the errors writing them.
+    async fn write(self, retry_policy: &UpdateRetryPolicy) -> Vec<PersistenceError> {
+        let mut errors = Vec::new();
+        let repository = match self.repository {
+            Some(repository) => repository,
+            None => return errors,
+        };
+        for (view_id, slot) in self.views {
+            let mut cached = slot.lock().await;
+            let result = write_view(repository.as_ref(), retry_policy, &view_id, &mut cached).await;
+            if let Err(error) = result {
+                errors.push(error);
+            }
+        }
+        errors
+    }
+}
+
+/// Writes the cached view if updated. A view reloaded to retry its update, or that could not
+/// be written, is emptied so that it is reloaded when next updated.
+async fn write_view<R, V, A>(
+    repository: &R,
+    retry_policy: &UpdateRetryPolicy,
+    view_id: &str,
+    cached: &mut Option<CachedView<V, A>>,
+) -> Result<(), PersistenceError>
+where
+    R: ViewRepository<V, A>,
+    V: View<A>,
+    A: Aggregate,
+{
+    let updated = match cached {
+        Some(updated) if !updated.events.is_empty() => updated,
+        _ => return Ok(()),
+    };
+    let result = match copy_view(&updated.view) {
+        Ok(view) => {
+            let written = Some((view, updated.version));
+            update_view_with_retry(
+                repository,
+                retry_policy,
+                view_id,
+                written,
+                &updated.events,
+                V::update,
+            )
+            .await
+        }
+        Err(error) => Err(error),
+    };
+    match result {
+        Ok(written) if !written.reloaded => {
+            updated.version = written.version;
+            updated.events.clear();
+            Ok(())
+        }
+        result => {
+            *cached = None;
+            result.map(|_| ())
+        }
+    }
+}
+
+/// Copies a view through its serialized form, as a `View` need not implement `Clone`.
+fn copy_view<V, A>(view: &V) -> Result<V, PersistenceError>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    Ok(serde_json::from_value(serde_json::to_value(view)?)?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use tokio::sync::Notify;
+
+    use crate::doc::setup::{MyAggregate, MyEvents};
+    use crate::persist::{
+        GenericQuery, PersistenceError, ViewCacheConfig, ViewContext, ViewRepository,
+    };
+    use crate::{EventEnvelope, Query, View};
+
+    #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+    struct CountView {
+        sequences: Vec<usize>,
+    }
+
+    impl View<MyAggregate> for CountView {
+        fn update(&mut self, event: &EventEnvelope<MyAggregate>) {
+            self.sequences.push(event.sequence);
+        }
+    }
+
+    /// A view repository counting its writes, whose loading of `view-A` may be held back.
+    #[derive(Default)]
+    struct GatedViewRepository {
+        views: Mutex<HashMap<String, (CountView, i64)>>,
+        writes: AtomicUsize,
+        /// Holds back loading `view-A` until notified, where present.
+        gate: Option<Notify>,
+    }
+
+    #[async_trait]
+    impl ViewRepository<CountView, MyAggregate> for GatedViewRepository {
+        async fn load(&self, view_id: &str) -> Result<Option<CountView>, PersistenceError> {
+            Ok(self.load_with_context(view_id).await?.map(|(view, _)| view))
+        }
+
+        async fn load_with_context(
+            &self,
+            view_id: &str,
+        ) -> Result<Option<(CountView, ViewContext)>, PersistenceError> {
+            if let Some(gate) = self.gate.as_ref().filter(|_| view_id == "view-A") {
+                gate.notified().await;
+            }
+            let views = self.views.lock().unwrap();
+            Ok(views.get(view_id).map(|(view, version)| {
+                (
+                    view.clone(),
+                    ViewContext::new(view_id.to_string(), *version),
+                )
+            }))
+        }
+
+        async fn update_view(
+            &self,
+            view: CountView,
+            context: ViewContext,
+        ) -> Result<(), PersistenceError> {
+            let mut views = self.views.lock().unwrap();
+            let stored = views.get(&context.view_instance_id).map_or(0, |(_, v)| *v);
+            if stored != context.version {
+                return Err(PersistenceError::OptimisticLockError);
+            }
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            views.insert(context.view_instance_id, (view, context.version + 1));
+            Ok(())
+        }
+    }
+
+    type CountQuery = GenericQuery<GatedViewRepository, CountView, MyAggregate>;
+
+    fn event(view_id: &str, sequence: usize) -> EventEnvelope<MyAggregate> {
+        EventEnvelope {
+            aggregate_id: view_id.to_string(),
+            event_type: "SomethingWasDone".to_string(),
+            sequence,
+            system_id: "".to_string(),
+            payload: MyEvents::SomethingWasDone,
+            metadata: Default::default(),
+        }
+    }
+
+    fn query(config: ViewCacheConfig) -> (CountQuery, Arc<GatedViewRepository>) {
+        let repo = Arc::new(GatedViewRepository::default());
+        let mut query = CountQuery::new(repo.clone()).with_view_cache(config);
+        query.use_error_handler(Box::new(|e| panic!("{}", e)));
+        (query, repo)
+    }
+
+    fn stored(repo: &GatedViewRepository, view_id: &str) -> Option<(Vec<usize>, i64)> {
+        let views = repo.views.lock().unwrap();
+        views
+            .get(view_id)
+            .map(|(view, version)| (view.sequences.clone(), *version))
+    }
+
+    #[tokio::test]
+    async fn coalesce_updates() {
+        let (query, repo) = query(ViewCacheConfig::default().without_flush_interval());
+        query.dispatch("view-A", &[event("view-A", 1)]).await;
+        query
+            .dispatch("view-A", &[event("view-A", 2), event("view-A", 3)])
+            .await;
+        assert_eq!(0, repo.writes.load(Ordering::Relaxed));
+        let view = query.load("view-A").await.unwrap();
+        assert_eq!(vec![1, 2, 3], view.sequences);
+
+        query.flush().await;
+        assert_eq!(1, repo.writes.load(Ordering::Relaxed));
+        assert_eq!(Some((vec![1, 2, 3], 1)), stored(&repo, "view-A"));
+
+        // the cached version follows the written view
+        query.dispatch("view-A", &[event("view-A", 4)]).await;
+        query.flush().await;
+        assert_eq!(Some((vec![1, 2, 3, 4], 2)), stored(&repo, "view-A"));
+        query.flush().await;
+        assert_eq!(2, repo.writes.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn flush_on_max_pending() {
+        let config = ViewCacheConfig::default()
+            .with_max_pending(2)
+            .without_flush_interval();
+        let (query, repo) = query(config);
+        query.dispatch("view-A", &[event("view-A", 1)]).await;
+        query.dispatch("view-A", &[event("view-A", 2)]).await;
+        assert_eq!(0, repo.writes.load(Ordering::Relaxed));
+        query.dispatch("view-B", &[event("view-B", 1)]).await;
+        assert_eq!(2, repo.writes.load(Ordering::Relaxed));
+        assert_eq!(Some((vec![1, 2], 1)), stored(&repo, "view-A"));
+        assert_eq!(Some((vec![1], 1)), stored(&repo, "view-B"));
+    }
+
+    #[tokio::test]
+    async fn evict_least_recently_used() {
+        let config = ViewCacheConfig::default()
+            .with_capacity(2)
+            .without_flush_interval();
+        let (query, repo) = query(config);
+        query.dispatch("view-A", &[event("view-A", 1)]).await;
+        query.dispatch("view-B", &[event("view-B", 1)]).await;
+        query.dispatch("view-A", &[event("view-A", 2)]).await;
+        query.dispatch("view-C", &[event("view-C", 1)]).await;
+        assert_eq!(1, repo.writes.load(Ordering::Relaxed));
+        assert_eq!(Some((vec![1], 1)), stored(&repo, "view-B"));
+
+        // an evicted view is reloaded when next updated
+        query.dispatch("view-B", &[event("view-B", 2)]).await;
+        query.flush().await;
+        assert_eq!(Some((vec![1, 2], 2)), stored(&repo, "view-B"));
+        assert_eq!(Some((vec![1, 2], 1)), stored(&repo, "view-A"));
+    }
+
+    #[tokio::test]
+    async fn update_while_loading() {
+        let repo = Arc::new(GatedViewRepository {
+            gate: Some(Notify::new()),
+            ..Default::default()
+        });
+        let query = Arc::new(
+            CountQuery::new(repo.clone())
+                .with_view_cache(ViewCacheConfig::default().without_flush_interval()),
+        );
+        let loading = tokio::spawn({
+            let query = query.clone();
+            async move { query.dispatch("view-A", &[event("view-A", 1)]).await }
+        });
+        tokio::task::yield_now().await;
+
+        // other views are updated and flushed while view-A is loaded
+        let update = async {
+            query.dispatch("view-B", &[event("view-B", 1)]).await;
+            query.flush().await;
+        };
+        tokio::time::timeout(Duration::from_secs(5), update)
+            .await
+            .unwrap();
+        assert_eq!(Some((vec![1], 1)), stored(&repo, "view-B"));
+
+        repo.gate.as_ref().unwrap().notify_one();
+        loading.await.unwrap();
+        query.flush().await;
+        assert_eq!(Some((vec![1], 1)), stored(&repo, "view-A"));
+    }
+}
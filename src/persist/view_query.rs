@@ -0,0 +1,373 @@
+use std::cmp::Ordering;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::persist::{PersistenceError, ViewRepository};
+use crate::{Aggregate, View};
+
+/// An extension of a `ViewRepository` able to list and search its views, e.g., to serve the
+/// collection endpoints of an API.
+#[async_trait]
+pub trait QueryableViewRepository<V, A>: ViewRepository<V, A>
+where
+    V: View<A>,
+    A: Aggregate,
+{
+    /// Returns a page of the views selected by the query, along with the cursor of the next
+    /// page if there are further views.
+    async fn query_views(&self, query: &ViewQuery) -> Result<ViewPage<V>, PersistenceError>;
+}
+
+/// Selects, sorts and pages the views of a `QueryableViewRepository`.
+///
+/// Fields are named by their path within the serialized view, separated by `.`, a missing field
+/// having a `null` value. Every condition must hold for a view to be selected, views being
+/// sorted by each sort field in turn and then by their view id.
+///
+/// ```
+/// use actuality::persist::{FieldCondition, SortOrder, ViewQuery};
+///
+/// let query = ViewQuery::default()
+///     .with_condition("status", FieldCondition::Equal("open".into()))
+///     .with_condition("customer.name", FieldCondition::Contains("Doe".into()))
+///     .with_sort("total", SortOrder::Descending)
+///     .with_limit(20);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewQuery {
+    pub(crate) conditions: Vec<(String, FieldCondition)>,
+    pub(crate) sort: Vec<(String, SortOrder)>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) cursor: Option<String>,
+}
+
+/// A condition on a field of the views selected by a `ViewQuery`.
+///
+/// Fields are only ordered against values of the same type, numbers, strings or booleans, such
+/// that a missing field never satisfies an ordering condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldCondition {
+    /// The field is equal to the value.
+    Equal(Value),
+    /// The field is not equal to the value.
+    NotEqual(Value),
+    /// The field is greater than the value.
+    GreaterThan(Value),
+    /// The field is greater than or equal to the value.
+    GreaterOrEqual(Value),
+    /// The field is less than the value.
+    LessThan(Value),
+    /// The field is less than or equal to the value.
+    LessOrEqual(Value),
+    /// The field is a string containing the string value, or an array containing the value.
+    Contains(Value),
+}
+
+/// The order in which views are sorted by a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Smallest value first, `null` values sorting first.
+    Ascending,
+    /// Largest value first, `null` values sorting last.
+    Descending,
+}
+
+/// A page of the views selected by a `ViewQuery`, with their view ids.
+#[derive(Debug)]
+pub struct ViewPage<V> {
+    /// The view id and view of each view in the page.
+    pub views: Vec<(String, V)>,
+    /// The cursor from which to request the next page, none on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl ViewQuery {
+    /// Selects only views with a field satisfying the condition.
+    pub fn with_condition(mut self, field: impl Into<String>, condition: FieldCondition) -> Self {
+        self.conditions.push((field.into(), condition));
+        self
+    }
+
+    /// Sorts the views by the field, after any sort fields already configured.
+    pub fn with_sort(mut self, field: impl Into<String>, order: SortOrder) -> Self {
+        self.sort.push((field.into(), order));
+        self
+    }
+
+    /// Returns at most this many views in a page, by default all selected views are returned.
+    pub fn with_limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Returns the page following the one that returned this cursor, the query otherwise being
+    /// unchanged.
+    pub fn with_cursor(self, cursor: impl Into<String>) -> Self {
+        Self {
+            cursor: Some(cursor.into()),
+            ..self
+        }
+    }
+
+    /// Selects, sorts and pages serialized views, for a repository without a query language of
+    /// its own.
+    pub(crate) fn page<V, I>(&self, views: I) -> Result<ViewPage<V>, PersistenceError>
+    where
+        V: DeserializeOwned,
+        I: IntoIterator<Item = (String, Value)>,
+    {
+        let mut selected: Vec<(Vec<Value>, String, Value)> = views
+            .into_iter()
+            .filter(|(_, view)| {
+                self.conditions
+                    .iter()
+                    .all(|(field, condition)| condition.matches(field_value(view, field)))
+            })
+            .map(|(view_id, view)| {
+                let key = self
+                    .sort
+                    .iter()
+                    .map(|(field, _)| field_value(&view, field).clone())
+                    .collect();
+                (key, view_id, view)
+            })
+            .collect();
+        selected.sort_by(|(a_key, a_id, _), (b_key, b_id, _)| {
+            self.compare_positions(a_key, a_id, b_key, b_id)
+        });
+        let start = match &self.cursor {
+            None => 0,
+            Some(cursor) => {
+                let (cursor_key, cursor_id) = self.decode_cursor(cursor)?;
+                selected.partition_point(|(key, view_id, _)| {
+                    self.compare_positions(key, view_id, &cursor_key, &cursor_id)
+                        != Ordering::Greater
+                })
+            }
+        };
+        let end = match self.limit {
+            Some(limit) => selected.len().min(start.saturating_add(limit)),
+            None => selected.len(),
+        };
+        let next_cursor = if end < selected.len() && end > start {
+            let (key, view_id, _) = &selected[end - 1];
+            Some(encode_cursor(key, view_id)?)
+        } else {
+            None
+        };
+        let mut views = Vec::with_capacity(end - start);
+        for (_, view_id, view) in selected.drain(start..end) {
+            views.push((view_id, serde_json::from_value(view)?));
+        }
+        Ok(ViewPage { views, next_cursor })
+    }
+
+    fn compare_positions(
+        &self,
+        a_key: &[Value],
+        a_id: &str,
+        b_key: &[Value],
+        b_id: &str,
+    ) -> Ordering {
+        self.sort
+            .iter()
+            .zip(a_key.iter().zip(b_key))
+            .map(|((_, order), (a, b))| match order {
+                SortOrder::Ascending => compare_values(a, b),
+                SortOrder::Descending => compare_values(b, a),
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a_id.cmp(b_id))
+    }
+
+    fn decode_cursor(&self, cursor: &str) -> Result<(Vec<Value>, String), PersistenceError> {
+        let invalid = || PersistenceError::DeserializationError("invalid view cursor".into());
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let (key, view_id): (Vec<Value>, String) =
+            serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if key.len() != self.sort.len() {
+            return Err(invalid());
+        }
+        Ok((key, view_id))
+    }
+}
+
+impl FieldCondition {
+    fn matches(&self, field: &Value) -> bool {
+        match self {
+            FieldCondition::Equal(value) => values_equal(field, value),
+            FieldCondition::NotEqual(value) => !values_equal(field, value),
+            FieldCondition::GreaterThan(value) => {
+                order_of(field, value).is_some_and(Ordering::is_gt)
+            }
+            FieldCondition::GreaterOrEqual(value) => {
+                order_of(field, value).is_some_and(Ordering::is_ge)
+            }
+            FieldCondition::LessThan(value) => order_of(field, value).is_some_and(Ordering::is_lt),
+            FieldCondition::LessOrEqual(value) => {
+                order_of(field, value).is_some_and(Ordering::is_le)
+            }
+            FieldCondition::Contains(value) => match (field, value) {
+                (Value::String(field), Value::String(value)) => field.contains(value.as_str()),
+                (Value::Array(field), value) => field.iter().any(|item| values_equal(item, value)),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn encode_cursor(key: &[Value], view_id: &str) -> Result<String, PersistenceError> {
+    Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(&(key, view_id))?))
+}
+
+fn field_value<'a>(view: &'a Value, field: &str) -> &'a Value {
+    field
+        .split('.')
+        .try_fold(view, |value, name| match value {
+            Value::Object(fields) => fields.get(name),
+            Value::Array(items) => name.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+        .unwrap_or(&Value::Null)
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => order_of(a, b) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+/// Orders values of the same type, numbers, strings or booleans.
+fn order_of(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Orders any values for sorting, values of different types by type with `null` first.
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => order_of(a, b).unwrap_or_else(|| rank(a).cmp(&rank(b))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use crate::persist::{FieldCondition, PersistenceError, SortOrder, ViewQuery};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct OrderView {
+        status: String,
+        total: f64,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    fn views() -> Vec<(String, serde_json::Value)> {
+        vec![
+            (
+                "order-1",
+                json!({"status": "open", "total": 30, "tags": ["gift"]}),
+            ),
+            ("order-2", json!({"status": "closed", "total": 10})),
+            ("order-3", json!({"status": "open", "total": 20})),
+            ("order-4", json!({"status": "open", "total": 30.0})),
+            (
+                "order-5",
+                json!({"status": "open", "total": 5, "tags": ["gift"]}),
+            ),
+        ]
+        .into_iter()
+        .map(|(view_id, view)| (view_id.to_string(), view))
+        .collect()
+    }
+
+    fn view_ids(page: &[(String, OrderView)]) -> Vec<&str> {
+        page.iter().map(|(view_id, _)| view_id.as_str()).collect()
+    }
+
+    #[test]
+    fn filter_and_sort() {
+        let query = ViewQuery::default()
+            .with_condition("status", FieldCondition::Equal(json!("open")))
+            .with_condition("total", FieldCondition::GreaterOrEqual(json!(20)))
+            .with_sort("total", SortOrder::Descending);
+        let page = query.page::<OrderView, _>(views()).unwrap();
+        assert_eq!(vec!["order-1", "order-4", "order-3"], view_ids(&page.views));
+        assert!(page.next_cursor.is_none());
+
+        let query = ViewQuery::default()
+            .with_condition("tags", FieldCondition::Contains(json!("gift")))
+            .with_sort("total", SortOrder::Ascending);
+        let page = query.page::<OrderView, _>(views()).unwrap();
+        assert_eq!(vec!["order-5", "order-1"], view_ids(&page.views));
+
+        // a missing field is never ordered against a value
+        let query =
+            ViewQuery::default().with_condition("discount", FieldCondition::LessThan(json!(1)));
+        assert!(query
+            .page::<OrderView, _>(views())
+            .unwrap()
+            .views
+            .is_empty());
+    }
+
+    #[test]
+    fn paginate() {
+        let query = ViewQuery::default()
+            .with_sort("status", SortOrder::Descending)
+            .with_sort("total", SortOrder::Ascending)
+            .with_limit(2);
+        let mut pages = Vec::new();
+        let mut next = query.clone();
+        loop {
+            let page = next.page::<OrderView, _>(views()).unwrap();
+            pages.push(view_ids(&page.views).join(","));
+            match page.next_cursor {
+                Some(cursor) => next = query.clone().with_cursor(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(vec!["order-5,order-3", "order-1,order-4", "order-2"], pages);
+
+        let result = query
+            .with_cursor("not a cursor")
+            .page::<OrderView, _>(views());
+        assert!(matches!(
+            result,
+            Err(PersistenceError::DeserializationError(_))
+        ));
+    }
+}
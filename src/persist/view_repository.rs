@@ -1,25 +1,23 @@
 use crate::persist::PersistenceError;
-use crate::{Aggregate, View};
 use async_trait::async_trait;
 
-/// Handles the database access needed for a GenericQuery.
+/// Handles the database access needed for a GenericQuery or a MultiSourceQuery.
+///
+/// The view is updated by the events of the aggregate `A` when backing a `GenericQuery`, or by
+/// the events `E` when backing a `MultiSourceQuery<R, V, E>`.
 #[async_trait]
-pub trait ViewRepository<V, A>: Send + Sync
-where
-    V: View<A>,
-    A: Aggregate,
-{
+pub trait ViewRepository<V, A>: Send + Sync {
     /// Returns the current view instance.
     async fn load(&self, view_id: &str) -> Result<Option<V>, PersistenceError>;
 
-    /// Returns the current view instance and context, used by the `GenericQuery` to update
+    /// Returns the current view instance and context, used by the query to update
     /// views with committed events.
     async fn load_with_context(
         &self,
         view_id: &str,
     ) -> Result<Option<(V, ViewContext)>, PersistenceError>;
 
-    /// Updates the view instance and context, used by the `GenericQuery` to update
+    /// Updates the view instance and context, used by the query to update
     /// views with committed events.
     async fn update_view(&self, view: V, context: ViewContext) -> Result<(), PersistenceError>;
 }
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -20,6 +21,18 @@ pub trait Query<A: Aggregate>: Send + Sync {
     async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<A>]);
 }
 
+/// A shared query, e.g., a `GenericQuery` also used to load views.
+#[async_trait]
+impl<A, Q> Query<A> for Arc<Q>
+where
+    A: Aggregate,
+    Q: Query<A>,
+{
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<A>]) {
+        self.as_ref().dispatch(aggregate_id, events).await
+    }
+}
+
 /// A `View` represents a materialized view, generally serialized for persistence, that is updated by a query.
 /// This a read element in a CQRS system.
 ///
@@ -28,3 +41,59 @@ pub trait View<A: Aggregate>: Debug + Default + Serialize + DeserializeOwned + S
     /// this method.
     fn update(&mut self, event: &EventEnvelope<A>);
 }
+
+/// A `MultiSourceView` is a materialized view updated by the events of several aggregate types,
+/// e.g., a dashboard joining customers and their orders.
+///
+/// Events are received as an `E`, typically an enum with a variant for the `EventEnvelope` of
+/// each aggregate type, converted from each envelope with `From`.
+///
+/// ```
+/// # use actuality::doc::setup::{Customer, CustomerEvent, MyAggregate};
+/// use actuality::EventEnvelope;
+/// use actuality::query::MultiSourceView;
+/// use serde::{Deserialize, Serialize};
+///
+/// enum DashboardEvent {
+///     Customer(EventEnvelope<Customer>),
+///     Other(EventEnvelope<MyAggregate>),
+/// }
+///
+/// impl From<EventEnvelope<Customer>> for DashboardEvent {
+///     fn from(event: EventEnvelope<Customer>) -> Self {
+///         DashboardEvent::Customer(event)
+///     }
+/// }
+///
+/// impl From<EventEnvelope<MyAggregate>> for DashboardEvent {
+///     fn from(event: EventEnvelope<MyAggregate>) -> Self {
+///         DashboardEvent::Other(event)
+///     }
+/// }
+///
+/// #[derive(Debug, Default, Serialize, Deserialize)]
+/// struct Dashboard {
+///     customer_name: Option<String>,
+///     other_events: usize,
+/// }
+///
+/// impl MultiSourceView<DashboardEvent> for Dashboard {
+///     fn update(&mut self, event: &DashboardEvent) {
+///         match event {
+///             DashboardEvent::Customer(event) => {
+///                 if let CustomerEvent::NameAdded { name } = &event.payload {
+///                     self.customer_name = Some(name.clone());
+///                 }
+///             }
+///             DashboardEvent::Other(_) => self.other_events += 1,
+///         }
+///     }
+/// }
+/// ```
+pub trait MultiSourceView<E>:
+    Debug + Default + Serialize + DeserializeOwned + Send + Sync
+{
+    /// Each implemented view is responsible for updating its state based on events passed via
+    /// this method.
+    fn update(&mut self, event: &E);
+}
@@ -5,7 +5,6 @@
 pub mod memory_store;
 
 use async_trait::async_trait;
-use std::collections::HashMap;
 
 use crate::aggregate::Aggregate;
 use crate::aggregate::context::AggregateContext;
@@ -53,6 +52,6 @@ where
         &self,
         events: Vec<A::Event>,
         context: Self::AC,
-        metadata: HashMap<String, String>,
+        metadata: A::Metadata,
     ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>>;
 }
@@ -126,7 +126,7 @@ impl<A: Aggregate> EventStore<A> for MemoryStore<A> {
         &self,
         events: Vec<A::Event>,
         context: MemoryStoreAggregateContext<A>,
-        metadata: HashMap<String, String>,
+        metadata: A::Metadata,
     ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
         let aggregate_id = context.aggregate_id.as_str();
         let current_sequence = context.current_sequence;
@@ -162,7 +162,7 @@ impl<A: Aggregate> MemoryStore<A> {
         current_sequence: usize,
         system_id: &str,
         resultant_events: Vec<A::Event>,
-        base_metadata: HashMap<String, String>,
+        base_metadata: A::Metadata,
     ) -> Vec<EventEnvelope<A>> {
         let mut sequence = current_sequence;
         let mut wrapped_events: Vec<EventEnvelope<A>> = Vec::new();
@@ -202,3 +202,47 @@ where
         }
     }
 }
+
+/// Verifies that the current event schemas can read every event recorded in the schema file,
+/// panicking with the breaking changes that no upcaster converts.
+///
+/// Where the file does not exist it is created, otherwise any new or changed schemas are added
+/// to it; the file should be committed with the events it describes. Requires the `schema`
+/// feature.
+///
+/// ```no_run
+/// # use actuality::persist::{EventUpcaster, SchemaRegistry};
+/// use actuality::test::assert_schemas_compatible;
+///
+/// # fn test(current: SchemaRegistry, upcasters: Vec<Box<dyn EventUpcaster>>) {
+/// assert_schemas_compatible("schemas/customer.json", &current, &upcasters);
+/// # }
+/// ```
+#[cfg(feature = "schema")]
+pub fn assert_schemas_compatible<P: AsRef<std::path::Path>>(
+    path: P,
+    current: &crate::persist::SchemaRegistry,
+    upcasters: &[Box<dyn crate::persist::EventUpcaster>],
+) {
+    let path = path.as_ref();
+    if !path.exists() {
+        current
+            .save(path)
+            .expect("unable to record the event schemas");
+        return;
+    }
+    let mut recorded =
+        crate::persist::SchemaRegistry::load(path).expect("unable to load the event schemas");
+    let changes = recorded.breaking_changes(current, upcasters);
+    if !changes.is_empty() {
+        let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+        panic!("breaking event changes:\n{}", changes.join("\n"));
+    }
+    let previous = recorded.clone();
+    recorded.merge(current);
+    if recorded != previous {
+        recorded
+            .save(path)
+            .expect("unable to record the event schemas");
+    }
+}
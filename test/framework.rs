@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 use actuality::MemoryStore;
 use actuality::test::TestFramework;
 use actuality::Query;
-use actuality::{Aggregate, AggregateError, Cqrs, DomainEvent, EventEnvelope, EventStore};
+use actuality::{Aggregate, AggregateError, Cqrs, DomainEvent, EventEnvelope, EventMetadata, EventStore};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TestAggregate {
@@ -43,6 +43,7 @@ impl Aggregate for TestAggregate {
     type Event = TestEvent;
     type Error = TestError;
     type Services = TestService;
+    type Metadata = EventMetadata;
 
     fn aggregate_type() -> String {
         "TestAggregate".to_string()
@@ -182,9 +183,9 @@ impl Query<TestAggregate> for TestView {
 
 pub type TestEventEnvelope = EventEnvelope<TestAggregate>;
 
-fn metadata() -> HashMap<String, String> {
+fn metadata() -> EventMetadata {
     let now = "2021-03-18T12:32:45.930Z".to_string();
-    let mut metadata = HashMap::new();
+    let mut metadata = EventMetadata::new();
     metadata.insert("time".to_string(), now);
     metadata
 }